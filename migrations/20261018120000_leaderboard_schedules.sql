@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE leaderboard_schedules
+(
+    guild           INT8 PRIMARY KEY,
+    channel         INT8      NOT NULL,
+    period          INT2      NOT NULL,
+    winner_card     BOOLEAN   NOT NULL,
+    mention_winners BOOLEAN   NOT NULL,
+    next_post       TIMESTAMP NOT NULL
+);
+
+CREATE INDEX ON leaderboard_schedules (next_post);
@@ -0,0 +1,24 @@
+-- Add migration script here
+CREATE TABLE scheduled_jobs
+(
+    id              BIGSERIAL PRIMARY KEY,
+    kind            INT2      NOT NULL,
+    guild           INT8,
+    next_run        TIMESTAMP NOT NULL,
+    interval_secs   INT8      NOT NULL,
+    interval_months INT4      NOT NULL
+);
+
+CREATE UNIQUE INDEX ON scheduled_jobs (kind, COALESCE(guild, 0));
+CREATE INDEX ON scheduled_jobs (next_run);
+
+-- Leaderboard posts are now driven by the scheduler
+INSERT INTO scheduled_jobs (kind, guild, next_run, interval_secs, interval_months)
+SELECT 0,
+       guild,
+       next_post,
+       CASE period WHEN 1 THEN 0 ELSE 604800 END,
+       CASE period WHEN 1 THEN 1 ELSE 0 END
+FROM leaderboard_schedules;
+
+ALTER TABLE leaderboard_schedules DROP COLUMN next_post;
@@ -0,0 +1,7 @@
+-- Add migration script here
+ALTER TABLE guild_configs
+    ADD COLUMN audit_log_retention_days INT4;
+ALTER TABLE guild_configs
+    ADD COLUMN prune_compliance_audit_logs BOOLEAN;
+
+CREATE INDEX ON audit_logs (timestamp);
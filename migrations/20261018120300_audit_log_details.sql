@@ -0,0 +1,5 @@
+-- Add migration script here
+ALTER TABLE audit_logs
+    ALTER COLUMN target DROP NOT NULL;
+ALTER TABLE audit_logs
+    ADD COLUMN details JSONB;
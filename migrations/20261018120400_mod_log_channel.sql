@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE guild_configs
+    ADD COLUMN mod_log_channel INT8;
@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE audit_logs
+    ADD COLUMN id INT8 GENERATED ALWAYS AS IDENTITY PRIMARY KEY;
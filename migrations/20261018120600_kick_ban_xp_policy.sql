@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE guild_configs
+    ADD COLUMN kick_ban_xp_policy INT2;
@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE guild_configs
+    ADD COLUMN leave_policy INT2,
+    ADD COLUMN leave_delete_after_days INT4;
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE role_rewards DROP CONSTRAINT role_rewards_guild_requirement_key;
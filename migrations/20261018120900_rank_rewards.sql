@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE rank_rewards (
+    id INT8 NOT NULL,
+    guild INT8 NOT NULL,
+    max_rank INT8 NOT NULL,
+    UNIQUE (guild, id)
+);
@@ -0,0 +1,13 @@
+-- Add migration script here
+ALTER TABLE role_rewards ADD COLUMN duration_hours INT8;
+
+CREATE TABLE reward_grants (
+    guild INT8 NOT NULL,
+    id INT8 NOT NULL,
+    role INT8 NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    removed BOOLEAN NOT NULL DEFAULT false,
+    PRIMARY KEY (guild, id, role)
+);
+
+CREATE INDEX ON reward_grants (expires_at) WHERE NOT removed;
@@ -0,0 +1,8 @@
+-- Add migration script here
+CREATE TABLE role_update_failures (
+    guild INT8 NOT NULL,
+    id INT8 NOT NULL,
+    reason TEXT NOT NULL,
+    failed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (guild, id)
+);
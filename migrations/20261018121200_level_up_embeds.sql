@@ -0,0 +1,8 @@
+-- Add migration script here
+ALTER TABLE guild_configs
+    ADD COLUMN level_up_embed BOOLEAN,
+    ADD COLUMN level_up_embed_title TEXT,
+    ADD COLUMN level_up_embed_description TEXT,
+    ADD COLUMN level_up_embed_color INT4,
+    ADD COLUMN level_up_embed_footer TEXT,
+    ADD COLUMN level_up_rank_card BOOLEAN;
@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE level_up_messages (
+    id INT8 GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    guild INT8 NOT NULL,
+    -- Unbounded when null
+    min_level INT8,
+    max_level INT8,
+    template TEXT NOT NULL,
+    CHECK (min_level <= max_level)
+);
+
+CREATE INDEX level_up_messages_guild ON level_up_messages (guild);
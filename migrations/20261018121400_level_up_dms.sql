@@ -0,0 +1,7 @@
+-- Add migration script here
+ALTER TABLE guild_configs ADD COLUMN level_up_delivery INT2;
+
+CREATE TABLE user_settings (
+    id INT8 PRIMARY KEY,
+    level_up_dms BOOLEAN NOT NULL DEFAULT TRUE
+);
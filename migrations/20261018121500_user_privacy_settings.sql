@@ -0,0 +1,11 @@
+-- Add migration script here
+ALTER TABLE user_settings
+    ADD COLUMN xp_opt_out BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN hide_from_leaderboard BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN hide_from_rank BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE user_guild_opt_outs (
+    id INT8 NOT NULL,
+    guild INT8 NOT NULL,
+    PRIMARY KEY (id, guild)
+);
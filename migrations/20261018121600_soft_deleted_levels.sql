@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE soft_deleted_levels (
+    guild INT8 NOT NULL,
+    id INT8 NOT NULL,
+    xp INT8 NOT NULL,
+    deleted_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (guild, id)
+);
+
+CREATE INDEX ON soft_deleted_levels (id);
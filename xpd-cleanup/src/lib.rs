@@ -0,0 +1,354 @@
+#[macro_use]
+extern crate tracing;
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    process::{ExitCode, Termination},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use sqlx::{Connection, PgConnection, Postgres, Transaction};
+use twilight_model::id::{Id, marker::GuildMarker};
+use xpd_common::{DISCORD_EPOCH_SECS, UserInGuild};
+
+/// Advisory lock key held while cleanup runs, so that the standalone binary and
+/// any number of gateways never clean up at the same time.
+const CLEANUP_LOCK_KEY: i64 = i64::from_be_bytes(*b"xpdclean");
+const DEFAULT_RETENTION: Duration = Duration::from_hours(30 * 24);
+
+#[derive(Clone, Copy, Debug)]
+pub struct CleanupOptions {
+    /// Do every deletion in a transaction, and then roll it back, so that the report shows
+    /// exactly what would have been deleted.
+    pub dry_run: bool,
+    /// How long to keep data for a guild after the bot leaves it
+    pub guild_retention: Duration,
+    /// Whether to clean up members who left guilds. Only guilds which chose to delete the XP
+    /// of members who leave are affected, after the number of days they configured.
+    pub users: bool,
+    /// Never clean up members sooner than this after they leave, whatever their guild chose
+    pub min_user_retention: Duration,
+}
+
+impl Default for CleanupOptions {
+    fn default() -> Self {
+        Self {
+            dry_run: false,
+            guild_retention: DEFAULT_RETENTION,
+            users: true,
+            min_user_retention: Duration::ZERO,
+        }
+    }
+}
+
+/// Everything that was (or, in a dry run, would have been) deleted.
+#[derive(Clone, Debug, Default, serde::Serialize)]
+pub struct CleanupReport {
+    pub dry_run: bool,
+    /// Another process was already cleaning up, so nothing was done
+    pub skipped: bool,
+    pub guilds: Vec<Id<GuildMarker>>,
+    pub users: Vec<UserInGuild>,
+    /// Deleted row counts, keyed by table name
+    pub rows: BTreeMap<&'static str, u64>,
+    /// The sum of the XP of every deleted `levels` row
+    pub total_xp: i64,
+}
+
+impl CleanupReport {
+    fn add_rows(&mut self, table: &'static str, count: u64) {
+        *self.rows.entry(table).or_default() += count;
+    }
+
+    fn merge(&mut self, other: Self) {
+        for (table, count) in other.rows {
+            self.add_rows(table, count);
+        }
+        self.total_xp += other.total_xp;
+    }
+}
+
+/// Run every enabled cleanup task. If another process is already cleaning up, this
+/// does nothing, and the report is marked as skipped.
+///
+/// This holds a session-level lock on `conn` while it runs, so `conn` must not be a pooled
+/// connection: if this future were dropped partway through, the lock would stay held.
+pub async fn cleanup(
+    conn: &mut PgConnection,
+    options: CleanupOptions,
+) -> Result<CleanupReport, Error> {
+    let mut report = CleanupReport {
+        dry_run: options.dry_run,
+        ..Default::default()
+    };
+    if !xpd_database::try_advisory_lock(&mut *conn, CLEANUP_LOCK_KEY).await? {
+        info!("Another process is already cleaning up, skipping");
+        report.skipped = true;
+        return Ok(report);
+    }
+    let result = cleanup_locked(conn, options, &mut report).await;
+    xpd_database::advisory_unlock(&mut *conn, CLEANUP_LOCK_KEY).await?;
+    result.map(|()| report)
+}
+
+async fn cleanup_locked(
+    conn: &mut PgConnection,
+    options: CleanupOptions,
+    report: &mut CleanupReport,
+) -> Result<(), Error> {
+    info!("Cleaning up guilds we are no longer in");
+    cleanup_guilds(conn, options, report).await?;
+    if options.users {
+        info!("Cleaning up users who have left");
+        cleanup_users(conn, options, report).await?;
+    }
+    info!("Cleaning up cooldowns");
+    cleanup_cooldowns(conn, options, report).await?;
+    info!("Pruning expired audit logs");
+    cleanup_audit_logs(conn, options, report).await?;
+    Ok(())
+}
+
+pub async fn cleanup_guilds(
+    conn: &mut PgConnection,
+    options: CleanupOptions,
+    report: &mut CleanupReport,
+) -> Result<(), Error> {
+    let cleanups =
+        xpd_database::get_active_guild_cleanups(&mut *conn, options.guild_retention).await?;
+    info!(?cleanups, count = cleanups.len(), "Got guild cleanups");
+    for guild in cleanups {
+        debug!(%guild, "Cleaning guild");
+        let mut txn = conn.begin().await?;
+        let mut guild_report = CleanupReport::default();
+        if let Err(source) = cleanup_guild(&mut txn, guild, &mut guild_report).await {
+            error!(%guild, ?source, "Unable to invalidate rewards for guild");
+            txn.rollback().await?;
+            continue;
+        }
+        if let Err(source) = finish(txn, options).await {
+            error!(%guild, ?source, "Unable to commit changes for guild");
+            continue;
+        }
+        info!(%guild, rows = ?guild_report.rows, xp = guild_report.total_xp, dry_run = options.dry_run, "Cleaned guild");
+        report.guilds.push(guild);
+        report.merge(guild_report);
+    }
+    Ok(())
+}
+
+pub async fn cleanup_users(
+    conn: &mut PgConnection,
+    options: CleanupOptions,
+    report: &mut CleanupReport,
+) -> Result<(), Error> {
+    let cleanups =
+        xpd_database::get_active_user_guild_cleanups(&mut *conn, options.min_user_retention)
+            .await?;
+    info!(?cleanups, count = cleanups.len(), "Got user cleanups");
+    for cleanup in cleanups {
+        debug!(guild = %cleanup.guild, user = %cleanup.user, "Cleaning user-guild combo");
+        let mut txn = conn.begin().await?;
+        let mut user_report = CleanupReport::default();
+        if let Err(source) = cleanup_user(&mut txn, cleanup, &mut user_report).await {
+            error!(guild = %cleanup.guild, user = %cleanup.user, ?source, "Unable to invalidate rewards for user");
+            txn.rollback().await?;
+            continue;
+        }
+        if let Err(source) = finish(txn, options).await {
+            error!(guild = %cleanup.guild, user = %cleanup.user, ?source, "Unable to commit changes for user");
+            continue;
+        }
+        info!(guild = %cleanup.guild, user = %cleanup.user, rows = ?user_report.rows, xp = user_report.total_xp, dry_run = options.dry_run, "Cleaned user");
+        report.users.push(cleanup);
+        report.merge(user_report);
+    }
+    Ok(())
+}
+
+pub async fn cleanup_cooldowns(
+    conn: &mut PgConnection,
+    options: CleanupOptions,
+    report: &mut CleanupReport,
+) -> Result<(), Error> {
+    let discord_epoch = Duration::from_secs(DISCORD_EPOCH_SECS.try_into().unwrap());
+    let max_message_cooldown =
+        Duration::from_secs(xpd_common::MAX_MESSAGE_COOLDOWN.try_into().unwrap());
+
+    let now_discord = UNIX_EPOCH
+        .elapsed()?
+        .checked_add(discord_epoch)
+        .and_then(|v| v.checked_sub(max_message_cooldown))
+        .ok_or(Error::GenericTime)?
+        .as_secs()
+        .try_into()
+        .unwrap_or(0); // nothing can start before 0
+    warn!(now_discord, "Deleting cooldowns starting before");
+    let mut txn = conn.begin().await?;
+    let deleted = xpd_database::delete_cooldowns_starting_before(txn.as_mut(), now_discord).await?;
+    finish(txn, options).await?;
+    report.add_rows("cooldowns", deleted);
+    Ok(())
+}
+
+/// Retention periods are configured per-guild, see [`xpd_database::prune_audit_log_events`].
+pub async fn cleanup_audit_logs(
+    conn: &mut PgConnection,
+    options: CleanupOptions,
+    report: &mut CleanupReport,
+) -> Result<(), Error> {
+    let mut txn = conn.begin().await?;
+    let deleted = xpd_database::prune_audit_log_events(txn.as_mut()).await?;
+    finish(txn, options).await?;
+    info!(deleted, "Pruned audit logs");
+    report.add_rows("audit_logs", deleted);
+    Ok(())
+}
+
+/// Commit the transaction, unless this is a dry run.
+async fn finish(txn: Transaction<'_, Postgres>, options: CleanupOptions) -> Result<(), Error> {
+    if options.dry_run {
+        txn.rollback().await?;
+    } else {
+        txn.commit().await?;
+    }
+    Ok(())
+}
+
+async fn cleanup_user(
+    db: &mut Transaction<'_, Postgres>,
+    target: UserInGuild,
+    report: &mut CleanupReport,
+) -> Result<(), Error> {
+    debug!(?target, "Deleting user levels in guild");
+    if let Some(xp) = xpd_database::user_xp(db.as_mut(), target.guild, target.user).await? {
+        xpd_database::delete_levels_user_guild(db.as_mut(), target.user, target.guild).await?;
+        report.add_rows("levels", 1);
+        report.total_xp += xp;
+    }
+    debug!(?target, "Deleting user soft-deleted levels in guild");
+    let count =
+        xpd_database::delete_soft_deleted_levels_user_guild(db.as_mut(), target.user, target.guild)
+            .await?;
+    report.add_rows("soft_deleted_levels", count);
+    debug!(?target, "Deleting user audit log events in guild");
+    let count =
+        xpd_database::delete_audit_log_events_user_guild(db.as_mut(), target.user, target.guild)
+            .await?;
+    report.add_rows("audit_logs", count);
+    debug!(?target, "Deleting user reward grants in guild");
+    let count =
+        xpd_database::delete_reward_grants_user_guild(db.as_mut(), target.guild, target.user)
+            .await?;
+    report.add_rows("reward_grants", count);
+    debug!(?target, "Deleting user role update failures in guild");
+    let count =
+        xpd_database::clear_role_update_failure(db.as_mut(), target.guild, target.user).await?;
+    report.add_rows("role_update_failures", count);
+    debug!(?target, "Acknowledging user has been cleaned up");
+    let count =
+        xpd_database::delete_user_guild_cleanup(db.as_mut(), target.guild, target.user).await?;
+    report.add_rows("user_cleanups", count);
+    Ok(())
+}
+
+async fn cleanup_guild(
+    db: &mut Transaction<'_, Postgres>,
+    guild: Id<GuildMarker>,
+    report: &mut CleanupReport,
+) -> Result<(), Error> {
+    debug!(%guild, "Deleting guild configs");
+    let count = xpd_database::delete_guild_config(db.as_mut(), guild).await?;
+    report.add_rows("guild_configs", count);
+    debug!(%guild, "Deleting guild card customizations");
+    let count = xpd_database::delete_card_customizations(db.as_mut(), guild.cast()).await?;
+    report.add_rows("custom_card", count);
+    debug!(%guild, "Deleting guild rewards");
+    let rewards = xpd_database::guild_rewards(db.as_mut(), guild).await?;
+    debug!(%guild, count = rewards.len(), "Deleting guild rewards");
+    for reward in rewards {
+        trace!(%guild, id = %reward.id, requirement = reward.requirement, "Deleting guild reward");
+        let count =
+            xpd_database::delete_reward_role(db.as_mut(), guild, None, Some(reward.id)).await?;
+        report.add_rows("role_rewards", count);
+    }
+    let rank_rewards = xpd_database::guild_rank_rewards(db.as_mut(), guild).await?;
+    debug!(%guild, count = rank_rewards.len(), "Deleting guild rank rewards");
+    for reward in rank_rewards {
+        trace!(%guild, id = %reward.id, max_rank = reward.max_rank, "Deleting guild rank reward");
+        let count = xpd_database::delete_rank_reward(db.as_mut(), guild, reward.id).await?;
+        report.add_rows("rank_rewards", count);
+    }
+    debug!(%guild, "Deleting guild reward grants");
+    let count = xpd_database::delete_reward_grants_guild(db.as_mut(), guild).await?;
+    report.add_rows("reward_grants", count);
+    debug!(%guild, "Deleting guild role update failures");
+    let count = xpd_database::delete_role_update_failures_guild(db.as_mut(), guild).await?;
+    report.add_rows("role_update_failures", count);
+    debug!(%guild, "Deleting guild level-up messages");
+    let count = xpd_database::delete_level_up_messages_guild(db.as_mut(), guild).await?;
+    report.add_rows("level_up_messages", count);
+    debug!(%guild, "Deleting guild leaderboard schedule");
+    let deleted = xpd_database::delete_leaderboard_schedule(db.as_mut(), guild).await?;
+    report.add_rows("leaderboard_schedules", deleted.into());
+    debug!(%guild, "Deleting guild scheduled jobs");
+    let count = xpd_database::delete_guild_scheduled_jobs(db.as_mut(), guild).await?;
+    report.add_rows("scheduled_jobs", count);
+    debug!(%guild, "Deleting guild levels");
+    report.total_xp += xpd_database::total_guild_xp(db.as_mut(), guild).await?;
+    let count = xpd_database::delete_levels_guild(db.as_mut(), guild).await?;
+    report.add_rows("levels", count);
+    debug!(%guild, "Deleting guild soft-deleted levels");
+    let count = xpd_database::delete_soft_deleted_levels_guild(db.as_mut(), guild).await?;
+    report.add_rows("soft_deleted_levels", count);
+    debug!(%guild, "Acknowledging guild has been cleaned up");
+    let count = xpd_database::delete_guild_cleanup(db.as_mut(), guild).await?;
+    report.add_rows("guild_cleanups", count);
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Sqlx(sqlx::Error),
+    DbReq(xpd_database::Error),
+    SystemTime(std::time::SystemTimeError),
+    GenericTime,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Sqlx(e) => write!(f, "{e}"),
+            Self::DbReq(e) => write!(f, "{e}"),
+            Self::SystemTime(e) => write!(f, "{e}"),
+            Self::GenericTime => f.write_str("Could not compute cooldown cutoff time"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<sqlx::Error> for Error {
+    fn from(value: sqlx::Error) -> Self {
+        Self::Sqlx(value)
+    }
+}
+
+impl From<xpd_database::Error> for Error {
+    fn from(value: xpd_database::Error) -> Self {
+        Self::DbReq(value)
+    }
+}
+
+impl From<std::time::SystemTimeError> for Error {
+    fn from(value: std::time::SystemTimeError) -> Self {
+        Self::SystemTime(value)
+    }
+}
+
+impl Termination for Error {
+    fn report(self) -> ExitCode {
+        ExitCode::FAILURE
+    }
+}
@@ -10,11 +10,12 @@ use strum_macros::FromRepr;
 use twilight_cache_inmemory::ResourceType;
 use twilight_gateway::EventTypeFlags;
 use twilight_model::{
+    channel::message::{AllowedMentions, Embed},
     gateway::Intents,
     guild::{Member, PartialMember},
     id::{
         Id,
-        marker::{ChannelMarker, GuildMarker, RoleMarker, UserMarker},
+        marker::{ChannelMarker, GuildMarker, MessageMarker, RoleMarker, UserMarker},
     },
     user::User,
     util::ImageHash,
@@ -100,10 +101,14 @@ pub const DEFAULT_MAX_XP_PER_MESSAGE: i16 = 25;
 pub const DEFAULT_MIN_XP_PER_MESSAGE: i16 = 15;
 pub const DEFAULT_MESSAGE_COOLDOWN: i16 = 60;
 pub const MAX_MESSAGE_COOLDOWN: i16 = 28800;
+pub const DEFAULT_AUDIT_LOG_RETENTION_DAYS: i32 = 365;
+pub const MAX_AUDIT_LOG_RETENTION_DAYS: i32 = 3650;
+pub const DEFAULT_LEAVE_DELETE_AFTER_DAYS: i32 = 30;
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, serde::Serialize)]
 pub struct GuildConfig {
     pub one_at_a_time: Option<bool>,
+    #[serde(serialize_with = "serialize_interpolation")]
     pub level_up_message: Option<Interpolation>,
     pub level_up_channel: Option<Id<ChannelMarker>>,
     pub ping_on_level_up: Option<bool>,
@@ -111,6 +116,202 @@ pub struct GuildConfig {
     pub max_xp_per_message: Option<i16>,
     pub cooldown: Option<i16>,
     pub guild_card_default_show_off: bool,
+    pub audit_log_retention_days: Option<i32>,
+    pub prune_compliance_audit_logs: Option<bool>,
+    pub mod_log_channel: Option<Id<ChannelMarker>>,
+    pub kick_ban_xp_policy: Option<KickBanXpPolicy>,
+    pub leave_policy: Option<LeavePolicy>,
+    pub leave_delete_after_days: Option<i32>,
+    /// Announce level-ups with an embed instead of plain text
+    pub level_up_embed: Option<bool>,
+    #[serde(serialize_with = "serialize_interpolation")]
+    pub level_up_embed_title: Option<Interpolation>,
+    /// Falls back to the level-up message when unset
+    #[serde(serialize_with = "serialize_interpolation")]
+    pub level_up_embed_description: Option<Interpolation>,
+    /// An RGB color, like `0xFF8800`
+    pub level_up_embed_color: Option<u32>,
+    #[serde(serialize_with = "serialize_interpolation")]
+    pub level_up_embed_footer: Option<Interpolation>,
+    /// Attach the member's rank card to level-up announcements
+    pub level_up_rank_card: Option<bool>,
+    pub level_up_delivery: Option<LevelUpDelivery>,
+}
+
+impl GuildConfig {
+    /// How many days audit log events are kept for in this guild
+    #[must_use]
+    pub fn audit_log_retention_days(&self) -> i32 {
+        self.audit_log_retention_days
+            .unwrap_or(DEFAULT_AUDIT_LOG_RETENTION_DAYS)
+    }
+
+    /// What happens to a member's XP in this guild when they're kicked or banned
+    #[must_use]
+    pub fn kick_ban_xp_policy(&self) -> KickBanXpPolicy {
+        self.kick_ban_xp_policy.unwrap_or_default()
+    }
+
+    /// What happens to a member's XP in this guild while they aren't in it
+    #[must_use]
+    pub fn leave_policy(&self) -> LeavePolicy {
+        self.leave_policy.unwrap_or_default()
+    }
+
+    /// Where level-up announcements are sent in this guild
+    #[must_use]
+    pub fn level_up_delivery(&self) -> LevelUpDelivery {
+        self.level_up_delivery.unwrap_or_default()
+    }
+
+    /// How many days after leaving a member's XP is deleted, with [`LeavePolicy::DeleteAfter`]
+    #[must_use]
+    pub fn leave_delete_after_days(&self) -> i32 {
+        self.leave_delete_after_days
+            .unwrap_or(DEFAULT_LEAVE_DELETE_AFTER_DAYS)
+    }
+}
+
+/// Where level-up announcements are sent
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, FromRepr, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(i16)]
+pub enum LevelUpDelivery {
+    /// In the level-up channel, or next to the message that leveled the member up
+    #[default]
+    Channel = 0,
+    /// In a DM to the member, unless they've turned level-up DMs off
+    Dm = 1,
+}
+
+impl LevelUpDelivery {
+    #[must_use]
+    pub const fn from_i16(t: i16) -> Option<Self> {
+        Self::from_repr(t)
+    }
+
+    #[must_use]
+    pub const fn to_i16(self) -> i16 {
+        self as i16
+    }
+}
+
+impl Display for LevelUpDelivery {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Channel => "in chat",
+            Self::Dm => "in DMs",
+        })
+    }
+}
+
+/// A user's own settings, which apply in every guild
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+// each one is an independent switch, not a state
+#[allow(clippy::struct_excessive_bools)]
+pub struct UserSettings {
+    /// Whether guilds which announce level-ups in DMs may DM this user
+    pub level_up_dms: bool,
+    /// Stop earning XP in every guild
+    pub xp_opt_out: bool,
+    /// Leave this user out of leaderboards
+    pub hide_from_leaderboard: bool,
+    /// Refuse `/rank` lookups of this user by anyone else
+    pub hide_from_rank: bool,
+}
+
+impl Default for UserSettings {
+    fn default() -> Self {
+        Self {
+            level_up_dms: true,
+            xp_opt_out: false,
+            hide_from_leaderboard: false,
+            hide_from_rank: false,
+        }
+    }
+}
+
+impl Display for UserSettings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Level-up DMs: {}", self.level_up_dms)?;
+        writeln!(f, "Earning XP in every server: {}", !self.xp_opt_out)?;
+        writeln!(f, "Shown on leaderboards: {}", !self.hide_from_leaderboard)?;
+        write!(f, "Rank visible to others: {}", !self.hide_from_rank)
+    }
+}
+
+/// What happens to a member's XP while they aren't in the guild
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, FromRepr, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(i16)]
+pub enum LeavePolicy {
+    /// Keep their XP forever
+    #[default]
+    Keep = 0,
+    /// Delete their XP once they've been gone for
+    /// [`leave_delete_after_days`](GuildConfig::leave_delete_after_days)
+    DeleteAfter = 1,
+    /// Leave them out of leaderboards and rankings until they rejoin
+    Hide = 2,
+}
+
+impl LeavePolicy {
+    #[must_use]
+    pub const fn from_i16(t: i16) -> Option<Self> {
+        Self::from_repr(t)
+    }
+
+    #[must_use]
+    pub const fn to_i16(self) -> i16 {
+        self as i16
+    }
+}
+
+/// What happens to a member's XP when they're kicked or banned
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, FromRepr, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(i16)]
+pub enum KickBanXpPolicy {
+    /// Delete their XP for good
+    #[default]
+    Reset = 0,
+    /// Leave their XP alone
+    Keep = 1,
+    /// Set their XP aside, and give it back if they're unbanned or rejoin
+    SoftDelete = 2,
+}
+
+impl KickBanXpPolicy {
+    #[must_use]
+    pub const fn from_i16(t: i16) -> Option<Self> {
+        Self::from_repr(t)
+    }
+
+    #[must_use]
+    pub const fn to_i16(self) -> i16 {
+        self as i16
+    }
+}
+
+impl Display for KickBanXpPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Reset => "reset",
+            Self::Keep => "keep",
+            Self::SoftDelete => "soft-delete, restored on unban or rejoin",
+        })
+    }
+}
+
+#[allow(clippy::ref_option)]
+fn serialize_interpolation<S: serde::Serializer>(
+    interpolation: &Option<Interpolation>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serde::Serialize::serialize(
+        &interpolation.as_ref().map(Interpolation::input_value),
+        serializer,
+    )
 }
 
 impl Display for GuildConfig {
@@ -127,10 +328,7 @@ impl Display for GuildConfig {
         writeln!(
             f,
             "Level-up message: {}",
-            self.level_up_message
-                .as_ref()
-                .map(Interpolation::input_value)
-                .map_or(Cow::Borrowed("unset"), |v| Cow::Owned(format!("`{v}`")))
+            template_or_unset(self.level_up_message.as_ref())
         )?;
         writeln!(
             f,
@@ -156,24 +354,236 @@ impl Display for GuildConfig {
             "Cooldown (seconds): {}",
             self.cooldown.unwrap_or(DEFAULT_MESSAGE_COOLDOWN)
         )?;
-        write!(
+        writeln!(
             f,
             "Show off guild card by default: {}",
             self.guild_card_default_show_off
         )?;
-        Ok(())
+        writeln!(
+            f,
+            "Audit log retention (days): {}",
+            self.audit_log_retention_days()
+        )?;
+        writeln!(
+            f,
+            "Prune compliance audit logs: {}",
+            self.prune_compliance_audit_logs.unwrap_or(false)
+        )?;
+        writeln!(
+            f,
+            "Mod-log channel: {}",
+            self.mod_log_channel.map_or(Cow::Borrowed("unset"), |v| {
+                Cow::Owned(format!("`<#{v}>`"))
+            })
+        )?;
+        writeln!(f, "XP on kick or ban: {}", self.kick_ban_xp_policy())?;
+        match self.leave_policy() {
+            LeavePolicy::Keep => writeln!(f, "XP when members leave: keep"),
+            LeavePolicy::DeleteAfter => writeln!(
+                f,
+                "XP when members leave: delete after {} days",
+                self.leave_delete_after_days()
+            ),
+            LeavePolicy::Hide => writeln!(f, "XP when members leave: hide until they rejoin"),
+        }?;
+        writeln!(
+            f,
+            "Level-up embed: {}",
+            self.level_up_embed.unwrap_or(false)
+        )?;
+        for (part, template) in [
+            ("title", &self.level_up_embed_title),
+            ("description", &self.level_up_embed_description),
+            ("footer", &self.level_up_embed_footer),
+        ] {
+            writeln!(
+                f,
+                "Level-up embed {part}: {}",
+                template_or_unset(template.as_ref())
+            )?;
+        }
+        writeln!(
+            f,
+            "Level-up embed color: {}",
+            self.level_up_embed_color
+                .map_or(Cow::Borrowed("unset"), |v| Cow::Owned(format!("#{v:06X}")))
+        )?;
+        writeln!(
+            f,
+            "Attach rank card to level-ups: {}",
+            self.level_up_rank_card.unwrap_or(false)
+        )?;
+        write!(f, "Level-ups are sent: {}", self.level_up_delivery())
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+fn template_or_unset(template: Option<&Interpolation>) -> Cow<'static, str> {
+    template
+        .map(Interpolation::input_value)
+        .map_or(Cow::Borrowed("unset"), |v| Cow::Owned(format!("`{v}`")))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct AuditLogEvent {
     pub guild: Id<GuildMarker>,
-    pub target: Id<UserMarker>,
+    /// The user whose XP was changed. Events which affect the whole guild have no target.
+    pub target: Option<Id<UserMarker>>,
     pub moderator: Id<UserMarker>,
     pub timestamp: i64,
     pub previous: i64,
     pub delta: i64,
     pub kind: AuditLogEventKind,
+    /// What the event changed, for events which don't just change XP
+    pub details: Option<AuditLogChange>,
+}
+
+/// An [`AuditLogEvent`] as stored, along with the ID it can be referred to by.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct AuditLogEntry {
+    pub id: i64,
+    #[serde(flatten)]
+    pub event: AuditLogEvent,
+}
+
+/// The state of whatever an audit log event changed, before and after it happened.
+/// Either side is null when something was created or deleted.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct AuditLogChange {
+    pub before: serde_json::Value,
+    pub after: serde_json::Value,
+}
+
+impl AuditLogChange {
+    /// Top-level keys which differ between the before and after of this change
+    #[must_use]
+    pub fn changed_fields(&self) -> Vec<&str> {
+        let before = self.before.as_object();
+        let after = self.after.as_object();
+        let mut fields: Vec<&str> = before
+            .into_iter()
+            .chain(after)
+            .flat_map(serde_json::Map::keys)
+            .filter(|key| before.and_then(|v| v.get(*key)) != after.and_then(|v| v.get(*key)))
+            .map(String::as_str)
+            .collect();
+        fields.sort_unstable();
+        fields.dedup();
+        fields
+    }
+}
+
+/// A one-line, human-readable summary of the event, ending in a discord relative timestamp
+impl Display for AuditLogEvent {
+    #[allow(clippy::too_many_lines)]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let moderator = self.moderator;
+        let target = self.target.map_or(Cow::Borrowed("someone"), |target| {
+            Cow::Owned(format!("<@{target}>"))
+        });
+        let amount = self.delta.abs();
+        let previous = self.previous;
+        let details = self.details.as_ref();
+        match self.kind {
+            AuditLogEventKind::AddOrSub if self.delta.is_negative() => {
+                write!(f, "<@{moderator}> removed {amount} XP from {target}")
+            }
+            AuditLogEventKind::AddOrSub => {
+                write!(f, "<@{moderator}> added {amount} XP to {target}")
+            }
+            AuditLogEventKind::Reset => {
+                write!(f, "<@{moderator}> reset {target}'s XP (was {previous})")
+            }
+            AuditLogEventKind::Set => write!(
+                f,
+                "<@{moderator}> set {target}'s XP to {} (was {previous})",
+                previous + self.delta
+            ),
+            AuditLogEventKind::KickReset => {
+                write!(f, "<@{moderator}> kicked {target}, resetting {amount} XP")
+            }
+            AuditLogEventKind::BanReset => {
+                write!(f, "<@{moderator}> banned {target}, resetting {amount} XP")
+            }
+            AuditLogEventKind::GdprDelete => {
+                write!(f, "{target} deleted their data, removing {amount} XP")
+            }
+            AuditLogEventKind::ConfigUpdate => {
+                let fields = details.map(AuditLogChange::changed_fields);
+                match fields.as_deref() {
+                    Some([]) | None => write!(f, "<@{moderator}> updated the server config"),
+                    Some(fields) => write!(
+                        f,
+                        "<@{moderator}> updated the server config: `{}`",
+                        fields.join("`, `")
+                    ),
+                }
+            }
+            AuditLogEventKind::RewardAdd => {
+                let after = details.map(|v| &v.after);
+                let role = after.and_then(|v| v["id"].as_str());
+                let level = after.and_then(|v| v["requirement"].as_i64());
+                if let (Some(role), Some(level)) = (role, level) {
+                    write!(
+                        f,
+                        "<@{moderator}> added role reward <@&{role}> at level {level}"
+                    )
+                } else {
+                    write!(f, "<@{moderator}> added a role reward")
+                }
+            }
+            AuditLogEventKind::RewardRemove => {
+                let count = details
+                    .and_then(|v| v.before.as_array())
+                    .map_or(0, Vec::len);
+                let pluralizer = if count == 1 { "" } else { "s" };
+                write!(f, "<@{moderator}> removed {count} role reward{pluralizer}")
+            }
+            AuditLogEventKind::Import => {
+                let users = details.and_then(|v| v.after["users"].as_u64()).unwrap_or(0);
+                write!(f, "<@{moderator}> imported XP for {users} users")
+            }
+            AuditLogEventKind::GuildReset => {
+                write!(f, "<@{moderator}> reset all XP in the server ({amount} XP)")
+            }
+            AuditLogEventKind::GuildCardUpdate => {
+                write!(f, "<@{moderator}> changed the server rank card")
+            }
+            AuditLogEventKind::Revert => {
+                let reverted = details.and_then(|v| v.after["reverted"].as_i64());
+                let verb = if self.delta.is_negative() {
+                    "removing"
+                } else {
+                    "restoring"
+                };
+                if let Some(reverted) = reverted {
+                    write!(
+                        f,
+                        "<@{moderator}> reverted event #{reverted}, {verb} {amount} XP for {target}"
+                    )
+                } else {
+                    write!(
+                        f,
+                        "<@{moderator}> reverted an event, {verb} {amount} XP for {target}"
+                    )
+                }
+            }
+            AuditLogEventKind::UnbanRestore => {
+                write!(f, "<@{moderator}> unbanned {target}, restoring {amount} XP")
+            }
+            AuditLogEventKind::RejoinRestore => {
+                write!(f, "{target} rejoined, restoring {amount} XP")
+            }
+            AuditLogEventKind::KickSoftDelete => write!(
+                f,
+                "<@{moderator}> kicked {target}, holding {amount} XP until they rejoin"
+            ),
+            AuditLogEventKind::BanSoftDelete => write!(
+                f,
+                "<@{moderator}> banned {target}, holding {amount} XP until they're unbanned"
+            ),
+        }?;
+        write!(f, " <t:{}:R>", self.timestamp + DISCORD_EPOCH_SECS)
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, FromRepr)]
@@ -185,9 +595,31 @@ pub enum AuditLogEventKind {
     Set = 2,
     KickReset = 3,
     BanReset = 4,
+    /// A user deleted their data with `/gdpr delete`
+    GdprDelete = 5,
+    ConfigUpdate = 6,
+    RewardAdd = 7,
+    RewardRemove = 8,
+    Import = 9,
+    GuildReset = 10,
+    GuildCardUpdate = 11,
+    /// A moderator undid an earlier XP change with `/audit revert`
+    Revert = 12,
+    /// A member was unbanned, and got back the XP they lost when they were banned
+    UnbanRestore = 13,
+    /// A kicked member came back, and got back the XP they lost when they were kicked
+    RejoinRestore = 14,
+    /// A member was kicked, and their XP was set aside until they rejoin
+    KickSoftDelete = 15,
+    /// A member was banned, and their XP was set aside until they're unbanned
+    BanSoftDelete = 16,
 }
 
 impl AuditLogEventKind {
+    /// Events which are kept as proof of compliance. Unless a guild opts in,
+    /// these are never pruned.
+    pub const COMPLIANCE: [Self; 1] = [Self::GdprDelete];
+
     #[must_use]
     pub fn from_i64(t: i64) -> Option<Self> {
         let Ok(disc) = t.try_into() else {
@@ -200,6 +632,189 @@ impl AuditLogEventKind {
     pub const fn to_i64(self) -> i64 {
         self as i64
     }
+
+    /// Whether `/audit revert` can undo this kind of event. Only changes to a single
+    /// user's XP can be undone, and reverts themselves can't be.
+    #[must_use]
+    pub const fn is_revertable(self) -> bool {
+        matches!(
+            self,
+            Self::AddOrSub
+                | Self::Reset
+                | Self::Set
+                | Self::KickReset
+                | Self::BanReset
+                | Self::UnbanRestore
+                | Self::RejoinRestore
+                | Self::KickSoftDelete
+                | Self::BanSoftDelete
+        )
+    }
+
+    #[must_use]
+    pub const fn category(self) -> AuditLogCategory {
+        match self {
+            Self::AddOrSub
+            | Self::Reset
+            | Self::Set
+            | Self::KickReset
+            | Self::BanReset
+            | Self::Revert
+            | Self::UnbanRestore
+            | Self::RejoinRestore
+            | Self::KickSoftDelete
+            | Self::BanSoftDelete => AuditLogCategory::Xp,
+            Self::GdprDelete => AuditLogCategory::Compliance,
+            Self::ConfigUpdate => AuditLogCategory::Config,
+            Self::RewardAdd | Self::RewardRemove => AuditLogCategory::Rewards,
+            Self::Import | Self::GuildReset => AuditLogCategory::Data,
+            Self::GuildCardUpdate => AuditLogCategory::Card,
+        }
+    }
+}
+
+/// Broad groups of [`AuditLogEventKind`]s, for filtering.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, FromRepr, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+#[repr(i16)]
+pub enum AuditLogCategory {
+    Xp = 0,
+    Config = 1,
+    Rewards = 2,
+    Data = 3,
+    Card = 4,
+    Compliance = 5,
+}
+
+impl AuditLogCategory {
+    #[must_use]
+    pub const fn from_i16(t: i16) -> Option<Self> {
+        Self::from_repr(t)
+    }
+
+    #[must_use]
+    pub const fn to_i16(self) -> i16 {
+        self as i16
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, FromRepr, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(i16)]
+pub enum LeaderboardSchedulePeriod {
+    Weekly = 0,
+    Monthly = 1,
+}
+
+impl LeaderboardSchedulePeriod {
+    #[must_use]
+    pub const fn from_i16(t: i16) -> Option<Self> {
+        Self::from_repr(t)
+    }
+
+    #[must_use]
+    pub const fn to_i16(self) -> i16 {
+        self as i16
+    }
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Weekly => "weekly",
+            Self::Monthly => "monthly",
+        }
+    }
+
+    #[must_use]
+    pub const fn job_interval(self) -> JobInterval {
+        match self {
+            Self::Weekly => JobInterval::Seconds(7 * 24 * 60 * 60),
+            Self::Monthly => JobInterval::Months(1),
+        }
+    }
+}
+
+/// The different things the gateway scheduler knows how to run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, FromRepr)]
+#[repr(i16)]
+pub enum ScheduledJobKind {
+    LeaderboardPost = 0,
+    /// Runs the `xpd-cleanup` tasks, for all guilds at once.
+    Cleanup = 1,
+    /// Takes away time-limited role rewards which have run out, for all guilds at once.
+    RewardExpiry = 2,
+}
+
+impl ScheduledJobKind {
+    #[must_use]
+    pub const fn from_i16(t: i16) -> Option<Self> {
+        Self::from_repr(t)
+    }
+
+    #[must_use]
+    pub const fn to_i16(self) -> i16 {
+        self as i16
+    }
+}
+
+/// How long to wait between runs of a scheduled job.
+/// Months are kept separate because they aren't a fixed number of seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum JobInterval {
+    Seconds(i64),
+    Months(i32),
+}
+
+impl JobInterval {
+    #[must_use]
+    pub const fn seconds(self) -> i64 {
+        match self {
+            Self::Seconds(secs) => secs,
+            Self::Months(_) => 0,
+        }
+    }
+
+    #[must_use]
+    pub const fn months(self) -> i32 {
+        match self {
+            Self::Seconds(_) => 0,
+            Self::Months(months) => months,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScheduledJob {
+    pub id: i64,
+    pub kind: ScheduledJobKind,
+    /// Jobs that aren't tied to one guild have no guild.
+    pub guild: Option<Id<GuildMarker>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct LeaderboardSchedule {
+    pub guild: Id<GuildMarker>,
+    pub channel: Id<ChannelMarker>,
+    pub period: LeaderboardSchedulePeriod,
+    /// Attach the rank card of the member in first place
+    pub winner_card: bool,
+    /// Ping the members on the podium
+    pub mention_winners: bool,
+}
+
+impl Display for LeaderboardSchedule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Posted {} in <#{}>", self.period.name(), self.channel)?;
+        if self.winner_card {
+            f.write_str(", with the winner's rank card")?;
+        }
+        if self.mention_winners {
+            f.write_str(", mentioning the winners")?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -209,16 +824,115 @@ pub struct UserStatus {
     pub xp: i64,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
 pub struct UserInGuild {
     pub guild: Id<GuildMarker>,
     pub user: Id<UserMarker>,
 }
 
-#[derive(Debug)]
+/// When a user last earned XP in a guild, which their message cooldown counts from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct Cooldown {
+    pub guild: Id<GuildMarker>,
+    pub user: Id<UserMarker>,
+    /// Seconds since the Discord epoch
+    pub last_message: i64,
+}
+
+/// A member who left a guild, kept so their XP can be dealt with by the guild's [`LeavePolicy`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct UserCleanup {
+    pub guild: Id<GuildMarker>,
+    pub user: Id<UserMarker>,
+    /// Unix timestamp, in seconds
+    pub removed_at: i64,
+}
+
+#[derive(Debug, serde::Serialize)]
 pub struct RoleReward {
     pub id: Id<RoleMarker>,
     pub requirement: i64,
+    /// Rewards with a duration are taken away again this many hours after they're given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_hours: Option<i64>,
+}
+
+/// The last time we couldn't update someone's roles, kept until an update for them works
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct RoleUpdateFailure {
+    pub guild: Id<GuildMarker>,
+    pub user: Id<UserMarker>,
+    pub reason: String,
+    /// Unix timestamp, in seconds
+    pub failed_at: i64,
+}
+
+/// A time-limited role reward that has been given to someone
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct RewardGrant {
+    pub guild: Id<GuildMarker>,
+    pub user: Id<UserMarker>,
+    pub role: Id<RoleMarker>,
+    /// The grant has run out, and won't be given again until it's earned again
+    pub expired: bool,
+    /// The role has been taken away since the grant ran out
+    pub removed: bool,
+}
+
+/// A level-up message used instead of the guild's usual one when a member reaches a level in
+/// its range. Messages with the same range form a pool, which one is picked from at random.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct LevelUpMessage {
+    pub id: i64,
+    /// Unbounded when unset
+    pub min_level: Option<i64>,
+    /// Unbounded when unset
+    pub max_level: Option<i64>,
+    #[serde(serialize_with = "serialize_template")]
+    pub template: Interpolation,
+}
+
+impl LevelUpMessage {
+    #[must_use]
+    pub fn covers(&self, level: i64) -> bool {
+        self.min_level.is_none_or(|min| min <= level)
+            && self.max_level.is_none_or(|max| level <= max)
+    }
+
+    /// How many levels past the first this message covers. Narrower ranges are more specific.
+    #[must_use]
+    pub fn span(&self) -> i64 {
+        self.max_level
+            .unwrap_or(i64::MAX)
+            .saturating_sub(self.min_level.unwrap_or(0))
+    }
+}
+
+impl Display for LevelUpMessage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (self.min_level, self.max_level) {
+            (Some(min), Some(max)) if min == max => write!(f, "Level {min}")?,
+            (Some(min), Some(max)) => write!(f, "Levels {min}-{max}")?,
+            (Some(min), None) => write!(f, "Level {min} and up")?,
+            (None, Some(max)) => write!(f, "Up to level {max}")?,
+            (None, None) => f.write_str("Any level")?,
+        }
+        write!(f, ": `{}`", self.template.input_value())
+    }
+}
+
+fn serialize_template<S: serde::Serializer>(
+    template: &Interpolation,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&template.input_value())
+}
+
+/// A role held by whoever is in the top `max_rank` of the leaderboard
+#[derive(Debug, serde::Serialize)]
+pub struct RankReward {
+    pub id: Id<RoleMarker>,
+    pub max_rank: i64,
 }
 
 #[inline]
@@ -233,7 +947,34 @@ pub trait RequiredDiscordResources {
     fn required_cache_types() -> ResourceType;
 }
 
+/// A level-up announcement with a rank card attached. The listener can't render cards,
+/// so it hands these off to be rendered and posted.
+#[derive(Clone, Debug)]
+pub struct LevelUpAnnouncement {
+    pub guild: Id<GuildMarker>,
+    pub channel: Id<ChannelMarker>,
+    /// The message that leveled the member up, if the announcement is posted next to it
+    pub reply_to: Option<Id<MessageMarker>>,
+    pub member: MemberDisplayInfo,
+    pub content: Option<String>,
+    /// The card is shown as the embed's image, if there is one
+    pub embed: Option<Embed>,
+    pub allowed_mentions: AllowedMentions,
+}
+
 pub enum EventBusMessage {
     InvalidateRewards(Id<GuildMarker>),
     UpdateConfig(Id<GuildMarker>, GuildConfig),
+    /// An audit log event was committed, and should be posted to the mod-log
+    AuditLogEvent(AuditLogEvent),
+    /// XP changed outside of message leveling, so role rewards should be re-evaluated
+    /// for one user, or every member of the guild if no user is given.
+    /// The flag is set when levels went down, so rewards above them are taken away too.
+    RecheckRewards(Id<GuildMarker>, Option<Id<UserMarker>>, bool),
+    /// A guild's milestone level-up messages changed, and should be reloaded
+    InvalidateLevelUpMessages(Id<GuildMarker>),
+    /// A user turned level-up DMs on or off
+    UpdateLevelUpDms(Id<UserMarker>, bool),
+    /// Discord refused to deliver a DM to this user, so level-up DMs to them should pause
+    DmsClosed(Id<UserMarker>),
 }
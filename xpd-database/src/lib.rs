@@ -11,20 +11,25 @@ mod acq_wrapper;
 mod test;
 mod util;
 
-use std::{fmt::Display, ops::DerefMut};
+use std::{fmt::Display, ops::DerefMut, time::Duration};
 
 pub use acq_wrapper::AcquireWrapper;
 use simpleinterpolation::Interpolation;
 pub use sqlx::PgPool;
-use sqlx::{Acquire, PgConnection, Postgres, query, query_as};
+use sqlx::{Acquire, PgConnection, Postgres, query, query_as, types::Json};
 use tokio_stream::StreamExt;
 use twilight_model::id::{
     Id,
     marker::{ChannelMarker, GenericMarker, GuildMarker, RoleMarker, UserMarker},
 };
-use util::{db_to_id, id_to_db};
+use util::{ReinterpretPrimitiveBits as _, db_to_id, id_to_db};
 use xpd_common::{
-    AuditLogEvent, AuditLogEventKind, GuildConfig, RoleReward, UserInGuild, UserStatus,
+    AuditLogChange, AuditLogEntry, AuditLogEvent, AuditLogEventKind, Cooldown,
+    DEFAULT_AUDIT_LOG_RETENTION_DAYS, DEFAULT_LEAVE_DELETE_AFTER_DAYS, DISCORD_EPOCH_SECS,
+    GuildConfig, JobInterval, KickBanXpPolicy, LeaderboardSchedule, LeaderboardSchedulePeriod,
+    LeavePolicy, LevelUpDelivery, LevelUpMessage, RankReward, RewardGrant, RoleReward,
+    RoleUpdateFailure, ScheduledJob, ScheduledJobKind, UserCleanup, UserInGuild, UserSettings,
+    UserStatus,
 };
 pub async fn guild_rewards<
     'a,
@@ -36,7 +41,7 @@ pub async fn guild_rewards<
 ) -> Result<Vec<RoleReward>, Error> {
     let mut conn = conn.acquire().await?;
     let rewards: Vec<RoleReward> = query!(
-        "SELECT id, requirement FROM role_rewards WHERE guild = $1",
+        "SELECT id, requirement, duration_hours FROM role_rewards WHERE guild = $1",
         id_to_db(guild_id),
     )
     .fetch_all(conn.as_mut())
@@ -45,11 +50,134 @@ pub async fn guild_rewards<
     .map(|row| RoleReward {
         id: db_to_id(row.id),
         requirement: row.requirement,
+        duration_hours: row.duration_hours,
     })
     .collect();
     Ok(rewards)
 }
 
+pub async fn guild_rank_rewards<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild_id: Id<GuildMarker>,
+) -> Result<Vec<RankReward>, Error> {
+    let mut conn = conn.acquire().await?;
+    let rewards: Vec<RankReward> = query!(
+        "SELECT id, max_rank FROM rank_rewards WHERE guild = $1",
+        id_to_db(guild_id),
+    )
+    .fetch_all(conn.as_mut())
+    .await?
+    .into_iter()
+    .map(|row| RankReward {
+        id: db_to_id(row.id),
+        max_rank: row.max_rank,
+    })
+    .collect();
+    Ok(rewards)
+}
+
+pub async fn guild_level_up_messages<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+) -> Result<Vec<LevelUpMessage>, Error> {
+    let mut conn = conn.acquire().await?;
+    let messages = query!(
+        "SELECT id, min_level, max_level, template FROM level_up_messages \
+        WHERE guild = $1 ORDER BY id",
+        id_to_db(guild)
+    )
+    .fetch_all(conn.as_mut())
+    .await?
+    .into_iter()
+    .map(|row| {
+        Ok::<_, Error>(LevelUpMessage {
+            id: row.id,
+            min_level: row.min_level,
+            max_level: row.max_level,
+            template: Interpolation::new(row.template)?,
+        })
+    })
+    .collect::<Result<_, _>>()?;
+    Ok(messages)
+}
+
+/// Returns the new message's ID.
+pub async fn add_level_up_message<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+    min_level: Option<i64>,
+    max_level: Option<i64>,
+    template: &str,
+) -> Result<i64, Error> {
+    let mut conn = conn.acquire().await?;
+    let id = query!(
+        "INSERT INTO level_up_messages (guild, min_level, max_level, template) \
+        VALUES ($1, $2, $3, $4) RETURNING id",
+        id_to_db(guild),
+        min_level,
+        max_level,
+        template
+    )
+    .fetch_one(conn.as_mut())
+    .await?
+    .id;
+    Ok(id)
+}
+
+/// Returns number of rows affected.
+pub async fn delete_level_up_message<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+    id: i64,
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!(
+        "DELETE FROM level_up_messages WHERE guild = $1 AND id = $2",
+        id_to_db(guild),
+        id
+    )
+    .execute(conn.as_mut())
+    .await?
+    .rows_affected();
+    Ok(rows)
+}
+
+/// Returns number of rows affected.
+pub async fn delete_level_up_messages_guild<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!(
+        "DELETE FROM level_up_messages WHERE guild = $1",
+        id_to_db(guild)
+    )
+    .execute(conn.as_mut())
+    .await?
+    .rows_affected();
+    Ok(rows)
+}
+
 pub async fn guild_config<
     'a,
     D: DerefMut<Target = PgConnection> + Send,
@@ -63,7 +191,11 @@ pub async fn guild_config<
         RawGuildConfig,
         "SELECT one_at_a_time, level_up_message, level_up_channel, ping_on_level_up,\
                  max_xp_per_message, min_xp_per_message, message_cooldown, \
-                 guild_card_default_show_off \
+                 guild_card_default_show_off, audit_log_retention_days, \
+                 prune_compliance_audit_logs, mod_log_channel, kick_ban_xp_policy, \
+                 leave_policy, leave_delete_after_days, level_up_embed, level_up_embed_title, \
+                 level_up_embed_description, level_up_embed_color, level_up_embed_footer, \
+                 level_up_rank_card, level_up_delivery \
                  FROM guild_configs WHERE id = $1",
         id_to_db(guild)
     )
@@ -185,24 +317,26 @@ pub async fn add_audit_log_event<
 >(
     conn: A,
     event: AuditLogEvent,
-) -> Result<(), Error> {
+) -> Result<i64, Error> {
     let mut conn = conn.acquire().await?;
-    query!(
+    let id = query!(
         "INSERT INTO audit_logs \
             (guild, target, moderator,
-                timestamp, previous, delta, kind)
-             VALUES ($1, $2, $3, $4, $5, $6, $7)",
+                timestamp, previous, delta, kind, details)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING id",
         id_to_db(event.guild),
-        id_to_db(event.target),
+        event.target.map(id_to_db),
         id_to_db(event.moderator),
         event.timestamp,
         event.previous,
         event.delta,
         event.kind.to_i64(),
+        event.details.map(Json) as _,
     )
-    .execute(conn.as_mut())
-    .await?;
-    Ok(())
+    .fetch_one(conn.as_mut())
+    .await?
+    .id;
+    Ok(id)
 }
 
 pub async fn get_audit_log_events<
@@ -214,25 +348,25 @@ pub async fn get_audit_log_events<
     guild: Id<GuildMarker>,
     actions_on_user: Option<Id<UserMarker>>,
     actions_by_moderator: Option<Id<UserMarker>>,
-) -> Result<Vec<AuditLogEvent>, Error> {
+) -> Result<Vec<AuditLogEntry>, Error> {
     let mut conn = conn.acquire().await?;
     let mut stream = query!(
-        "SELECT target, moderator,
-        timestamp, previous, delta, kind
-        FROM audit_logs WHERE guild = $1",
+        r#"SELECT id, target, moderator,
+        timestamp, previous, delta, kind, details as "details: Json<AuditLogChange>"
+        FROM audit_logs WHERE guild = $1"#,
         id_to_db(guild)
     )
     .fetch(conn.as_mut());
     let mut logs = Vec::new();
     while let Some(row) = stream.next().await.transpose()? {
-        let target = db_to_id(row.target);
+        let target = row.target.map(db_to_id);
         let moderator = db_to_id(row.moderator);
         if actions_by_moderator.is_some_and(|requested_moderator| requested_moderator != moderator)
-            || actions_on_user.is_some_and(|requested_user| requested_user != target)
+            || actions_on_user.is_some_and(|requested_user| Some(requested_user) != target)
         {
             continue;
         }
-        let log = AuditLogEvent {
+        let event = AuditLogEvent {
             guild,
             target,
             moderator: db_to_id(row.moderator),
@@ -240,8 +374,44 @@ pub async fn get_audit_log_events<
             previous: row.previous,
             delta: row.delta,
             kind: AuditLogEventKind::from_i64(row.kind).ok_or(Error::UnknownAuditLogEventKind)?,
+            details: row.details.map(|Json(details)| details),
+        };
+        logs.push(AuditLogEntry { id: row.id, event });
+    }
+    Ok(logs)
+}
+
+/// Every audit log event in any guild where this user was the target or the moderator, oldest
+/// first.
+pub async fn user_audit_log_events<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    user: Id<UserMarker>,
+) -> Result<Vec<AuditLogEntry>, Error> {
+    let mut conn = conn.acquire().await?;
+    let mut stream = query!(
+        r#"SELECT id, guild, target, moderator,
+        timestamp, previous, delta, kind, details as "details: Json<AuditLogChange>"
+        FROM audit_logs WHERE target = $1 OR moderator = $1 ORDER BY id"#,
+        id_to_db(user)
+    )
+    .fetch(conn.as_mut());
+    let mut logs = Vec::new();
+    while let Some(row) = stream.next().await.transpose()? {
+        let event = AuditLogEvent {
+            guild: db_to_id(row.guild),
+            target: row.target.map(db_to_id),
+            moderator: db_to_id(row.moderator),
+            timestamp: row.timestamp,
+            previous: row.previous,
+            delta: row.delta,
+            kind: AuditLogEventKind::from_i64(row.kind).ok_or(Error::UnknownAuditLogEventKind)?,
+            details: row.details.map(|Json(details)| details),
         };
-        logs.push(log);
+        logs.push(AuditLogEntry { id: row.id, event });
     }
     Ok(logs)
 }
@@ -261,6 +431,38 @@ pub async fn delete_audit_log_events_guild<
     Ok(())
 }
 
+/// Delete audit log events older than their guild's retention period. Events of a
+/// [compliance](AuditLogEventKind::COMPLIANCE) kind are kept, unless the guild opted in to
+/// pruning them too.
+pub async fn prune_audit_log_events<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    let compliance_kinds = AuditLogEventKind::COMPLIANCE.map(AuditLogEventKind::to_i64);
+    let rows = query!(
+        "DELETE FROM audit_logs WHERE \
+            timestamp < EXTRACT(EPOCH FROM NOW())::INT8 - $1::INT8 - 86400 * COALESCE( \
+                (SELECT audit_log_retention_days FROM guild_configs WHERE id = audit_logs.guild), \
+                $2::INT4 \
+            ) \
+            AND (kind != ALL($3) OR COALESCE( \
+                (SELECT prune_compliance_audit_logs FROM guild_configs WHERE id = audit_logs.guild), \
+                FALSE \
+            ))",
+        DISCORD_EPOCH_SECS,
+        DEFAULT_AUDIT_LOG_RETENTION_DAYS,
+        &compliance_kinds
+    )
+    .execute(conn.as_mut())
+    .await?
+    .rows_affected();
+    Ok(rows)
+}
+
 pub async fn delete_audit_log_events_user<
     'a,
     D: DerefMut<Target = PgConnection> + Send,
@@ -284,16 +486,17 @@ pub async fn delete_audit_log_events_user_guild<
     conn: A,
     target: Id<UserMarker>,
     guild: Id<GuildMarker>,
-) -> Result<(), Error> {
+) -> Result<u64, Error> {
     let mut conn = conn.acquire().await?;
-    query!(
+    let rows = query!(
         "DELETE FROM audit_logs WHERE target = $1 AND guild = $2",
         id_to_db(target),
         id_to_db(guild)
     )
     .execute(conn.as_mut())
-    .await?;
-    Ok(())
+    .await?
+    .rows_affected();
+    Ok(rows)
 }
 
 pub async fn get_last_message<
@@ -317,6 +520,48 @@ pub async fn get_last_message<
     Ok(last_message)
 }
 
+pub async fn user_cooldowns<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    user: Id<UserMarker>,
+) -> Result<Vec<Cooldown>, Error> {
+    let mut conn = conn.acquire().await?;
+    let cooldowns = query!(
+        "SELECT guild_id, last_message FROM cooldowns WHERE user_id = $1 ORDER BY guild_id",
+        id_to_db(user)
+    )
+    .fetch_all(conn.as_mut())
+    .await?
+    .into_iter()
+    .map(|row| Cooldown {
+        guild: db_to_id(row.guild_id),
+        user,
+        last_message: row.last_message,
+    })
+    .collect();
+    Ok(cooldowns)
+}
+
+/// Returns number of rows affected.
+pub async fn delete_cooldowns_user<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    user: Id<UserMarker>,
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!("DELETE FROM cooldowns WHERE user_id = $1", id_to_db(user))
+        .execute(conn.as_mut())
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
 pub async fn delete_cooldowns_starting_before<
     'a,
     D: DerefMut<Target = PgConnection> + Send,
@@ -352,105 +597,282 @@ pub async fn delete_levels_user_guild<
     Ok(output.map_or(0, |v| v.xp))
 }
 
-pub async fn count_with_higher_xp<
+/// Like [`delete_levels_user_guild`], but the XP is set aside, to be given back by
+/// [`restore_soft_deleted_levels`]. Returns the XP that was removed.
+pub async fn soft_delete_levels_user_guild<
     'a,
     D: DerefMut<Target = PgConnection> + Send,
     A: Acquire<'a, Database = Postgres, Connection = D> + Send,
 >(
     conn: A,
+    user: Id<UserMarker>,
     guild: Id<GuildMarker>,
-    xp: i64,
-) -> Result<Option<i64>, Error> {
+) -> Result<i64, Error> {
     let mut conn = conn.acquire().await?;
-    let count = query!(
-        "SELECT COUNT(*) as count FROM levels WHERE xp > $1 AND guild = $2",
-        xp,
+    // XP set aside earlier, and earned again since, is added together
+    let output = query!(
+        "WITH deleted AS (DELETE FROM levels WHERE id = $1 AND guild = $2 RETURNING xp), \
+            kept AS (INSERT INTO soft_deleted_levels (guild, id, xp) \
+                SELECT $2::INT8, $1::INT8, xp FROM deleted WHERE xp > 0 \
+                ON CONFLICT (guild, id) \
+                DO UPDATE SET xp = soft_deleted_levels.xp + excluded.xp, deleted_at = NOW()) \
+            SELECT xp FROM deleted",
+        id_to_db(user),
         id_to_db(guild)
     )
-    .fetch_one(conn.as_mut())
-    .await?
-    .count;
-    Ok(count)
+    .fetch_optional(conn.as_mut())
+    .await?;
+    Ok(output.map_or(0, |v| v.xp))
 }
 
-pub async fn levels_in_guild<
+/// Take back XP set aside by [`soft_delete_levels_user_guild`], so it can be given back.
+/// Returns [`None`] if there wasn't any.
+pub async fn restore_soft_deleted_levels<
     'a,
     D: DerefMut<Target = PgConnection> + Send,
     A: Acquire<'a, Database = Postgres, Connection = D> + Send,
 >(
     conn: A,
+    user: Id<UserMarker>,
     guild: Id<GuildMarker>,
-) -> Result<i64, Error> {
+) -> Result<Option<i64>, Error> {
     let mut conn = conn.acquire().await?;
-    let count = query!(
-        "SELECT COUNT(id) as count FROM levels WHERE guild = $1",
+    let output = query!(
+        "DELETE FROM soft_deleted_levels WHERE id = $1 AND guild = $2 RETURNING xp",
+        id_to_db(user),
         id_to_db(guild)
     )
-    .fetch_one(conn.as_mut())
-    .await?
-    .count;
-    Ok(count.unwrap_or(0))
+    .fetch_optional(conn.as_mut())
+    .await?;
+    Ok(output.map(|v| v.xp))
 }
 
-pub async fn total_levels<
+/// Take `xp` off what's set aside for a user, because it was given back some other way.
+/// Returns number of rows affected.
+pub async fn reduce_soft_deleted_levels<
     'a,
     D: DerefMut<Target = PgConnection> + Send,
     A: Acquire<'a, Database = Postgres, Connection = D> + Send,
 >(
     conn: A,
-) -> Result<i64, Error> {
+    user: Id<UserMarker>,
+    guild: Id<GuildMarker>,
+    xp: i64,
+) -> Result<u64, Error> {
     let mut conn = conn.acquire().await?;
-    let count = query!(
-        "SELECT reltuples::bigint AS count FROM pg_class
-        WHERE oid = 'public.levels'::regclass",
+    let rows = query!(
+        "UPDATE soft_deleted_levels SET xp = xp - $3 WHERE id = $1 AND guild = $2",
+        id_to_db(user),
+        id_to_db(guild),
+        xp
     )
-    .fetch_one(conn.as_mut())
+    .execute(conn.as_mut())
     .await?
-    .count;
-    Ok(count.unwrap_or(0))
+    .rows_affected();
+    query!(
+        "DELETE FROM soft_deleted_levels WHERE id = $1 AND guild = $2 AND xp <= 0",
+        id_to_db(user),
+        id_to_db(guild)
+    )
+    .execute(conn.as_mut())
+    .await?;
+    Ok(rows)
 }
 
-pub async fn user_xp<
+/// XP set aside for a user in each guild
+pub async fn soft_deleted_levels_user<
     'a,
     D: DerefMut<Target = PgConnection> + Send,
     A: Acquire<'a, Database = Postgres, Connection = D> + Send,
 >(
     conn: A,
-    guild: Id<GuildMarker>,
     user: Id<UserMarker>,
-) -> Result<Option<i64>, Error> {
+) -> Result<Vec<UserStatus>, Error> {
     let mut conn = conn.acquire().await?;
-    // Select current XP from the database, return 0 if there is no row
-    let xp = query!(
-        "SELECT xp FROM levels WHERE id = $1 AND guild = $2",
-        id_to_db(user),
-        id_to_db(guild)
+    let levels = query!(
+        "SELECT guild, xp FROM soft_deleted_levels WHERE id = $1",
+        id_to_db(user)
     )
-    .fetch_optional(conn.as_mut())
+    .fetch_all(conn.as_mut())
     .await?
-    .map(|v| v.xp);
-    Ok(xp)
+    .into_iter()
+    .map(|row| UserStatus {
+        id: user,
+        guild: db_to_id(row.guild),
+        xp: row.xp,
+    })
+    .collect();
+    Ok(levels)
 }
 
-pub async fn get_all_levels<
+/// Returns number of rows affected.
+pub async fn delete_soft_deleted_levels_user_guild<
     'a,
     D: DerefMut<Target = PgConnection> + Send,
     A: Acquire<'a, Database = Postgres, Connection = D> + Send,
 >(
     conn: A,
     user: Id<UserMarker>,
-) -> Result<Vec<UserStatus>, Error> {
+    guild: Id<GuildMarker>,
+) -> Result<u64, Error> {
     let mut conn = conn.acquire().await?;
-    let mut raw_levels =
-        query!("SELECT guild, xp FROM levels WHERE id = $1", id_to_db(user)).fetch(conn.as_mut());
-    // 200 was chosen because that's the max number of guilds you can be in.
-    let mut output = Vec::with_capacity(200);
-    while let Some(v) = raw_levels.next().await.transpose()? {
-        let status = UserStatus {
-            id: user,
-            guild: db_to_id(v.guild),
-            xp: v.xp,
-        };
+    let rows = query!(
+        "DELETE FROM soft_deleted_levels WHERE id = $1 AND guild = $2",
+        id_to_db(user),
+        id_to_db(guild)
+    )
+    .execute(conn.as_mut())
+    .await?
+    .rows_affected();
+    Ok(rows)
+}
+
+/// Returns number of rows affected.
+pub async fn delete_soft_deleted_levels_user<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    user: Id<UserMarker>,
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!(
+        "DELETE FROM soft_deleted_levels WHERE id = $1",
+        id_to_db(user)
+    )
+    .execute(conn.as_mut())
+    .await?
+    .rows_affected();
+    Ok(rows)
+}
+
+/// Returns number of rows affected.
+pub async fn delete_soft_deleted_levels_guild<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!(
+        "DELETE FROM soft_deleted_levels WHERE guild = $1",
+        id_to_db(guild)
+    )
+    .execute(conn.as_mut())
+    .await?
+    .rows_affected();
+    Ok(rows)
+}
+
+/// How many members have more XP than `xp`. Members who left a guild which
+/// [hides](LeavePolicy::Hide) them aren't counted.
+pub async fn count_with_higher_xp<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+    xp: i64,
+) -> Result<Option<i64>, Error> {
+    let mut conn = conn.acquire().await?;
+    let count = query!(
+        "SELECT COUNT(*) as count FROM levels WHERE xp > $1 AND guild = $2 \
+            AND NOT EXISTS (SELECT 1 FROM user_cleanups \
+                INNER JOIN guild_configs ON guild_configs.id = user_cleanups.guild_id \
+                WHERE user_cleanups.guild_id = levels.guild AND user_cleanups.user_id = levels.id \
+                AND guild_configs.leave_policy = $3)",
+        xp,
+        id_to_db(guild),
+        LeavePolicy::Hide.to_i16()
+    )
+    .fetch_one(conn.as_mut())
+    .await?
+    .count;
+    Ok(count)
+}
+
+pub async fn levels_in_guild<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+) -> Result<i64, Error> {
+    let mut conn = conn.acquire().await?;
+    let count = query!(
+        "SELECT COUNT(id) as count FROM levels WHERE guild = $1",
+        id_to_db(guild)
+    )
+    .fetch_one(conn.as_mut())
+    .await?
+    .count;
+    Ok(count.unwrap_or(0))
+}
+
+pub async fn total_levels<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+) -> Result<i64, Error> {
+    let mut conn = conn.acquire().await?;
+    let count = query!(
+        "SELECT reltuples::bigint AS count FROM pg_class
+        WHERE oid = 'public.levels'::regclass",
+    )
+    .fetch_one(conn.as_mut())
+    .await?
+    .count;
+    Ok(count.unwrap_or(0))
+}
+
+pub async fn user_xp<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+    user: Id<UserMarker>,
+) -> Result<Option<i64>, Error> {
+    let mut conn = conn.acquire().await?;
+    // Select current XP from the database, return 0 if there is no row
+    let xp = query!(
+        "SELECT xp FROM levels WHERE id = $1 AND guild = $2",
+        id_to_db(user),
+        id_to_db(guild)
+    )
+    .fetch_optional(conn.as_mut())
+    .await?
+    .map(|v| v.xp);
+    Ok(xp)
+}
+
+pub async fn get_all_levels<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    user: Id<UserMarker>,
+) -> Result<Vec<UserStatus>, Error> {
+    let mut conn = conn.acquire().await?;
+    let mut raw_levels =
+        query!("SELECT guild, xp FROM levels WHERE id = $1", id_to_db(user)).fetch(conn.as_mut());
+    // 200 was chosen because that's the max number of guilds you can be in.
+    let mut output = Vec::with_capacity(200);
+    while let Some(v) = raw_levels.next().await.transpose()? {
+        let status = UserStatus {
+            id: user,
+            guild: db_to_id(v.guild),
+            xp: v.xp,
+        };
         output.push(status);
     }
     Ok(output)
@@ -487,12 +909,13 @@ pub async fn delete_card_customizations<
 >(
     conn: A,
     target: Id<GenericMarker>,
-) -> Result<(), Error> {
+) -> Result<u64, Error> {
     let mut conn = conn.acquire().await?;
-    query!("DELETE FROM custom_card WHERE id = $1", id_to_db(target))
+    let rows = query!("DELETE FROM custom_card WHERE id = $1", id_to_db(target))
         .execute(conn.as_mut())
-        .await?;
-    Ok(())
+        .await?
+        .rows_affected();
+    Ok(rows)
 }
 
 pub async fn delete_levels_user<
@@ -511,6 +934,25 @@ pub async fn delete_levels_user<
     Ok(rows)
 }
 
+pub async fn total_guild_xp<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+) -> Result<i64, Error> {
+    let mut conn = conn.acquire().await?;
+    let xp = query!(
+        r#"SELECT COALESCE(SUM(xp), 0)::INT8 AS "xp!" FROM levels WHERE guild = $1"#,
+        id_to_db(guild)
+    )
+    .fetch_one(conn.as_mut())
+    .await?
+    .xp;
+    Ok(xp)
+}
+
 pub async fn delete_levels_guild<
     'a,
     D: DerefMut<Target = PgConnection> + Send,
@@ -662,29 +1104,79 @@ pub async fn update_guild_config<
                 RawGuildConfig,
                 "INSERT INTO guild_configs (id, level_up_message, level_up_channel, ping_on_level_up, \
                     max_xp_per_message, min_xp_per_message, message_cooldown, one_at_a_time,
-                    guild_card_default_show_off) \
-                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, COALESCE($9, FALSE)) \
+                    guild_card_default_show_off, audit_log_retention_days, \
+                    prune_compliance_audit_logs, mod_log_channel, kick_ban_xp_policy, \
+                    leave_policy, leave_delete_after_days, level_up_embed, level_up_embed_title, \
+                    level_up_embed_description, level_up_embed_color, level_up_embed_footer, \
+                    level_up_rank_card, level_up_delivery) \
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, COALESCE($9, FALSE), $10, $11, $12, $13, \
+                    $14, $15, $16, $17, $18, $19, $20, $21, $22) \
                 ON CONFLICT (id) DO UPDATE SET \
-                level_up_message = COALESCE($2, guild_configs.level_up_message), \
-                level_up_channel = COALESCE($3, guild_configs.level_up_channel), \
+                level_up_message = CASE WHEN $23 THEN $2 ELSE guild_configs.level_up_message END, \
+                level_up_channel = CASE WHEN $24 THEN $3 ELSE guild_configs.level_up_channel END, \
                 ping_on_level_up = COALESCE($4, guild_configs.ping_on_level_up), \
                 max_xp_per_message = COALESCE($5, guild_configs.max_xp_per_message), \
                 min_xp_per_message = COALESCE($6, guild_configs.min_xp_per_message), \
                 message_cooldown = COALESCE($7, guild_configs.message_cooldown), \
                 one_at_a_time = COALESCE($8, guild_configs.one_at_a_time), \
-                guild_card_default_show_off = COALESCE($9, guild_configs.guild_card_default_show_off) \
+                guild_card_default_show_off = COALESCE($9, guild_configs.guild_card_default_show_off), \
+                audit_log_retention_days = \
+                    CASE WHEN $25 THEN $10 ELSE guild_configs.audit_log_retention_days END, \
+                prune_compliance_audit_logs = \
+                    CASE WHEN $26 THEN $11 ELSE guild_configs.prune_compliance_audit_logs END, \
+                mod_log_channel = CASE WHEN $27 THEN $12 ELSE guild_configs.mod_log_channel END, \
+                kick_ban_xp_policy = COALESCE($13, guild_configs.kick_ban_xp_policy), \
+                leave_policy = COALESCE($14, guild_configs.leave_policy), \
+                leave_delete_after_days = COALESCE($15, guild_configs.leave_delete_after_days), \
+                level_up_embed = COALESCE($16, guild_configs.level_up_embed), \
+                level_up_embed_title = \
+                    CASE WHEN $28 THEN $17 ELSE guild_configs.level_up_embed_title END, \
+                level_up_embed_description = \
+                    CASE WHEN $29 THEN $18 ELSE guild_configs.level_up_embed_description END, \
+                level_up_embed_color = \
+                    CASE WHEN $30 THEN $19 ELSE guild_configs.level_up_embed_color END, \
+                level_up_embed_footer = \
+                    CASE WHEN $31 THEN $20 ELSE guild_configs.level_up_embed_footer END, \
+                level_up_rank_card = COALESCE($21, guild_configs.level_up_rank_card), \
+                level_up_delivery = COALESCE($22, guild_configs.level_up_delivery) \
                 RETURNING one_at_a_time, level_up_message, level_up_channel, ping_on_level_up, \
                 max_xp_per_message, min_xp_per_message, message_cooldown, \
-                guild_card_default_show_off",
+                guild_card_default_show_off, audit_log_retention_days, prune_compliance_audit_logs, \
+                mod_log_channel, kick_ban_xp_policy, leave_policy, leave_delete_after_days, \
+                level_up_embed, level_up_embed_title, level_up_embed_description, \
+                level_up_embed_color, level_up_embed_footer, level_up_rank_card, \
+                level_up_delivery",
                 id_to_db(guild),
-                cfg.level_up_message.map(|v| v),
-                cfg.level_up_channel.as_ref().map(|id| id_to_db(*id)),
+                cfg.level_up_message.as_ref().and_then(Option::as_deref),
+                cfg.level_up_channel.flatten().map(id_to_db),
                 cfg.ping_users,
                 cfg.max_xp_per_message,
                 cfg.min_xp_per_message,
                 cfg.message_cooldown,
                 cfg.one_at_a_time,
-                cfg.guild_card_default_show_off
+                cfg.guild_card_default_show_off,
+                cfg.audit_log_retention_days.flatten(),
+                cfg.prune_compliance_audit_logs.flatten(),
+                cfg.mod_log_channel.flatten().map(id_to_db),
+                cfg.kick_ban_xp_policy.map(KickBanXpPolicy::to_i16),
+                cfg.leave_policy.map(LeavePolicy::to_i16),
+                cfg.leave_delete_after_days,
+                cfg.level_up_embed,
+                cfg.level_up_embed_title.as_ref().and_then(Option::as_deref),
+                cfg.level_up_embed_description.as_ref().and_then(Option::as_deref),
+                cfg.level_up_embed_color.flatten().map(|v| v.reinterpret_bits()),
+                cfg.level_up_embed_footer.as_ref().and_then(Option::as_deref),
+                cfg.level_up_rank_card,
+                cfg.level_up_delivery.map(LevelUpDelivery::to_i16),
+                cfg.level_up_message.is_some(),
+                cfg.level_up_channel.is_some(),
+                cfg.audit_log_retention_days.is_some(),
+                cfg.prune_compliance_audit_logs.is_some(),
+                cfg.mod_log_channel.is_some(),
+                cfg.level_up_embed_title.is_some(),
+                cfg.level_up_embed_description.is_some(),
+                cfg.level_up_embed_color.is_some(),
+                cfg.level_up_embed_footer.is_some()
             )
         .fetch_one(conn.as_mut())
         .await?
@@ -699,12 +1191,13 @@ pub async fn delete_guild_config<
 >(
     conn: A,
     guild: Id<GuildMarker>,
-) -> Result<(), Error> {
+) -> Result<u64, Error> {
     let mut conn = conn.acquire().await?;
-    query!("DELETE FROM guild_configs WHERE id = $1", id_to_db(guild))
+    let rows = query!("DELETE FROM guild_configs WHERE id = $1", id_to_db(guild))
         .execute(conn.as_mut())
-        .await?;
-    Ok(())
+        .await?
+        .rows_affected();
+    Ok(rows)
 }
 
 pub async fn add_guild_cleanup<
@@ -733,15 +1226,16 @@ pub async fn delete_guild_cleanup<
 >(
     conn: A,
     guild: Id<GuildMarker>,
-) -> Result<(), Error> {
+) -> Result<u64, Error> {
     let mut conn = conn.acquire().await?;
-    query!(
+    let rows = query!(
         "DELETE FROM guild_cleanups WHERE guild = $1",
         id_to_db(guild)
     )
     .execute(conn.as_mut())
-    .await?;
-    Ok(())
+    .await?
+    .rows_affected();
+    Ok(rows)
 }
 
 pub async fn get_active_guild_cleanups<
@@ -750,11 +1244,14 @@ pub async fn get_active_guild_cleanups<
     A: Acquire<'a, Database = Postgres, Connection = D> + Send,
 >(
     conn: A,
+    retention: Duration,
 ) -> Result<Vec<Id<GuildMarker>>, Error> {
     let mut conn = conn.acquire().await?;
-    let mut records =
-        query!("SELECT guild FROM guild_cleanups WHERE removed_at + interval '30 days' < NOW()")
-            .fetch(conn.as_mut());
+    let mut records = query!(
+        "SELECT guild FROM guild_cleanups WHERE removed_at + $1::INTERVAL < NOW()",
+        retention as _
+    )
+    .fetch(conn.as_mut());
 
     let mut output = Vec::with_capacity(16);
     while let Some(v) = records.next().await {
@@ -799,28 +1296,87 @@ pub async fn delete_user_guild_cleanup<
     conn: A,
     guild: Id<GuildMarker>,
     user: Id<UserMarker>,
-) -> Result<(), Error> {
+) -> Result<u64, Error> {
     let mut conn = conn.acquire().await?;
-    query!(
+    let rows = query!(
         "DELETE FROM user_cleanups WHERE guild_id = $1 AND user_id = $2",
         id_to_db(guild),
         id_to_db(user)
     )
     .execute(conn.as_mut())
-    .await?;
-    Ok(())
+    .await?
+    .rows_affected();
+    Ok(rows)
+}
+
+/// Every guild this user has left which still has a cleanup pending for them
+pub async fn user_cleanups<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    user: Id<UserMarker>,
+) -> Result<Vec<UserCleanup>, Error> {
+    let mut conn = conn.acquire().await?;
+    let cleanups = query!(
+        "SELECT guild_id, EXTRACT(EPOCH FROM removed_at)::INT8 as \"removed_at!\" \
+        FROM user_cleanups WHERE user_id = $1 ORDER BY guild_id",
+        id_to_db(user)
+    )
+    .fetch_all(conn.as_mut())
+    .await?
+    .into_iter()
+    .map(|row| UserCleanup {
+        guild: db_to_id(row.guild_id),
+        user,
+        removed_at: row.removed_at,
+    })
+    .collect();
+    Ok(cleanups)
+}
+
+/// Returns number of rows affected.
+pub async fn delete_user_cleanups_user<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    user: Id<UserMarker>,
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!(
+        "DELETE FROM user_cleanups WHERE user_id = $1",
+        id_to_db(user)
+    )
+    .execute(conn.as_mut())
+    .await?
+    .rows_affected();
+    Ok(rows)
 }
 
+/// Members whose guild deletes the XP of members who leave, and who have been gone for as long
+/// as that guild asked, and at least `min_retention`.
 pub async fn get_active_user_guild_cleanups<
     'a,
     D: DerefMut<Target = PgConnection> + Send,
     A: Acquire<'a, Database = Postgres, Connection = D> + Send,
 >(
     conn: A,
+    min_retention: Duration,
 ) -> Result<Vec<UserInGuild>, Error> {
     let mut conn = conn.acquire().await?;
     let mut records = query!(
-        "SELECT user_id, guild_id FROM user_cleanups WHERE removed_at + interval '30 days' < NOW()"
+        "SELECT user_id, guild_id FROM user_cleanups \
+            INNER JOIN guild_configs ON guild_configs.id = user_cleanups.guild_id \
+            WHERE guild_configs.leave_policy = $1 \
+            AND removed_at + make_interval(days => \
+                COALESCE(guild_configs.leave_delete_after_days, $2)) < NOW() \
+            AND removed_at + $3::INTERVAL < NOW()",
+        LeavePolicy::DeleteAfter.to_i16(),
+        DEFAULT_LEAVE_DELETE_AFTER_DAYS,
+        min_retention as _
     )
     .fetch(conn.as_mut());
 
@@ -840,6 +1396,8 @@ pub async fn get_active_user_guild_cleanups<
     Ok(output)
 }
 
+/// Every member of the guild with levels. Members who left a guild which
+/// [hides](LeavePolicy::Hide) them are left out.
 pub async fn get_guild_leaderboard<
     'a,
     D: DerefMut<Target = PgConnection> + Send,
@@ -849,8 +1407,16 @@ pub async fn get_guild_leaderboard<
     guild: Id<GuildMarker>,
 ) -> Result<Vec<UserStatus>, Error> {
     let mut conn = conn.acquire().await?;
-    let mut users =
-        query!("SELECT * FROM levels WHERE guild = $1", id_to_db(guild),).fetch(conn.as_mut());
+    let mut users = query!(
+        "SELECT id, xp FROM levels WHERE guild = $1 \
+            AND NOT EXISTS (SELECT 1 FROM user_cleanups \
+                INNER JOIN guild_configs ON guild_configs.id = user_cleanups.guild_id \
+                WHERE user_cleanups.guild_id = levels.guild AND user_cleanups.user_id = levels.id \
+                AND guild_configs.leave_policy = $2)",
+        id_to_db(guild),
+        LeavePolicy::Hide.to_i16()
+    )
+    .fetch(conn.as_mut());
     let mut output = Vec::with_capacity(1024);
     while let Some(rec) = users.next().await.transpose()? {
         let status = UserStatus {
@@ -863,6 +1429,31 @@ pub async fn get_guild_leaderboard<
     Ok(output)
 }
 
+/// Members of this guild's leaderboard who asked not to be shown on it. They still
+/// count for everything else that ranks members, like rank rewards.
+pub async fn hidden_from_leaderboard<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+) -> Result<Vec<Id<UserMarker>>, Error> {
+    let mut conn = conn.acquire().await?;
+    let users = query!(
+        "SELECT levels.id FROM levels INNER JOIN user_settings \
+            ON user_settings.id = levels.id \
+            WHERE levels.guild = $1 AND user_settings.hide_from_leaderboard",
+        id_to_db(guild)
+    )
+    .fetch_all(conn.as_mut())
+    .await?
+    .into_iter()
+    .map(|row| db_to_id(row.id))
+    .collect();
+    Ok(users)
+}
+
 pub async fn add_reward_role<
     'a,
     D: DerefMut<Target = PgConnection> + Send,
@@ -872,42 +1463,609 @@ pub async fn add_reward_role<
     guild: Id<GuildMarker>,
     requirement: i64,
     role: Id<RoleMarker>,
+    duration_hours: Option<i64>,
 ) -> Result<(), Error> {
     let mut conn = conn.acquire().await?;
     query!(
-        "INSERT INTO role_rewards (id, requirement, guild) VALUES ($1, $2, $3) \
-        ON CONFLICT (id, guild) DO UPDATE SET requirement = $2",
+        "INSERT INTO role_rewards (id, requirement, guild, duration_hours) VALUES ($1, $2, $3, $4) \
+        ON CONFLICT (id, guild) DO UPDATE SET requirement = $2, duration_hours = $4",
         id_to_db(role),
         requirement,
-        id_to_db(guild)
+        id_to_db(guild),
+        duration_hours
     )
     .execute(conn.as_mut())
     .await?;
     Ok(())
 }
 
-/// Returns number of rows affected.
-/// If two Some values are passed in, all the values that match *either* will be deleted.
-/// TODO: Consider if this behavior makes sense. Maybe it should be and.
-pub async fn delete_reward_role<
+pub async fn add_rank_reward<
     'a,
     D: DerefMut<Target = PgConnection> + Send,
     A: Acquire<'a, Database = Postgres, Connection = D> + Send,
 >(
     conn: A,
     guild: Id<GuildMarker>,
-    requirement: Option<i64>,
-    role: Option<Id<RoleMarker>>,
+    max_rank: i64,
+    role: Id<RoleMarker>,
+) -> Result<(), Error> {
+    let mut conn = conn.acquire().await?;
+    query!(
+        "INSERT INTO rank_rewards (id, max_rank, guild) VALUES ($1, $2, $3) \
+        ON CONFLICT (guild, id) DO UPDATE SET max_rank = $2",
+        id_to_db(role),
+        max_rank,
+        id_to_db(guild)
+    )
+    .execute(conn.as_mut())
+    .await?;
+    Ok(())
+}
+
+/// Returns number of rows affected.
+pub async fn delete_rank_reward<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+    role: Id<RoleMarker>,
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!(
+        "DELETE FROM rank_rewards WHERE guild = $1 AND id = $2",
+        id_to_db(guild),
+        id_to_db(role)
+    )
+    .execute(conn.as_mut())
+    .await?
+    .rows_affected();
+    Ok(rows)
+}
+
+/// Returns number of rows affected.
+/// If two Some values are passed in, all the values that match *either* will be deleted.
+/// TODO: Consider if this behavior makes sense. Maybe it should be and.
+///
+/// Grants of the deleted rewards are deleted with them.
+pub async fn delete_reward_role<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+    requirement: Option<i64>,
+    role: Option<Id<RoleMarker>>,
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    if requirement.is_none() && role.is_none() {
+        return Err(Error::UnspecifiedDelete);
+    }
+    let rows = query!(
+        "WITH deleted AS (DELETE FROM role_rewards \
+                WHERE guild = $1 AND (id = $2 OR requirement = $3) RETURNING id), \
+            grants AS (DELETE FROM reward_grants \
+                WHERE guild = $1 AND role IN (SELECT id FROM deleted)) \
+            SELECT COUNT(*) AS \"count!\" FROM deleted",
+        id_to_db(guild),
+        role.map(id_to_db),
+        requirement
+    )
+    .fetch_one(conn.as_mut())
+    .await?
+    .count;
+    Ok(rows.try_into().unwrap_or(0))
+}
+
+/// Every grant of a time-limited reward a user has in a guild, expired or not
+pub async fn user_reward_grants<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+    user: Id<UserMarker>,
+) -> Result<Vec<RewardGrant>, Error> {
+    let mut conn = conn.acquire().await?;
+    let grants = query!(
+        "SELECT role, expires_at <= NOW() as \"expired!\", removed FROM reward_grants \
+        WHERE guild = $1 AND id = $2",
+        id_to_db(guild),
+        id_to_db(user)
+    )
+    .fetch_all(conn.as_mut())
+    .await?
+    .into_iter()
+    .map(|row| RewardGrant {
+        guild,
+        user,
+        role: db_to_id(row.role),
+        expired: row.expired,
+        removed: row.removed,
+    })
+    .collect();
+    Ok(grants)
+}
+
+/// Record that a time-limited reward was given. An existing grant keeps its original expiry.
+pub async fn add_reward_grant<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+    user: Id<UserMarker>,
+    role: Id<RoleMarker>,
+    duration_hours: i64,
+) -> Result<(), Error> {
+    let mut conn = conn.acquire().await?;
+    let hours: i32 = duration_hours.try_into().unwrap_or(i32::MAX);
+    query!(
+        "INSERT INTO reward_grants (guild, id, role, expires_at) \
+        VALUES ($1, $2, $3, NOW() + make_interval(hours => $4)) \
+        ON CONFLICT (guild, id, role) DO NOTHING",
+        id_to_db(guild),
+        id_to_db(user),
+        id_to_db(role),
+        hours
+    )
+    .execute(conn.as_mut())
+    .await?;
+    Ok(())
+}
+
+/// Returns number of rows affected.
+pub async fn delete_reward_grant<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+    user: Id<UserMarker>,
+    role: Id<RoleMarker>,
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!(
+        "DELETE FROM reward_grants WHERE guild = $1 AND id = $2 AND role = $3",
+        id_to_db(guild),
+        id_to_db(user),
+        id_to_db(role)
+    )
+    .execute(conn.as_mut())
+    .await?
+    .rows_affected();
+    Ok(rows)
+}
+
+/// Grants which have run out, but whose role hasn't been taken away yet
+pub async fn due_reward_grants<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+) -> Result<Vec<RewardGrant>, Error> {
+    let mut conn = conn.acquire().await?;
+    let grants = query!(
+        "SELECT guild, id, role FROM reward_grants \
+        WHERE expires_at <= NOW() AND NOT removed"
+    )
+    .fetch_all(conn.as_mut())
+    .await?
+    .into_iter()
+    .map(|row| RewardGrant {
+        guild: db_to_id(row.guild),
+        user: db_to_id(row.id),
+        role: db_to_id(row.role),
+        expired: true,
+        removed: false,
+    })
+    .collect();
+    Ok(grants)
+}
+
+pub async fn mark_reward_grant_removed<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+    user: Id<UserMarker>,
+    role: Id<RoleMarker>,
+) -> Result<(), Error> {
+    let mut conn = conn.acquire().await?;
+    query!(
+        "UPDATE reward_grants SET removed = true \
+        WHERE guild = $1 AND id = $2 AND role = $3",
+        id_to_db(guild),
+        id_to_db(user),
+        id_to_db(role)
+    )
+    .execute(conn.as_mut())
+    .await?;
+    Ok(())
+}
+
+/// Returns number of rows affected.
+pub async fn delete_reward_grants_guild<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!(
+        "DELETE FROM reward_grants WHERE guild = $1",
+        id_to_db(guild)
+    )
+    .execute(conn.as_mut())
+    .await?
+    .rows_affected();
+    Ok(rows)
+}
+
+/// Returns number of rows affected.
+pub async fn delete_reward_grants_user_guild<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+    user: Id<UserMarker>,
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!(
+        "DELETE FROM reward_grants WHERE guild = $1 AND id = $2",
+        id_to_db(guild),
+        id_to_db(user)
+    )
+    .execute(conn.as_mut())
+    .await?
+    .rows_affected();
+    Ok(rows)
+}
+
+/// Every time-limited reward given to this user, in any guild
+pub async fn reward_grants_for_user<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    user: Id<UserMarker>,
+) -> Result<Vec<RewardGrant>, Error> {
+    let mut conn = conn.acquire().await?;
+    let grants = query!(
+        "SELECT guild, role, expires_at <= NOW() as \"expired!\", removed \
+        FROM reward_grants WHERE id = $1 ORDER BY guild, role",
+        id_to_db(user)
+    )
+    .fetch_all(conn.as_mut())
+    .await?
+    .into_iter()
+    .map(|row| RewardGrant {
+        guild: db_to_id(row.guild),
+        user,
+        role: db_to_id(row.role),
+        expired: row.expired,
+        removed: row.removed,
+    })
+    .collect();
+    Ok(grants)
+}
+
+/// Returns number of rows affected.
+pub async fn delete_reward_grants_user<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    user: Id<UserMarker>,
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!("DELETE FROM reward_grants WHERE id = $1", id_to_db(user))
+        .execute(conn.as_mut())
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
+pub async fn record_role_update_failure<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+    user: Id<UserMarker>,
+    reason: &str,
+) -> Result<(), Error> {
+    let mut conn = conn.acquire().await?;
+    query!(
+        "INSERT INTO role_update_failures (guild, id, reason) VALUES ($1, $2, $3) \
+        ON CONFLICT (guild, id) DO UPDATE SET reason = $3, failed_at = NOW()",
+        id_to_db(guild),
+        id_to_db(user),
+        reason
+    )
+    .execute(conn.as_mut())
+    .await?;
+    Ok(())
+}
+
+/// Returns number of rows affected.
+pub async fn clear_role_update_failure<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+    user: Id<UserMarker>,
 ) -> Result<u64, Error> {
     let mut conn = conn.acquire().await?;
-    if requirement.is_none() && role.is_none() {
-        return Err(Error::UnspecifiedDelete);
+    let rows = query!(
+        "DELETE FROM role_update_failures WHERE guild = $1 AND id = $2",
+        id_to_db(guild),
+        id_to_db(user)
+    )
+    .execute(conn.as_mut())
+    .await?
+    .rows_affected();
+    Ok(rows)
+}
+
+/// Every member of the guild whose roles we currently can't update, newest first
+pub async fn guild_role_update_failures<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+) -> Result<Vec<RoleUpdateFailure>, Error> {
+    let mut conn = conn.acquire().await?;
+    let failures = query!(
+        "SELECT id, reason, EXTRACT(EPOCH FROM failed_at)::INT8 as \"failed_at!\" \
+        FROM role_update_failures WHERE guild = $1 ORDER BY failed_at DESC",
+        id_to_db(guild)
+    )
+    .fetch_all(conn.as_mut())
+    .await?
+    .into_iter()
+    .map(|row| RoleUpdateFailure {
+        guild,
+        user: db_to_id(row.id),
+        reason: row.reason,
+        failed_at: row.failed_at,
+    })
+    .collect();
+    Ok(failures)
+}
+
+pub async fn user_role_update_failures<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    user: Id<UserMarker>,
+) -> Result<Vec<RoleUpdateFailure>, Error> {
+    let mut conn = conn.acquire().await?;
+    let failures = query!(
+        "SELECT guild, reason, EXTRACT(EPOCH FROM failed_at)::INT8 as \"failed_at!\" \
+        FROM role_update_failures WHERE id = $1 ORDER BY guild",
+        id_to_db(user)
+    )
+    .fetch_all(conn.as_mut())
+    .await?
+    .into_iter()
+    .map(|row| RoleUpdateFailure {
+        guild: db_to_id(row.guild),
+        user,
+        reason: row.reason,
+        failed_at: row.failed_at,
+    })
+    .collect();
+    Ok(failures)
+}
+
+/// Returns number of rows affected.
+pub async fn delete_role_update_failures_guild<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!(
+        "DELETE FROM role_update_failures WHERE guild = $1",
+        id_to_db(guild)
+    )
+    .execute(conn.as_mut())
+    .await?
+    .rows_affected();
+    Ok(rows)
+}
+
+/// Returns number of rows affected.
+pub async fn delete_role_update_failures_user<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    user: Id<UserMarker>,
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!(
+        "DELETE FROM role_update_failures WHERE id = $1",
+        id_to_db(user)
+    )
+    .execute(conn.as_mut())
+    .await?
+    .rows_affected();
+    Ok(rows)
+}
+
+pub async fn user_settings<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    user: Id<UserMarker>,
+) -> Result<UserSettings, Error> {
+    let mut conn = conn.acquire().await?;
+    let settings = query_as!(
+        UserSettings,
+        "SELECT level_up_dms, xp_opt_out, hide_from_leaderboard, hide_from_rank \
+            FROM user_settings WHERE id = $1",
+        id_to_db(user)
+    )
+    .fetch_optional(conn.as_mut())
+    .await?
+    .unwrap_or_default();
+    Ok(settings)
+}
+
+pub async fn update_user_settings<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    user: Id<UserMarker>,
+    settings: UpdateUserSettings,
+) -> Result<UserSettings, Error> {
+    let mut conn = conn.acquire().await?;
+    let settings = query_as!(
+        UserSettings,
+        "INSERT INTO user_settings \
+            (id, level_up_dms, xp_opt_out, hide_from_leaderboard, hide_from_rank) \
+            VALUES ($1, COALESCE($2, TRUE), COALESCE($3, FALSE), COALESCE($4, FALSE), \
+                COALESCE($5, FALSE)) \
+            ON CONFLICT (id) DO UPDATE SET \
+            level_up_dms = COALESCE($2, user_settings.level_up_dms), \
+            xp_opt_out = COALESCE($3, user_settings.xp_opt_out), \
+            hide_from_leaderboard = COALESCE($4, user_settings.hide_from_leaderboard), \
+            hide_from_rank = COALESCE($5, user_settings.hide_from_rank) \
+            RETURNING level_up_dms, xp_opt_out, hide_from_leaderboard, hide_from_rank",
+        id_to_db(user),
+        settings.level_up_dms,
+        settings.xp_opt_out,
+        settings.hide_from_leaderboard,
+        settings.hide_from_rank
+    )
+    .fetch_one(conn.as_mut())
+    .await?;
+    Ok(settings)
+}
+
+/// Whether this user has stopped earning XP, either everywhere or in this guild.
+pub async fn xp_opted_out<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    user: Id<UserMarker>,
+    guild: Id<GuildMarker>,
+) -> Result<bool, Error> {
+    let mut conn = conn.acquire().await?;
+    let opted_out = query!(
+        "SELECT EXISTS(SELECT 1 FROM user_settings WHERE id = $1 AND xp_opt_out) \
+            OR EXISTS(SELECT 1 FROM user_guild_opt_outs WHERE id = $1 AND guild = $2) \
+            AS \"opted_out!\"",
+        id_to_db(user),
+        id_to_db(guild)
+    )
+    .fetch_one(conn.as_mut())
+    .await?
+    .opted_out;
+    Ok(opted_out)
+}
+
+/// Returns the guilds this user has stopped earning XP in, not counting a global opt-out.
+pub async fn user_guild_opt_outs<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    user: Id<UserMarker>,
+) -> Result<Vec<Id<GuildMarker>>, Error> {
+    let mut conn = conn.acquire().await?;
+    let guilds = query!(
+        "SELECT guild FROM user_guild_opt_outs WHERE id = $1 ORDER BY guild",
+        id_to_db(user)
+    )
+    .fetch_all(conn.as_mut())
+    .await?
+    .into_iter()
+    .map(|row| db_to_id(row.guild))
+    .collect();
+    Ok(guilds)
+}
+
+/// Stop (or resume) earning XP in one guild.
+pub async fn set_guild_opt_out<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    user: Id<UserMarker>,
+    guild: Id<GuildMarker>,
+    opted_out: bool,
+) -> Result<(), Error> {
+    let mut conn = conn.acquire().await?;
+    if opted_out {
+        query!(
+            "INSERT INTO user_guild_opt_outs (id, guild) VALUES ($1, $2) \
+                ON CONFLICT (id, guild) DO NOTHING",
+            id_to_db(user),
+            id_to_db(guild)
+        )
+        .execute(conn.as_mut())
+        .await?;
+    } else {
+        query!(
+            "DELETE FROM user_guild_opt_outs WHERE id = $1 AND guild = $2",
+            id_to_db(user),
+            id_to_db(guild)
+        )
+        .execute(conn.as_mut())
+        .await?;
     }
+    Ok(())
+}
+
+/// Returns number of rows affected.
+pub async fn delete_user_guild_opt_outs<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    user: Id<UserMarker>,
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
     let rows = query!(
-        "DELETE FROM role_rewards WHERE guild = $1 AND (id = $2 OR requirement = $3)",
-        id_to_db(guild),
-        role.map(id_to_db),
-        requirement
+        "DELETE FROM user_guild_opt_outs WHERE id = $1",
+        id_to_db(user)
     )
     .execute(conn.as_mut())
     .await?
@@ -915,6 +2073,23 @@ pub async fn delete_reward_role<
     Ok(rows)
 }
 
+/// Returns number of rows affected.
+pub async fn delete_user_settings<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    user: Id<UserMarker>,
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!("DELETE FROM user_settings WHERE id = $1", id_to_db(user))
+        .execute(conn.as_mut())
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
 pub async fn export_bulk_users<
     'a,
     D: DerefMut<Target = PgConnection> + Send,
@@ -941,16 +2116,299 @@ pub async fn export_bulk_users<
     Ok(out)
 }
 
+pub async fn set_leaderboard_schedule<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    schedule: LeaderboardSchedule,
+) -> Result<(), Error> {
+    let mut conn = conn.acquire().await?;
+    query!(
+        "INSERT INTO leaderboard_schedules \
+            (guild, channel, period, winner_card, mention_winners) \
+            VALUES ($1, $2, $3, $4, $5) \
+            ON CONFLICT (guild) DO UPDATE SET \
+            channel = excluded.channel, period = excluded.period, \
+            winner_card = excluded.winner_card, mention_winners = excluded.mention_winners",
+        id_to_db(schedule.guild),
+        id_to_db(schedule.channel),
+        schedule.period.to_i16(),
+        schedule.winner_card,
+        schedule.mention_winners
+    )
+    .execute(conn.as_mut())
+    .await?;
+    Ok(())
+}
+
+pub async fn leaderboard_schedule<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+) -> Result<Option<LeaderboardSchedule>, Error> {
+    let mut conn = conn.acquire().await?;
+    let schedule = query!(
+        "SELECT channel, period, winner_card, mention_winners \
+            FROM leaderboard_schedules WHERE guild = $1",
+        id_to_db(guild)
+    )
+    .fetch_optional(conn.as_mut())
+    .await?
+    .map(|row| {
+        Ok::<_, Error>(LeaderboardSchedule {
+            guild,
+            channel: db_to_id(row.channel),
+            period: LeaderboardSchedulePeriod::from_i16(row.period)
+                .ok_or(Error::UnknownLeaderboardSchedulePeriod)?,
+            winner_card: row.winner_card,
+            mention_winners: row.mention_winners,
+        })
+    })
+    .transpose()?;
+    Ok(schedule)
+}
+
+/// Returns whether a schedule existed to be deleted.
+pub async fn delete_leaderboard_schedule<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+) -> Result<bool, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!(
+        "DELETE FROM leaderboard_schedules WHERE guild = $1",
+        id_to_db(guild)
+    )
+    .execute(conn.as_mut())
+    .await?
+    .rows_affected();
+    Ok(rows > 0)
+}
+
+/// Create or replace the job of this kind for this guild. The first run happens one interval from now.
+///
+/// Replacing a job with the same interval keeps its next run, so editing a job doesn't skip or
+/// repeat a run.
+pub async fn set_scheduled_job<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    kind: ScheduledJobKind,
+    guild: Option<Id<GuildMarker>>,
+    interval: JobInterval,
+) -> Result<(), Error> {
+    let mut conn = conn.acquire().await?;
+    query!(
+        "INSERT INTO scheduled_jobs (kind, guild, next_run, interval_secs, interval_months) \
+            VALUES ($1, $2, NOW() + $3::INT8 * interval '1 second' + $4::INT4 * interval '1 month', $3, $4) \
+            ON CONFLICT (kind, COALESCE(guild, 0)) DO UPDATE SET \
+            next_run = CASE WHEN scheduled_jobs.interval_secs = excluded.interval_secs \
+                AND scheduled_jobs.interval_months = excluded.interval_months \
+                THEN scheduled_jobs.next_run ELSE excluded.next_run END, \
+            interval_secs = excluded.interval_secs, interval_months = excluded.interval_months",
+        kind.to_i16(),
+        guild.map(id_to_db),
+        interval.seconds(),
+        interval.months()
+    )
+    .execute(conn.as_mut())
+    .await?;
+    Ok(())
+}
+
+/// Create the job of this kind for this guild if it doesn't exist yet. If it does exist, only its
+/// interval is updated, so restarting doesn't push back the next run.
+pub async fn ensure_scheduled_job<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    kind: ScheduledJobKind,
+    guild: Option<Id<GuildMarker>>,
+    interval: JobInterval,
+) -> Result<(), Error> {
+    let mut conn = conn.acquire().await?;
+    query!(
+        "INSERT INTO scheduled_jobs (kind, guild, next_run, interval_secs, interval_months) \
+            VALUES ($1, $2, NOW() + $3::INT8 * interval '1 second' + $4::INT4 * interval '1 month', $3, $4) \
+            ON CONFLICT (kind, COALESCE(guild, 0)) DO UPDATE SET \
+            interval_secs = excluded.interval_secs, interval_months = excluded.interval_months",
+        kind.to_i16(),
+        guild.map(id_to_db),
+        interval.seconds(),
+        interval.months()
+    )
+    .execute(conn.as_mut())
+    .await?;
+    Ok(())
+}
+
+/// Returns whether a job existed to be deleted.
+pub async fn delete_scheduled_job<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    kind: ScheduledJobKind,
+    guild: Option<Id<GuildMarker>>,
+) -> Result<bool, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!(
+        "DELETE FROM scheduled_jobs WHERE kind = $1 AND guild IS NOT DISTINCT FROM $2",
+        kind.to_i16(),
+        guild.map(id_to_db)
+    )
+    .execute(conn.as_mut())
+    .await?
+    .rows_affected();
+    Ok(rows > 0)
+}
+
+pub async fn delete_guild_scheduled_jobs<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    guild: Id<GuildMarker>,
+) -> Result<u64, Error> {
+    let mut conn = conn.acquire().await?;
+    let rows = query!(
+        "DELETE FROM scheduled_jobs WHERE guild = $1",
+        id_to_db(guild)
+    )
+    .execute(conn.as_mut())
+    .await?
+    .rows_affected();
+    Ok(rows)
+}
+
+/// Get the IDs of all jobs which should have run by now, oldest first.
+/// These must be claimed with [`claim_scheduled_job`] before they are run.
+pub async fn due_scheduled_jobs<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+) -> Result<Vec<i64>, Error> {
+    let mut conn = conn.acquire().await?;
+    let ids = query!("SELECT id FROM scheduled_jobs WHERE next_run <= NOW() ORDER BY next_run")
+        .fetch_all(conn.as_mut())
+        .await?
+        .into_iter()
+        .map(|row| row.id)
+        .collect();
+    Ok(ids)
+}
+
+/// Take ownership of a due job, moving its next run forward by one interval.
+///
+/// Call this while holding an advisory lock on the job's ID with [`try_advisory_lock`], and
+/// keep holding it while the job runs, so no other instance can run it at the same time.
+/// Returns [`None`] if the job has already been run or deleted. If the bot was down for longer
+/// than a whole interval, missed runs are skipped rather than all run at once.
+pub async fn claim_scheduled_job<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    id: i64,
+) -> Result<Option<ScheduledJob>, Error> {
+    let mut conn = conn.acquire().await?;
+    let Some(row) = query!(
+        "UPDATE scheduled_jobs SET next_run = GREATEST(next_run, NOW()) \
+            + interval_secs * interval '1 second' + interval_months * interval '1 month' \
+            WHERE id = $1 AND next_run <= NOW() RETURNING kind, guild",
+        id
+    )
+    .fetch_optional(conn.as_mut())
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let kind = ScheduledJobKind::from_i16(row.kind).ok_or(Error::UnknownScheduledJobKind)?;
+    Ok(Some(ScheduledJob {
+        id,
+        kind,
+        guild: row.guild.map(db_to_id),
+    }))
+}
+
+/// Try to take a session-level advisory lock. This is held until [`advisory_unlock`] is called
+/// on the same connection, or it closes.
+pub async fn try_advisory_lock<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    key: i64,
+) -> Result<bool, Error> {
+    let mut conn = conn.acquire().await?;
+    let locked = query!(r#"SELECT pg_try_advisory_lock($1) AS "locked!""#, key)
+        .fetch_one(conn.as_mut())
+        .await?
+        .locked;
+    Ok(locked)
+}
+
+/// Returns whether the lock was held by this connection.
+pub async fn advisory_unlock<
+    'a,
+    D: DerefMut<Target = PgConnection> + Send,
+    A: Acquire<'a, Database = Postgres, Connection = D> + Send,
+>(
+    conn: A,
+    key: i64,
+) -> Result<bool, Error> {
+    let mut conn = conn.acquire().await?;
+    let unlocked = query!(r#"SELECT pg_advisory_unlock($1) AS "unlocked!""#, key)
+        .fetch_one(conn.as_mut())
+        .await?
+        .unlocked;
+    Ok(unlocked)
+}
+
+/// Settings left as `None` are kept. Settings which can be unset take `Some(None)` to unset them.
 #[derive(Default)]
+#[allow(clippy::option_option)]
 pub struct UpdateGuildConfig {
-    pub level_up_message: Option<String>,
-    pub level_up_channel: Option<Id<ChannelMarker>>,
+    pub level_up_message: Option<Option<String>>,
+    pub level_up_channel: Option<Option<Id<ChannelMarker>>>,
     pub ping_users: Option<bool>,
     pub max_xp_per_message: Option<i16>,
     pub min_xp_per_message: Option<i16>,
     pub message_cooldown: Option<i16>,
     pub one_at_a_time: Option<bool>,
     pub guild_card_default_show_off: Option<bool>,
+    pub audit_log_retention_days: Option<Option<i32>>,
+    pub prune_compliance_audit_logs: Option<Option<bool>>,
+    pub mod_log_channel: Option<Option<Id<ChannelMarker>>>,
+    pub kick_ban_xp_policy: Option<KickBanXpPolicy>,
+    pub leave_policy: Option<LeavePolicy>,
+    pub leave_delete_after_days: Option<i32>,
+    pub level_up_embed: Option<bool>,
+    pub level_up_embed_title: Option<Option<String>>,
+    pub level_up_embed_description: Option<Option<String>>,
+    pub level_up_embed_color: Option<Option<u32>>,
+    pub level_up_embed_footer: Option<Option<String>>,
+    pub level_up_rank_card: Option<bool>,
+    pub level_up_delivery: Option<LevelUpDelivery>,
 }
 
 macro_rules! setter {
@@ -964,12 +2422,22 @@ macro_rules! setter {
             self
         }
     };
+    (clearable $name:ident, $kind:ty) => {
+        #[must_use]
+        #[allow(clippy::missing_const_for_fn, clippy::option_option)]
+        pub fn $name(mut self, p1: Option<Option<$kind>>) -> Self {
+            if p1.is_some() {
+                self.$name = p1;
+            }
+            self
+        }
+    };
 }
 
 impl UpdateGuildConfig {
-    setter!(level_up_message, String);
+    setter!(clearable level_up_message, String);
 
-    setter!(level_up_channel, Id<ChannelMarker>);
+    setter!(clearable level_up_channel, Id<ChannelMarker>);
 
     setter!(ping_users, bool);
 
@@ -983,6 +2451,55 @@ impl UpdateGuildConfig {
 
     setter!(guild_card_default_show_off, bool);
 
+    setter!(clearable audit_log_retention_days, i32);
+
+    setter!(clearable prune_compliance_audit_logs, bool);
+
+    setter!(clearable mod_log_channel, Id<ChannelMarker>);
+
+    setter!(kick_ban_xp_policy, KickBanXpPolicy);
+
+    setter!(leave_policy, LeavePolicy);
+
+    setter!(leave_delete_after_days, i32);
+
+    setter!(level_up_embed, bool);
+
+    setter!(clearable level_up_embed_title, String);
+
+    setter!(clearable level_up_embed_description, String);
+
+    setter!(clearable level_up_embed_color, u32);
+
+    setter!(clearable level_up_embed_footer, String);
+
+    setter!(level_up_rank_card, bool);
+
+    setter!(level_up_delivery, LevelUpDelivery);
+
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[derive(Default)]
+pub struct UpdateUserSettings {
+    pub level_up_dms: Option<bool>,
+    pub xp_opt_out: Option<bool>,
+    pub hide_from_leaderboard: Option<bool>,
+    pub hide_from_rank: Option<bool>,
+}
+
+impl UpdateUserSettings {
+    setter!(level_up_dms, bool);
+
+    setter!(xp_opt_out, bool);
+
+    setter!(hide_from_leaderboard, bool);
+
+    setter!(hide_from_rank, bool);
+
     #[must_use]
     pub fn new() -> Self {
         Self::default()
@@ -1049,36 +2566,77 @@ pub struct RawGuildConfig {
     pub max_xp_per_message: Option<i16>,
     pub message_cooldown: Option<i16>,
     pub guild_card_default_show_off: bool,
+    pub audit_log_retention_days: Option<i32>,
+    pub prune_compliance_audit_logs: Option<bool>,
+    pub mod_log_channel: Option<i64>,
+    pub kick_ban_xp_policy: Option<i16>,
+    pub leave_policy: Option<i16>,
+    pub leave_delete_after_days: Option<i32>,
+    pub level_up_embed: Option<bool>,
+    pub level_up_embed_title: Option<String>,
+    pub level_up_embed_description: Option<String>,
+    pub level_up_embed_color: Option<i32>,
+    pub level_up_embed_footer: Option<String>,
+    pub level_up_rank_card: Option<bool>,
+    pub level_up_delivery: Option<i16>,
 }
 
 impl RawGuildConfig {
-    fn cook(self) -> Result<GuildConfig, simpleinterpolation::ParseError> {
-        let level_up_message = if let Some(str) = self.level_up_message {
-            Some(Interpolation::new(str)?)
-        } else {
-            None
-        };
-
+    fn cook(self) -> Result<GuildConfig, Error> {
         let gc = GuildConfig {
             one_at_a_time: self.one_at_a_time,
-            level_up_message,
+            level_up_message: parse_template(self.level_up_message)?,
             level_up_channel: self.level_up_channel.map(db_to_id),
             ping_on_level_up: self.ping_on_level_up,
             min_xp_per_message: self.min_xp_per_message,
             max_xp_per_message: self.max_xp_per_message,
             cooldown: self.message_cooldown,
             guild_card_default_show_off: self.guild_card_default_show_off,
+            audit_log_retention_days: self.audit_log_retention_days,
+            prune_compliance_audit_logs: self.prune_compliance_audit_logs,
+            mod_log_channel: self.mod_log_channel.map(db_to_id),
+            kick_ban_xp_policy: self
+                .kick_ban_xp_policy
+                .map(|v| KickBanXpPolicy::from_i16(v).ok_or(Error::UnknownKickBanXpPolicy))
+                .transpose()?,
+            leave_policy: self
+                .leave_policy
+                .map(|v| LeavePolicy::from_i16(v).ok_or(Error::UnknownLeavePolicy))
+                .transpose()?,
+            leave_delete_after_days: self.leave_delete_after_days,
+            level_up_embed: self.level_up_embed,
+            level_up_embed_title: parse_template(self.level_up_embed_title)?,
+            level_up_embed_description: parse_template(self.level_up_embed_description)?,
+            level_up_embed_color: self.level_up_embed_color.map(|v| v.reinterpret_bits()),
+            level_up_embed_footer: parse_template(self.level_up_embed_footer)?,
+            level_up_rank_card: self.level_up_rank_card,
+            level_up_delivery: self
+                .level_up_delivery
+                .map(|v| LevelUpDelivery::from_i16(v).ok_or(Error::UnknownLevelUpDelivery))
+                .transpose()?,
         };
         Ok(gc)
     }
 }
 
+fn parse_template(template: Option<String>) -> Result<Option<Interpolation>, Error> {
+    template
+        .map(Interpolation::new)
+        .transpose()
+        .map_err(Into::into)
+}
+
 #[derive(Debug)]
 pub enum Error {
     Database(sqlx::Error),
     Interpolation(simpleinterpolation::ParseError),
     UnspecifiedDelete,
     UnknownAuditLogEventKind,
+    UnknownLeaderboardSchedulePeriod,
+    UnknownScheduledJobKind,
+    UnknownKickBanXpPolicy,
+    UnknownLeavePolicy,
+    UnknownLevelUpDelivery,
 }
 
 impl Display for Error {
@@ -1088,6 +2646,13 @@ impl Display for Error {
             Self::Interpolation(ie) => write!(f, "{ie}"),
             Self::UnspecifiedDelete => f.write_str("No constraints specified to delete by."),
             Self::UnknownAuditLogEventKind => f.write_str("Unknown audit log event kind"),
+            Self::UnknownLeaderboardSchedulePeriod => {
+                f.write_str("Unknown leaderboard schedule period")
+            }
+            Self::UnknownScheduledJobKind => f.write_str("Unknown scheduled job kind"),
+            Self::UnknownKickBanXpPolicy => f.write_str("Unknown kick/ban XP policy"),
+            Self::UnknownLeavePolicy => f.write_str("Unknown leave policy"),
+            Self::UnknownLevelUpDelivery => f.write_str("Unknown level-up delivery mode"),
         }
     }
 }
@@ -1,6 +1,11 @@
+use std::time::Duration;
+
 use sqlx::PgPool;
 use twilight_model::id::Id;
-use xpd_common::AuditLogEventKind;
+use xpd_common::{
+    AuditLogChange, AuditLogEntry, AuditLogEventKind, JobInterval, LeavePolicy, ScheduledJobKind,
+    UserInGuild,
+};
 
 use crate::*;
 
@@ -29,26 +34,112 @@ async fn find_deletes_returns_correctly(db: PgPool) -> Result<(), Box<dyn std::e
     .execute(&db)
     .await?;
     delete_guild_cleanup(&db, Id::new(3)).await?;
-    let cleanups = get_active_guild_cleanups(&db).await?;
+    let cleanups = get_active_guild_cleanups(&db, Duration::from_hours(30 * 24)).await?;
     assert!(cleanups.contains(&Id::new(1)));
     assert!(!cleanups.contains(&Id::new(2)));
     Ok(())
 }
 
+#[sqlx::test(migrations = "../migrations/")]
+async fn leave_policies(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    // Guild 1 deletes after 10 days, guild 2 hides absent members, guild 3 keeps everything
+    update_guild_config(
+        &db,
+        Id::new(1),
+        UpdateGuildConfig::new()
+            .leave_policy(Some(LeavePolicy::DeleteAfter))
+            .leave_delete_after_days(Some(10)),
+    )
+    .await?;
+    update_guild_config(
+        &db,
+        Id::new(2),
+        UpdateGuildConfig::new().leave_policy(Some(LeavePolicy::Hide)),
+    )
+    .await?;
+    for guild in 1..=3 {
+        add_xp(&db, Id::new(10), db_to_id(guild), 100).await?;
+        add_xp(&db, Id::new(11), db_to_id(guild), 50).await?;
+        query!(
+            "INSERT INTO user_cleanups (guild_id, user_id, removed_at) \
+                VALUES ($1, 10, NOW() - interval '11 days')",
+            guild
+        )
+        .execute(&db)
+        .await?;
+    }
+
+    let cleanups = get_active_user_guild_cleanups(&db, Duration::ZERO).await?;
+    assert_eq!(
+        cleanups,
+        [UserInGuild {
+            guild: Id::new(1),
+            user: Id::new(10)
+        }]
+    );
+    // An operator's minimum retention outlasts the guild's 10 days
+    let cleanups = get_active_user_guild_cleanups(&db, Duration::from_hours(30 * 24)).await?;
+    assert!(cleanups.is_empty());
+
+    let leaderboard = get_guild_leaderboard(&db, Id::new(2)).await?;
+    assert_eq!(leaderboard.len(), 1);
+    assert_eq!(count_with_higher_xp(&db, Id::new(2), 50).await?, Some(0));
+    assert_eq!(count_with_higher_xp(&db, Id::new(3), 50).await?, Some(1));
+
+    // Coming back makes them visible again
+    delete_user_guild_cleanup(&db, Id::new(2), Id::new(10)).await?;
+    assert_eq!(get_guild_leaderboard(&db, Id::new(2)).await?.len(), 2);
+    Ok(())
+}
+
 #[sqlx::test(migrations = "../migrations/")]
 async fn audit_log_refetch(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
     let original_event = AuditLogEvent {
         guild: Id::new(1),
-        target: Id::new(2),
+        target: Some(Id::new(2)),
         moderator: Id::new(3),
         timestamp: 50,
         previous: 100,
         delta: -100,
         kind: AuditLogEventKind::Reset,
+        details: None,
+    };
+    let id = add_audit_log_event(&db, original_event.clone()).await?;
+    let roundtripped_event = get_audit_log_events(&db, Id::new(1), None, None).await?;
+    assert_eq!(
+        roundtripped_event,
+        &[AuditLogEntry {
+            id,
+            event: original_event
+        }]
+    );
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn audit_log_details_refetch(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let original_event = AuditLogEvent {
+        guild: Id::new(1),
+        target: None,
+        moderator: Id::new(3),
+        timestamp: 50,
+        previous: 0,
+        delta: 0,
+        kind: AuditLogEventKind::RewardAdd,
+        details: Some(AuditLogChange {
+            before: serde_json::Value::Null,
+            after: serde_json::json!({ "id": "4", "requirement": 5 }),
+        }),
     };
-    add_audit_log_event(&db, original_event).await?;
+    add_audit_log_event(&db, original_event.clone()).await?;
     let roundtripped_event = get_audit_log_events(&db, Id::new(1), None, None).await?;
-    assert_eq!(roundtripped_event, &[original_event]);
+    assert_eq!(roundtripped_event.events_by_timestamp(), &[original_event]);
+    // Guild-wide events aren't about any particular user
+    assert!(
+        get_audit_log_events(&db, Id::new(1), Some(Id::new(3)), None)
+            .await?
+            .is_empty()
+    );
     Ok(())
 }
 
@@ -57,30 +148,32 @@ async fn audit_log_multi(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
     let original_events = vec![
         AuditLogEvent {
             guild: Id::new(1),
-            target: Id::new(2),
+            target: Some(Id::new(2)),
             moderator: Id::new(3),
             timestamp: 50,
             previous: 100,
             delta: -100,
             kind: AuditLogEventKind::Reset,
+            details: None,
         },
         AuditLogEvent {
             guild: Id::new(1),
-            target: Id::new(4),
+            target: Some(Id::new(4)),
             moderator: Id::new(5),
             timestamp: 591,
             previous: 15,
             delta: 50,
             kind: AuditLogEventKind::Set,
+            details: None,
         },
     ];
     for event in &original_events {
-        add_audit_log_event(&db, *event).await?;
+        add_audit_log_event(&db, event.clone()).await?;
     }
     let roundtripped_events = get_audit_log_events(&db, Id::new(1), None, None).await?;
 
     assert_eq!(
-        roundtripped_events.sorted_by_timestamp(),
+        roundtripped_events.events_by_timestamp(),
         original_events.sorted_by_timestamp()
     );
     Ok(())
@@ -91,43 +184,47 @@ async fn audit_logs_deleted_guild(db: PgPool) -> Result<(), Box<dyn std::error::
     let original_events = vec![
         AuditLogEvent {
             guild: Id::new(5),
-            target: Id::new(4),
+            target: Some(Id::new(4)),
             moderator: Id::new(15),
             timestamp: 0,
             previous: 100,
             delta: -100,
             kind: AuditLogEventKind::AddOrSub,
+            details: None,
         },
         AuditLogEvent {
             guild: Id::new(1),
-            target: Id::new(2),
+            target: Some(Id::new(2)),
             moderator: Id::new(3),
             timestamp: 50,
             previous: 100,
             delta: -100,
             kind: AuditLogEventKind::AddOrSub,
+            details: None,
         },
         AuditLogEvent {
             guild: Id::new(1),
-            target: Id::new(4),
+            target: Some(Id::new(4)),
             moderator: Id::new(5),
             timestamp: 591,
             previous: 15,
             delta: 50,
             kind: AuditLogEventKind::AddOrSub,
+            details: None,
         },
         AuditLogEvent {
             guild: Id::new(2),
-            target: Id::new(4),
+            target: Some(Id::new(4)),
             moderator: Id::new(5),
             timestamp: 595,
             previous: 100,
             delta: 50,
             kind: AuditLogEventKind::Set,
+            details: None,
         },
     ];
     for event in &original_events {
-        add_audit_log_event(&db, *event).await?;
+        add_audit_log_event(&db, event.clone()).await?;
     }
     delete_audit_log_events_guild(&db, Id::new(1)).await?;
 
@@ -136,19 +233,434 @@ async fn audit_logs_deleted_guild(db: PgPool) -> Result<(), Box<dyn std::error::
 
     assert!(should_be_nonexistent.is_empty());
     assert_eq!(
-        roundtripped_events.sorted_by_timestamp(),
-        vec![original_events[3]]
+        roundtripped_events.events_by_timestamp(),
+        vec![original_events[3].clone()]
     );
     Ok(())
 }
 
+#[sqlx::test(migrations = "../migrations/")]
+async fn audit_logs_pruned(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    const DAY: i64 = 24 * 60 * 60;
+    let now = i64::try_from(std::time::UNIX_EPOCH.elapsed()?.as_secs())? - DISCORD_EPOCH_SECS;
+    update_guild_config(
+        &db,
+        Id::new(1),
+        UpdateGuildConfig::new().audit_log_retention_days(Some(Some(10))),
+    )
+    .await?;
+
+    let event = |guild, days_ago, kind| AuditLogEvent {
+        guild: Id::new(guild),
+        target: Some(Id::new(2)),
+        moderator: Id::new(3),
+        timestamp: now - days_ago * DAY,
+        previous: 100,
+        delta: -100,
+        kind,
+        details: None,
+    };
+    let events = [
+        // Older than the guild's 10 day retention
+        event(1, 20, AuditLogEventKind::AddOrSub),
+        // Compliance events are kept
+        event(1, 20, AuditLogEventKind::GdprDelete),
+        event(1, 5, AuditLogEventKind::Set),
+        // Within the default retention
+        event(2, 20, AuditLogEventKind::AddOrSub),
+        // Older than the default retention
+        event(2, 400, AuditLogEventKind::Reset),
+    ];
+    for event in &events {
+        add_audit_log_event(&db, event.clone()).await?;
+    }
+
+    assert_eq!(prune_audit_log_events(&db).await?, 2);
+    assert_eq!(
+        get_audit_log_events(&db, Id::new(1), None, None)
+            .await?
+            .events_by_timestamp(),
+        vec![events[1].clone(), events[2].clone()]
+    );
+    assert_eq!(
+        get_audit_log_events(&db, Id::new(2), None, None)
+            .await?
+            .events_by_timestamp(),
+        vec![events[3].clone()]
+    );
+
+    update_guild_config(
+        &db,
+        Id::new(1),
+        UpdateGuildConfig::new().prune_compliance_audit_logs(Some(Some(true))),
+    )
+    .await?;
+    assert_eq!(prune_audit_log_events(&db).await?, 1);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn scheduled_job_claimed_once(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let kind = ScheduledJobKind::LeaderboardPost;
+    let guild = Some(Id::new(1));
+    set_scheduled_job(&db, kind, guild, JobInterval::Seconds(60)).await?;
+    assert!(due_scheduled_jobs(&db).await?.is_empty());
+
+    // Pretend the job should have run 5 minutes ago
+    query!("UPDATE scheduled_jobs SET next_run = NOW() - interval '5 minutes'")
+        .execute(&db)
+        .await?;
+    let due = due_scheduled_jobs(&db).await?;
+    assert_eq!(due.len(), 1);
+
+    // While one instance holds the job's lock, the other can't take it
+    let mut first = db.acquire().await?;
+    let mut second = db.acquire().await?;
+    assert!(try_advisory_lock(&mut *first, due[0]).await?);
+    assert!(!try_advisory_lock(&mut *second, due[0]).await?);
+    let claimed = claim_scheduled_job(&mut *first, due[0]).await?;
+    assert_eq!(claimed.map(|v| (v.kind, v.guild)), Some((kind, guild)));
+    assert!(advisory_unlock(&mut *first, due[0]).await?);
+
+    // Once claimed, it isn't due again until the next interval
+    assert!(due_scheduled_jobs(&db).await?.is_empty());
+    assert_eq!(claim_scheduled_job(&db, due[0]).await?, None);
+
+    assert!(delete_scheduled_job(&db, kind, guild).await?);
+    assert!(!delete_scheduled_job(&db, kind, guild).await?);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn scheduled_job_edits_keep_next_run(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let kind = ScheduledJobKind::LeaderboardPost;
+    let guild = Some(Id::new(1));
+    let next_run = || async {
+        query!(r#"SELECT EXTRACT(EPOCH FROM next_run)::INT8 AS "next_run!" FROM scheduled_jobs"#)
+            .fetch_one(&db)
+            .await
+            .map(|row| row.next_run)
+    };
+    set_scheduled_job(&db, kind, guild, JobInterval::Seconds(60)).await?;
+    let first = next_run().await?;
+
+    // Same interval, so the run that's already scheduled still happens
+    set_scheduled_job(&db, kind, guild, JobInterval::Seconds(60)).await?;
+    assert_eq!(next_run().await?, first);
+
+    set_scheduled_job(&db, kind, guild, JobInterval::Months(1)).await?;
+    assert_ne!(next_run().await?, first);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn rewards_share_level(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let guild = Id::new(1);
+    add_reward_role(&db, guild, 10, Id::new(1), None).await?;
+    add_reward_role(&db, guild, 10, Id::new(2), None).await?;
+    add_reward_role(&db, guild, 20, Id::new(3), Some(24)).await?;
+    // Re-adding a role moves it rather than duplicating it
+    add_reward_role(&db, guild, 20, Id::new(2), None).await?;
+
+    let mut rewards = guild_rewards(&db, guild).await?;
+    rewards.sort_by_key(|v| v.id);
+    let pairs: Vec<(u64, i64)> = rewards
+        .iter()
+        .map(|v| (v.id.get(), v.requirement))
+        .collect();
+    assert_eq!(pairs, [(1, 10), (2, 20), (3, 20)]);
+
+    add_reward_grant(&db, guild, Id::new(4), Id::new(1), 24).await?;
+    add_reward_grant(&db, guild, Id::new(4), Id::new(3), 24).await?;
+    assert_eq!(delete_reward_role(&db, guild, Some(20), None).await?, 2);
+    assert_eq!(guild_rewards(&db, guild).await?.len(), 1);
+    // Grants of deleted rewards go with them
+    let grants = user_reward_grants(&db, guild, Id::new(4)).await?;
+    assert_eq!(grants.len(), 1);
+    assert_eq!(grants[0].role, Id::new(1));
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn reward_grants_expire(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let guild = Id::new(1);
+    let user = Id::new(2);
+    add_reward_grant(&db, guild, user, Id::new(3), 24).await?;
+    // Expired an hour ago
+    query!(
+        "INSERT INTO reward_grants (guild, id, role, expires_at) \
+        VALUES (1, 2, 4, NOW() - interval '1 hour')"
+    )
+    .execute(&db)
+    .await?;
+    // Re-granting doesn't push the expiry back
+    add_reward_grant(&db, guild, user, Id::new(4), 24).await?;
+
+    let due = due_reward_grants(&db).await?;
+    assert_eq!(due.len(), 1);
+    assert_eq!(due[0].role, Id::new(4));
+
+    mark_reward_grant_removed(&db, guild, user, Id::new(4)).await?;
+    assert!(due_reward_grants(&db).await?.is_empty());
+
+    let mut grants = user_reward_grants(&db, guild, user).await?;
+    grants.sort_by_key(|v| v.role);
+    let states: Vec<(u64, bool, bool)> = grants
+        .iter()
+        .map(|v| (v.role.get(), v.expired, v.removed))
+        .collect();
+    assert_eq!(states, [(3, false, false), (4, true, true)]);
+
+    assert_eq!(delete_reward_grants_user_guild(&db, guild, user).await?, 2);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn rank_rewards_upsert(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let guild = Id::new(1);
+    add_rank_reward(&db, guild, 10, Id::new(1)).await?;
+    add_rank_reward(&db, guild, 1, Id::new(2)).await?;
+    add_rank_reward(&db, guild, 5, Id::new(1)).await?;
+
+    let mut rewards = guild_rank_rewards(&db, guild).await?;
+    rewards.sort_by_key(|v| v.id);
+    let pairs: Vec<(u64, i64)> = rewards.iter().map(|v| (v.id.get(), v.max_rank)).collect();
+    assert_eq!(pairs, [(1, 5), (2, 1)]);
+    assert!(guild_rank_rewards(&db, Id::new(2)).await?.is_empty());
+
+    assert_eq!(delete_rank_reward(&db, guild, Id::new(1)).await?, 1);
+    assert_eq!(delete_rank_reward(&db, guild, Id::new(1)).await?, 0);
+    assert_eq!(guild_rank_rewards(&db, guild).await?.len(), 1);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn role_update_failures_latest(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let guild = Id::new(1);
+    record_role_update_failure(&db, guild, Id::new(2), "first").await?;
+    record_role_update_failure(&db, guild, Id::new(2), "second").await?;
+    record_role_update_failure(&db, guild, Id::new(3), "other").await?;
+
+    let failures = guild_role_update_failures(&db, guild).await?;
+    let mut reasons: Vec<(u64, &str)> = failures
+        .iter()
+        .map(|v| (v.user.get(), v.reason.as_str()))
+        .collect();
+    reasons.sort_unstable();
+    assert_eq!(reasons, [(2, "second"), (3, "other")]);
+
+    assert_eq!(clear_role_update_failure(&db, guild, Id::new(2)).await?, 1);
+    assert_eq!(clear_role_update_failure(&db, guild, Id::new(2)).await?, 0);
+    assert_eq!(delete_role_update_failures_user(&db, Id::new(3)).await?, 1);
+    assert!(guild_role_update_failures(&db, guild).await?.is_empty());
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn level_up_embed_config(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let guild = Id::new(1);
+    let cfg = UpdateGuildConfig::new()
+        .level_up_embed(Some(true))
+        .level_up_embed_title(Some(Some("{user_nickname} leveled up!".to_string())))
+        .level_up_embed_color(Some(Some(0xFF_88_00)))
+        .mod_log_channel(Some(Some(Id::new(2))));
+    update_guild_config(&db, guild, cfg).await?;
+    // Later updates leave the embed alone
+    let cfg = UpdateGuildConfig::new().level_up_rank_card(Some(true));
+    update_guild_config(&db, guild, cfg).await?;
+
+    let config = guild_config(&db, guild).await?.unwrap();
+    assert_eq!(config.level_up_embed, Some(true));
+    assert_eq!(
+        config.level_up_embed_title.map(|v| v.input_value()),
+        Some("{user_nickname} leveled up!".to_string())
+    );
+    assert_eq!(config.level_up_embed_color, Some(0xFF_88_00));
+    assert!(config.level_up_embed_description.is_none());
+    assert_eq!(config.level_up_rank_card, Some(true));
+
+    // Clearing one setting leaves the rest alone
+    let cfg = UpdateGuildConfig::new()
+        .level_up_embed_title(Some(None))
+        .mod_log_channel(Some(None));
+    let config = update_guild_config(&db, guild, cfg).await?;
+    assert!(config.level_up_embed_title.is_none());
+    assert!(config.mod_log_channel.is_none());
+    assert_eq!(config.level_up_embed_color, Some(0xFF_88_00));
+    assert_eq!(config.level_up_embed, Some(true));
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn level_up_messages_by_guild(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let guild = Id::new(1);
+    let first = add_level_up_message(&db, guild, Some(50), Some(50), "{level}!").await?;
+    let second = add_level_up_message(&db, guild, None, None, "GG").await?;
+    add_level_up_message(&db, Id::new(2), None, None, "elsewhere").await?;
+    // Ranges can't be backwards
+    assert!(
+        add_level_up_message(&db, guild, Some(5), Some(4), "nope")
+            .await
+            .is_err()
+    );
+
+    let messages = guild_level_up_messages(&db, guild).await?;
+    let ids: Vec<i64> = messages.iter().map(|v| v.id).collect();
+    assert_eq!(ids, [first, second]);
+    assert_eq!(messages[0].min_level, Some(50));
+    assert_eq!(messages[1].template.input_value(), "GG");
+
+    assert_eq!(delete_level_up_message(&db, Id::new(2), first).await?, 0);
+    assert_eq!(delete_level_up_message(&db, guild, first).await?, 1);
+    assert_eq!(delete_level_up_messages_guild(&db, guild).await?, 1);
+    assert_eq!(delete_level_up_messages_guild(&db, Id::new(2)).await?, 1);
+    Ok(())
+}
+
 trait SortedByTimestamp {
     fn sorted_by_timestamp(self) -> Self;
 }
 
 impl SortedByTimestamp for Vec<AuditLogEvent> {
+    #[allow(clippy::unnecessary_sort_by)]
     fn sorted_by_timestamp(mut self) -> Self {
         self.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
         self
     }
 }
+
+trait EventsByTimestamp {
+    fn events_by_timestamp(self) -> Vec<AuditLogEvent>;
+}
+
+impl EventsByTimestamp for Vec<AuditLogEntry> {
+    fn events_by_timestamp(self) -> Vec<AuditLogEvent> {
+        self.into_iter()
+            .map(|v| v.event)
+            .collect::<Vec<AuditLogEvent>>()
+            .sorted_by_timestamp()
+    }
+}
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn user_settings_defaults(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let user = Id::new(1);
+    assert!(user_settings(&db, user).await?.level_up_dms);
+
+    let cfg = UpdateUserSettings::new().level_up_dms(Some(false));
+    assert!(!update_user_settings(&db, user, cfg).await?.level_up_dms);
+    // Updates leave other settings alone
+    let cfg = UpdateUserSettings::new().hide_from_rank(Some(true));
+    let settings = update_user_settings(&db, user, cfg).await?;
+    assert!(!settings.level_up_dms);
+    assert!(settings.hide_from_rank);
+    assert!(!settings.hide_from_leaderboard);
+    assert!(user_settings(&db, Id::new(2)).await?.level_up_dms);
+
+    assert_eq!(delete_user_settings(&db, user).await?, 1);
+    assert!(user_settings(&db, user).await?.level_up_dms);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn xp_opt_outs(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let user = Id::new(1);
+    let (guild, other_guild) = (Id::new(2), Id::new(3));
+    assert!(!xp_opted_out(&db, user, guild).await?);
+
+    set_guild_opt_out(&db, user, guild, true).await?;
+    set_guild_opt_out(&db, user, guild, true).await?;
+    assert!(xp_opted_out(&db, user, guild).await?);
+    assert!(!xp_opted_out(&db, user, other_guild).await?);
+    assert_eq!(user_guild_opt_outs(&db, user).await?, [guild]);
+
+    let cfg = UpdateUserSettings::new().xp_opt_out(Some(true));
+    update_user_settings(&db, user, cfg).await?;
+    assert!(xp_opted_out(&db, user, other_guild).await?);
+
+    set_guild_opt_out(&db, user, guild, false).await?;
+    assert!(user_guild_opt_outs(&db, user).await?.is_empty());
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn leaderboard_hidden_users(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let guild = Id::new(1);
+    add_xp(&db, Id::new(2), guild, 10).await?;
+    add_xp(&db, Id::new(3), guild, 20).await?;
+    let cfg = UpdateUserSettings::new().hide_from_leaderboard(Some(true));
+    update_user_settings(&db, Id::new(3), cfg).await?;
+
+    // Hidden members still hold their place for rank rewards
+    assert_eq!(get_guild_leaderboard(&db, guild).await?.len(), 2);
+    assert_eq!(hidden_from_leaderboard(&db, guild).await?, [Id::new(3)]);
+    assert!(hidden_from_leaderboard(&db, Id::new(4)).await?.is_empty());
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn user_data_for_export(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let (user, other) = (Id::new(1), Id::new(2));
+    let guild = Id::new(3);
+    set_cooldown(&db, user, guild, 100, 0).await?;
+    set_cooldown(&db, other, guild, 100, 0).await?;
+    add_user_guild_cleanup(&db, guild, user).await?;
+    let event = |target, moderator| AuditLogEvent {
+        guild,
+        target,
+        moderator,
+        timestamp: 0,
+        previous: 0,
+        delta: 10,
+        kind: AuditLogEventKind::AddOrSub,
+        details: None,
+    };
+    add_audit_log_event(&db, event(Some(user), other)).await?;
+    add_audit_log_event(&db, event(Some(other), user)).await?;
+    add_audit_log_event(&db, event(Some(other), other)).await?;
+
+    assert_eq!(user_audit_log_events(&db, user).await?.len(), 2);
+    let cooldowns = user_cooldowns(&db, user).await?;
+    assert_eq!(cooldowns.len(), 1);
+    assert_eq!(cooldowns[0].last_message, 100);
+    assert_eq!(user_cleanups(&db, user).await?.len(), 1);
+
+    assert_eq!(delete_cooldowns_user(&db, user).await?, 1);
+    assert_eq!(delete_user_cleanups_user(&db, user).await?, 1);
+    assert!(user_cooldowns(&db, user).await?.is_empty());
+    assert!(user_cleanups(&db, user).await?.is_empty());
+    assert_eq!(user_cooldowns(&db, other).await?.len(), 1);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../migrations/")]
+async fn soft_deleted_levels_restore(db: PgPool) -> Result<(), Box<dyn std::error::Error>> {
+    let (user, guild) = (Id::new(1), Id::new(2));
+    add_xp(&db, user, guild, 100).await?;
+    assert_eq!(soft_delete_levels_user_guild(&db, user, guild).await?, 100);
+    assert_eq!(user_xp(&db, guild, user).await?, None);
+
+    // Kicked again after earning more, so both are kept
+    add_xp(&db, user, guild, 30).await?;
+    assert_eq!(soft_delete_levels_user_guild(&db, user, guild).await?, 30);
+    let kept = soft_deleted_levels_user(&db, user).await?;
+    assert_eq!(kept.len(), 1);
+    assert_eq!(kept[0].xp, 130);
+
+    assert_eq!(
+        restore_soft_deleted_levels(&db, user, guild).await?,
+        Some(130)
+    );
+    assert_eq!(restore_soft_deleted_levels(&db, user, guild).await?, None);
+    // Nothing to set aside
+    assert_eq!(soft_delete_levels_user_guild(&db, user, guild).await?, 0);
+    assert!(soft_deleted_levels_user(&db, user).await?.is_empty());
+
+    add_xp(&db, user, guild, 50).await?;
+    soft_delete_levels_user_guild(&db, user, guild).await?;
+    reduce_soft_deleted_levels(&db, user, guild, 20).await?;
+    assert_eq!(soft_deleted_levels_user(&db, user).await?[0].xp, 30);
+    reduce_soft_deleted_levels(&db, user, guild, 30).await?;
+    assert_eq!(restore_soft_deleted_levels(&db, user, guild).await?, None);
+    Ok(())
+}
@@ -3,6 +3,8 @@
 #[macro_use]
 extern crate tracing;
 
+mod scheduler;
+
 use std::{
     env::VarError,
     process::{ExitCode, Termination},
@@ -93,6 +95,7 @@ async fn main() -> Result<(), SetupError> {
     let task_tracker = TaskTracker::new();
 
     let (event_bus_tx, mut event_bus_rx) = tokio::sync::mpsc::channel(10);
+    let (level_up_card_tx, mut level_up_card_rx) = tokio::sync::mpsc::channel(10);
 
     let listener = XpdListener::new(
         db.clone(),
@@ -100,6 +103,7 @@ async fn main() -> Result<(), SetupError> {
         cache.clone(),
         task_tracker.clone(),
         bot_id,
+        level_up_card_tx,
     );
 
     let shutdown = CancellationToken::new();
@@ -132,6 +136,33 @@ async fn main() -> Result<(), SetupError> {
         owners,
         event_bus_tx,
     );
+
+    let card_slash = slash.clone();
+    let card_task_tracker = task_tracker.clone();
+    let card_shutdown = shutdown.clone();
+    let level_up_cards = tokio::spawn(async move {
+        while let Some(announcement) = card_shutdown
+            .run_until_cancelled(level_up_card_rx.recv())
+            .await
+            .flatten()
+        {
+            let slash = card_slash.clone();
+            card_task_tracker.spawn(async move {
+                slash
+                    .post_level_up_card(announcement)
+                    .await
+                    .log_error("Failed to post level-up card");
+            });
+        }
+    });
+    let scheduler = scheduler::Scheduler::new(
+        db.clone(),
+        slash.clone(),
+        listener.clone(),
+        task_tracker.clone(),
+    );
+    task_tracker.spawn(scheduler.run(shutdown.clone()));
+
     let config = Config::new(token.clone(), intents);
     let shards: Vec<Shard> =
         twilight_gateway::create_recommended(&client, config, |_, builder| builder.build())
@@ -178,6 +209,10 @@ async fn main() -> Result<(), SetupError> {
     config_update
         .await
         .log_error("Could not shut down config updater");
+    debug!("Waiting for level-up card poster to close");
+    level_up_cards
+        .await
+        .log_error("Could not shut down level-up card poster");
 
     info!("Done, see ya!");
     Ok(())
@@ -277,10 +312,8 @@ async fn handle_event(
         Event::MemberRemove(mr) => {
             xpd_database::add_user_guild_cleanup(&db, mr.guild_id, mr.user.id).await?;
         }
-        Event::MemberAdd(ma) => {
-            xpd_database::delete_user_guild_cleanup(&db, ma.guild_id, ma.user.id).await?;
-        }
-        Event::GuildAuditLogEntryCreate(gae) => xpd_listener::audit_log(&db, *gae).await?,
+        Event::MemberAdd(ma) => listener.member_add(*ma).await?,
+        Event::GuildAuditLogEntryCreate(gae) => listener.audit_log(*gae).await?,
         Event::InteractionCreate(interaction_create) => slash.execute(*interaction_create).await,
         _ => {}
     };
@@ -322,6 +355,10 @@ pub enum Error {
     DeserializeBody(#[from] twilight_http::response::DeserializeBodyError),
     #[error("Postgres error: {0}")]
     Postgres(#[from] xpd_database::Error),
+    #[error("slash-library error: {0}")]
+    Slash(#[from] xpd_slash::Error),
+    #[error("Cleanup error: {0}")]
+    Cleanup(#[from] xpd_cleanup::Error),
     #[error("Channel error: {0}")]
     Channel(#[from] twilight_gateway::error::ChannelError),
 }
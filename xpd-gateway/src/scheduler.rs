@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use sqlx::{Connection as _, PgConnection, PgPool};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use xpd_cleanup::CleanupOptions;
+use xpd_common::{JobInterval, ScheduledJob, ScheduledJobKind};
+use xpd_listener::XpdListener;
+use xpd_slash::XpdSlash;
+use xpd_util::LogError;
+
+use crate::Error;
+
+/// How often to check the database for jobs which are due to run
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+/// How often to delete data for guilds we've left, and stale cooldowns
+const CLEANUP_INTERVAL: JobInterval = JobInterval::Seconds(24 * 60 * 60);
+/// How often to take away time-limited role rewards which have run out
+const REWARD_EXPIRY_INTERVAL: JobInterval = JobInterval::Seconds(5 * 60);
+
+/// Runs jobs stored in the `scheduled_jobs` table. Multiple instances of the
+/// gateway can safely run a scheduler against the same database, as each job
+/// is claimed and run under an advisory lock keyed on its ID.
+#[derive(Clone)]
+pub struct Scheduler {
+    db: PgPool,
+    slash: XpdSlash,
+    listener: XpdListener,
+    task_tracker: TaskTracker,
+}
+
+impl Scheduler {
+    pub const fn new(
+        db: PgPool,
+        slash: XpdSlash,
+        listener: XpdListener,
+        task_tracker: TaskTracker,
+    ) -> Self {
+        Self {
+            db,
+            slash,
+            listener,
+            task_tracker,
+        }
+    }
+
+    pub async fn run(self, shutdown: CancellationToken) {
+        xpd_database::ensure_scheduled_job(
+            &self.db,
+            ScheduledJobKind::Cleanup,
+            None,
+            CLEANUP_INTERVAL,
+        )
+        .await
+        .log_error("Failed to schedule cleanup job");
+        xpd_database::ensure_scheduled_job(
+            &self.db,
+            ScheduledJobKind::RewardExpiry,
+            None,
+            REWARD_EXPIRY_INTERVAL,
+        )
+        .await
+        .log_error("Failed to schedule reward expiry job");
+
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        while shutdown
+            .run_until_cancelled(interval.tick())
+            .await
+            .is_some()
+        {
+            self.run_due()
+                .await
+                .log_error("Failed to run scheduled jobs");
+        }
+        debug!("Scheduler shut down");
+    }
+
+    async fn run_due(&self) -> Result<(), Error> {
+        let due = xpd_database::due_scheduled_jobs(&self.db).await?;
+        for id in due {
+            let scheduler = self.clone();
+            self.task_tracker.spawn(async move {
+                scheduler
+                    .claim_and_run(id)
+                    .await
+                    .log_error("Failed to run scheduled job");
+            });
+        }
+        Ok(())
+    }
+
+    /// Run a due job, holding an advisory lock on its ID until it's done
+    async fn claim_and_run(&self, id: i64) -> Result<(), Error> {
+        // The lock is session-level. If this task were cancelled or panicked with the lock held,
+        // a pooled connection would go back to the pool still holding it, and the job would
+        // never run again. A detached connection is closed when dropped instead.
+        let mut conn = self
+            .db
+            .acquire()
+            .await
+            .map_err(xpd_database::Error::from)?
+            .detach();
+        if !xpd_database::try_advisory_lock(&mut conn, id).await? {
+            trace!(id, "Scheduled job is running elsewhere");
+            return Ok(());
+        }
+        let result = self.claim_and_run_locked(&mut conn, id).await;
+        xpd_database::advisory_unlock(&mut conn, id).await?;
+        conn.close().await.map_err(xpd_database::Error::from)?;
+        result
+    }
+
+    async fn claim_and_run_locked(&self, conn: &mut PgConnection, id: i64) -> Result<(), Error> {
+        // Another instance may have run it between listing due jobs and taking the lock
+        let Some(job) = xpd_database::claim_scheduled_job(&mut *conn, id).await? else {
+            trace!(id, "Scheduled job was already run elsewhere");
+            return Ok(());
+        };
+        self.run_job(conn, job).await
+    }
+
+    async fn run_job(&self, conn: &mut PgConnection, job: ScheduledJob) -> Result<(), Error> {
+        debug!(?job, "Running scheduled job");
+        match job.kind {
+            ScheduledJobKind::LeaderboardPost => self.post_leaderboard(job).await,
+            ScheduledJobKind::Cleanup => self.cleanup(conn).await,
+            ScheduledJobKind::RewardExpiry => Ok(self.listener.expire_reward_grants().await?),
+        }
+    }
+
+    async fn cleanup(&self, conn: &mut PgConnection) -> Result<(), Error> {
+        let report = xpd_cleanup::cleanup(conn, CleanupOptions::default()).await?;
+        info!(
+            guilds = report.guilds.len(),
+            rows = ?report.rows,
+            skipped = report.skipped,
+            "Finished cleanup"
+        );
+        Ok(())
+    }
+
+    async fn post_leaderboard(&self, job: ScheduledJob) -> Result<(), Error> {
+        let Some(guild) = job.guild else {
+            warn!(?job, "Leaderboard post job has no guild");
+            return Ok(());
+        };
+        let Some(schedule) = xpd_database::leaderboard_schedule(&self.db, guild).await? else {
+            warn!(?job, "Leaderboard post job has no leaderboard schedule");
+            return Ok(());
+        };
+        self.slash.post_scheduled_leaderboard(schedule).await?;
+        Ok(())
+    }
+}
@@ -6,10 +6,10 @@ use twilight_model::{
         marker::{AuditLogEntryMarker, GuildMarker, UserMarker},
     },
 };
-use xpd_common::{AuditLogEvent, AuditLogEventKind};
-use xpd_database::{AcquireWrapper as _, PgPool};
+use xpd_common::{AuditLogEvent, AuditLogEventKind, KickBanXpPolicy};
+use xpd_database::AcquireWrapper as _;
 
-use crate::Error;
+use crate::{Error, XpdListenerInner};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 struct DiscordAuditLogClearEvent {
@@ -23,6 +23,7 @@ struct DiscordAuditLogClearEvent {
 enum DiscordAuditLogClearType {
     Kick,
     Ban,
+    Unban,
 }
 
 impl DiscordAuditLogClearType {
@@ -30,62 +31,139 @@ impl DiscordAuditLogClearType {
         match ty {
             AuditLogEventType::MemberKick => Some(Self::Kick),
             AuditLogEventType::MemberBanAdd => Some(Self::Ban),
+            AuditLogEventType::MemberBanRemove => Some(Self::Unban),
             _ => None,
         }
     }
 }
 
-pub async fn audit_log(db: &PgPool, audit_log: GuildAuditLogEntryCreate) -> Result<(), Error> {
-    if let Some(kind) = DiscordAuditLogClearType::from_audit_log_type(audit_log.action_type) {
-        let Some(guild) = audit_log.guild_id else {
-            return Err(AuditLogError::GuildMissing.into());
-        };
-        let Some(target) = audit_log.target_id else {
-            return Err(AuditLogError::TargetMissing.into());
-        };
-        let Some(moderator) = audit_log.user_id else {
-            return Err(AuditLogError::ModeratorMissing.into());
+impl XpdListenerInner {
+    pub async fn audit_log(&self, audit_log: GuildAuditLogEntryCreate) -> Result<(), Error> {
+        if let Some(kind) = DiscordAuditLogClearType::from_audit_log_type(audit_log.action_type) {
+            let Some(guild) = audit_log.guild_id else {
+                return Err(AuditLogError::GuildMissing.into());
+            };
+            let Some(target) = audit_log.target_id else {
+                return Err(AuditLogError::TargetMissing.into());
+            };
+            let Some(moderator) = audit_log.user_id else {
+                return Err(AuditLogError::ModeratorMissing.into());
+            };
+            let ev = DiscordAuditLogClearEvent {
+                kind,
+                guild,
+                target: target.cast(),
+                moderator,
+            };
+            let event = match kind {
+                DiscordAuditLogClearType::Kick | DiscordAuditLogClearType::Ban => {
+                    self.take_audit_log_action(audit_log.id, ev).await?
+                }
+                DiscordAuditLogClearType::Unban => self.restore_unbanned(audit_log.id, ev).await?,
+            };
+            if let Some(event) = event {
+                self.notify_mod_log(event).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn take_audit_log_action(
+        &self,
+        id: Id<AuditLogEntryMarker>,
+        event: DiscordAuditLogClearEvent,
+    ) -> Result<Option<AuditLogEvent>, Error> {
+        let config = self.get_guild_config(event.guild).await?;
+        if config.kick_ban_xp_policy() == KickBanXpPolicy::Keep {
+            return Ok(None);
+        }
+
+        let mut txn = self.db.xbegin().await?;
+        let old_xp = xpd_database::user_xp(txn.as_mut(), event.guild, event.target)
+            .await?
+            .unwrap_or(0);
+
+        let soft_delete = config.kick_ban_xp_policy() == KickBanXpPolicy::SoftDelete;
+        let kind = match (event.kind, soft_delete) {
+            (DiscordAuditLogClearType::Kick, false) => AuditLogEventKind::KickReset,
+            (DiscordAuditLogClearType::Ban, false) => AuditLogEventKind::BanReset,
+            (DiscordAuditLogClearType::Kick, true) => AuditLogEventKind::KickSoftDelete,
+            (DiscordAuditLogClearType::Ban, true) => AuditLogEventKind::BanSoftDelete,
+            (DiscordAuditLogClearType::Unban, _) => {
+                return Err(AuditLogError::UnusedKind.into());
+            }
         };
-        let ev = DiscordAuditLogClearEvent {
+
+        let audit_log_event = AuditLogEvent {
+            guild: event.guild,
+            target: Some(event.target),
+            moderator: event.moderator,
+            timestamp: xpd_util::snowflake_to_timestamp(id),
+            previous: old_xp,
+            delta: -old_xp,
             kind,
-            guild,
-            target: target.cast(),
-            moderator,
+            details: None,
         };
-        take_audit_log_action(db, audit_log.id, ev).await?;
-    };
-    Ok(())
-}
 
-async fn take_audit_log_action(
-    db: &PgPool,
-    id: Id<AuditLogEntryMarker>,
-    event: DiscordAuditLogClearEvent,
-) -> Result<(), Error> {
-    let mut txn = db.xbegin().await?;
-    let old_xp = xpd_database::user_xp(txn.as_mut(), event.guild, event.target)
-        .await?
-        .unwrap_or(0);
-
-    let kind = match event.kind {
-        DiscordAuditLogClearType::Kick => AuditLogEventKind::KickReset,
-        DiscordAuditLogClearType::Ban => AuditLogEventKind::BanReset,
-    };
+        xpd_database::add_audit_log_event(txn.as_mut(), audit_log_event.clone()).await?;
+        if soft_delete {
+            xpd_database::soft_delete_levels_user_guild(txn.as_mut(), event.target, event.guild)
+                .await?;
+        } else {
+            xpd_database::delete_levels_user_guild(txn.as_mut(), event.target, event.guild).await?;
+        }
+        txn.commit().await?;
+        Ok(Some(audit_log_event))
+    }
 
-    let audit_log_event = AuditLogEvent {
-        guild: event.guild,
-        target: event.target,
-        moderator: event.moderator,
-        timestamp: xpd_util::snowflake_to_timestamp(id),
-        previous: old_xp,
-        delta: -old_xp,
-        kind,
-    };
+    /// Give an unbanned member back the XP set aside when they were banned
+    async fn restore_unbanned(
+        &self,
+        id: Id<AuditLogEntryMarker>,
+        event: DiscordAuditLogClearEvent,
+    ) -> Result<Option<AuditLogEvent>, Error> {
+        self.restore_soft_deleted(
+            event.guild,
+            event.target,
+            event.moderator,
+            xpd_util::snowflake_to_timestamp(id),
+            AuditLogEventKind::UnbanRestore,
+        )
+        .await
+    }
 
-    xpd_database::add_audit_log_event(txn.as_mut(), audit_log_event).await?;
-    xpd_database::delete_levels_user_guild(txn.as_mut(), event.target, event.guild).await?;
-    txn.commit().await?;
-    Ok(())
+    /// Give a member back the XP set aside when they were kicked or banned, if there is any.
+    /// The guild's current policy doesn't matter, since the XP was only set aside under one
+    /// that promised to give it back.
+    pub(crate) async fn restore_soft_deleted(
+        &self,
+        guild: Id<GuildMarker>,
+        target: Id<UserMarker>,
+        moderator: Id<UserMarker>,
+        timestamp: i64,
+        kind: AuditLogEventKind,
+    ) -> Result<Option<AuditLogEvent>, Error> {
+        let mut txn = self.db.xbegin().await?;
+        let Some(restored) =
+            xpd_database::restore_soft_deleted_levels(txn.as_mut(), target, guild).await?
+        else {
+            return Ok(None);
+        };
+        let new_xp = xpd_database::add_xp(txn.as_mut(), target, guild, restored).await?;
+        let audit_log_event = AuditLogEvent {
+            guild,
+            target: Some(target),
+            moderator,
+            timestamp,
+            previous: new_xp - restored,
+            delta: restored,
+            kind,
+            details: None,
+        };
+        xpd_database::add_audit_log_event(txn.as_mut(), audit_log_event.clone()).await?;
+        txn.commit().await?;
+        Ok(Some(audit_log_event))
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -96,6 +174,6 @@ pub enum AuditLogError {
     TargetMissing,
     #[error("Discord did not send a moderator for the audit log event!")]
     ModeratorMissing,
-    #[error("Discord did not send a moderator for the audit log event!")]
+    #[error("This kind of audit log event does not reset XP!")]
     UnusedKind,
 }
@@ -0,0 +1,274 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use rand::RngExt as _;
+use simpleinterpolation::Interpolation;
+use twilight_model::{
+    channel::message::{AllowedMentions, Embed},
+    gateway::payload::incoming::MessageCreate,
+    id::{
+        Id,
+        marker::{ChannelMarker, GuildMarker, UserMarker},
+    },
+};
+use twilight_util::builder::embed::{EmbedAuthorBuilder, EmbedBuilder, EmbedFooterBuilder};
+use xpd_common::{
+    GuildConfig, LevelUpAnnouncement, LevelUpDelivery, LevelUpMessage, MemberDisplayInfo,
+};
+use xpd_util::DisplayName;
+
+use crate::{Error, XpdListenerInner};
+
+type TemplateArgs<'a> = HashMap<Cow<'a, str>, Cow<'a, str>>;
+
+/// How long to wait before trying to DM someone again after Discord refused to deliver one
+const CLOSED_DMS_RETRY: Duration = Duration::from_secs(12 * 60 * 60);
+/// How long a user's level-up DM setting is trusted before it's loaded again. Changes made
+/// through this gateway apply straight away, but other gateways only find out this way.
+const LEVEL_UP_DM_SETTING_TTL: Duration = Duration::from_secs(10 * 60);
+
+impl XpdListenerInner {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn congratulate_user(
+        &self,
+        guild_id: Id<GuildMarker>,
+        guild_config: &GuildConfig,
+        msg: &MessageCreate,
+        user_level: i64,
+        old_user_level: i64,
+        xp: u64,
+        old_xp: u64,
+    ) -> Result<(), Error> {
+        let level_up_messages = self.get_level_up_messages(guild_id).await?;
+        let pool = xpd_util::level_up_message_pool(&level_up_messages, user_level);
+        // The guild's usual message is only used when no milestone message covers this level
+        let template = if pool.is_empty() {
+            guild_config.level_up_message.as_ref()
+        } else {
+            Some(&pool[rand::rng().random_range(0..pool.len())].template)
+        };
+        let use_embed = guild_config.level_up_embed == Some(true);
+        // A rank card can go out on its own
+        let rank_card = guild_config.level_up_rank_card == Some(true);
+        if template.is_none() && !use_embed && !rank_card {
+            return Ok(());
+        }
+        let delivery = guild_config.level_up_delivery();
+        let target_channel = match delivery {
+            LevelUpDelivery::Channel => {
+                let channel = guild_config.level_up_channel.unwrap_or(msg.channel_id);
+                if !xpd_util::can_create_message(&self.cache, self.bot_id, channel)? {
+                    warn!(channel = ?msg.channel_id, user = ?msg.author.id, guild = ?msg.guild_id, "Could not congratulate user");
+                    return Ok(());
+                }
+                channel
+            }
+            LevelUpDelivery::Dm => match self.level_up_dm_channel(msg.author.id).await? {
+                Some(channel) => channel,
+                None => return Ok(()),
+            },
+        };
+        debug!(user = ?msg.author.id, channel = ?msg.channel_id, ?target_channel, old = old_user_level, new = user_level, "Congratulating user");
+        let mention = format!("<@{}>", msg.author.id);
+        // this is horrible but i love it.
+        let author_id_str = &mention[2..=mention.len() - 2];
+
+        let nickname = msg
+            .member
+            .as_ref()
+            .and_then(|v| v.nick.as_deref().map(Cow::Borrowed))
+            .unwrap_or_else(|| Cow::Borrowed(msg.author.display_name()));
+
+        let map: TemplateArgs = HashMap::from([
+            (Cow::Borrowed("user_id"), Cow::Borrowed(author_id_str)),
+            ("user_mention".into(), mention.as_str().into()),
+            ("user_username".into(), msg.author.name.as_str().into()),
+            ("user_display_name".into(), msg.author.display_name().into()),
+            ("user_nickname".into(), nickname),
+            ("old_level".into(), old_user_level.to_string().into()),
+            ("level".into(), user_level.to_string().into()),
+            ("old_xp".into(), old_xp.to_string().into()),
+            ("xp".into(), xp.to_string().into()),
+        ]);
+        let message = template.map(|template| template.render(&map));
+
+        // DMs notify the member anyway
+        let ping =
+            delivery == LevelUpDelivery::Channel && guild_config.ping_on_level_up != Some(false);
+        let allowed_mentions = if ping {
+            AllowedMentions {
+                replied_user: true,
+                users: vec![msg.author.id],
+                ..AllowedMentions::default()
+            }
+        } else {
+            AllowedMentions::default()
+        };
+
+        let (mut content, mut embed) = if use_embed {
+            // Mentions in embeds don't ping, so the mention goes alongside it
+            let content = ping.then(|| mention.clone());
+            (content, Some(level_up_embed(guild_config, &map, message)))
+        } else {
+            (message, None)
+        };
+        // A DM needs to say which server it's about
+        if delivery == LevelUpDelivery::Dm
+            && let Some(guild_name) = self.cache.guild(guild_id).map(|v| v.name().to_owned())
+        {
+            if let Some(embed) = embed.as_mut() {
+                embed.author = Some(EmbedAuthorBuilder::new(guild_name).build());
+            } else {
+                content = content.map(|content| format!("From **{guild_name}**:\n{content}"));
+            }
+        }
+        // only reply to a message if it's in the same channel
+        let reply_to = (target_channel == msg.channel_id).then_some(msg.id);
+
+        if rank_card {
+            let member = MemberDisplayInfo {
+                nick: msg.member.as_ref().and_then(|member| member.nick.clone()),
+                local_avatar: msg.member.as_ref().and_then(|member| member.avatar),
+                ..msg.author.clone().into()
+            };
+            let announcement = LevelUpAnnouncement {
+                guild: guild_id,
+                channel: target_channel,
+                reply_to,
+                member,
+                content,
+                embed,
+                allowed_mentions,
+            };
+            if self.level_up_cards.send(announcement).await.is_err() {
+                warn!(guild = ?guild_id, "Level-up card renderer is gone, skipping announcement");
+            }
+            return Ok(());
+        }
+
+        let mut congratulatory_msg = self.http.create_message(target_channel);
+        if let Some(reply_to) = reply_to {
+            congratulatory_msg = congratulatory_msg.reply(reply_to);
+        }
+        if let Some(content) = content.as_deref() {
+            congratulatory_msg = congratulatory_msg.content(content);
+        }
+        let sent = congratulatory_msg
+            .allowed_mentions(Some(&allowed_mentions))
+            .embeds(embed.as_slice())
+            .await;
+        match sent {
+            Ok(_) => Ok(()),
+            Err(source) if xpd_util::is_cannot_message_user(&source) => {
+                self.close_dms(msg.author.id);
+                Ok(())
+            }
+            Err(source) => Err(source.into()),
+        }
+    }
+
+    /// Pause level-up DMs to a user Discord wouldn't deliver one to
+    pub(crate) fn close_dms(&self, user: Id<UserMarker>) {
+        debug!(?user, "User does not accept DMs, pausing level-up DMs");
+        self.closed_dms
+            .retain(|_, closed_at| closed_at.elapsed() < CLOSED_DMS_RETRY);
+        self.closed_dms.insert(user, Instant::now());
+    }
+
+    /// Find the DM channel to announce this user's level-ups in. Returns [`None`] when they've
+    /// turned level-up DMs off, or Discord recently refused to deliver one to them.
+    async fn level_up_dm_channel(
+        &self,
+        user: Id<UserMarker>,
+    ) -> Result<Option<Id<ChannelMarker>>, Error> {
+        let closed_at = self.closed_dms.get(&user).map(|v| *v);
+        if let Some(closed_at) = closed_at {
+            if closed_at.elapsed() < CLOSED_DMS_RETRY {
+                return Ok(None);
+            }
+            self.closed_dms.remove(&user);
+        }
+        let cached = self
+            .level_up_dm_settings
+            .get(&user)
+            .map(|v| *v)
+            .filter(|(_, checked_at)| checked_at.elapsed() < LEVEL_UP_DM_SETTING_TTL);
+        let enabled = if let Some((enabled, _)) = cached {
+            enabled
+        } else {
+            let enabled = xpd_database::user_settings(&self.db, user)
+                .await?
+                .level_up_dms;
+            self.level_up_dm_settings
+                .retain(|_, (_, checked_at)| checked_at.elapsed() < LEVEL_UP_DM_SETTING_TTL);
+            self.level_up_dm_settings
+                .insert(user, (enabled, Instant::now()));
+            enabled
+        };
+        if !enabled {
+            return Ok(None);
+        }
+        // A user's DM channel never changes, so it's only looked up once
+        if let Some(channel) = self.dm_channels.get(&user) {
+            return Ok(Some(*channel));
+        }
+        let channel = self
+            .http
+            .create_private_channel(user)
+            .await?
+            .model()
+            .await?;
+        self.dm_channels.insert(user, channel.id);
+        Ok(Some(channel.id))
+    }
+}
+
+impl XpdListenerInner {
+    async fn get_level_up_messages(
+        &self,
+        guild: Id<GuildMarker>,
+    ) -> Result<Arc<[LevelUpMessage]>, Error> {
+        if let Some(messages) = self.level_up_messages.get(&guild) {
+            return Ok(messages.clone());
+        }
+        let messages: Arc<[LevelUpMessage]> =
+            xpd_database::guild_level_up_messages(&self.db, guild)
+                .await?
+                .into();
+        self.level_up_messages.insert(guild, messages.clone());
+        Ok(messages)
+    }
+
+    pub fn invalidate_level_up_messages(&self, guild: Id<GuildMarker>) {
+        self.level_up_messages.remove(&guild);
+    }
+}
+
+/// Build the level-up embed from the guild's templates. The description falls back to the
+/// rendered level-up message, and then to a plain announcement, since embeds can't be empty.
+fn level_up_embed(
+    guild_config: &GuildConfig,
+    map: &TemplateArgs,
+    message: Option<String>,
+) -> Embed {
+    let render = |template: Option<&Interpolation>| template.map(|v| v.render(map));
+    let mut embed = EmbedBuilder::new();
+    if let Some(title) = render(guild_config.level_up_embed_title.as_ref()) {
+        embed = embed.title(title);
+    }
+    let description = render(guild_config.level_up_embed_description.as_ref())
+        .or(message)
+        .unwrap_or_else(|| format!("{} reached level {}!", map["user_mention"], map["level"]));
+    embed = embed.description(description);
+    if let Some(color) = guild_config.level_up_embed_color {
+        embed = embed.color(color);
+    }
+    if let Some(footer) = render(guild_config.level_up_embed_footer.as_ref()) {
+        embed = embed.footer(EmbedFooterBuilder::new(footer));
+    }
+    embed.build()
+}
@@ -1,6 +1,7 @@
-use std::{ops::Deref, sync::Arc};
+use std::{ops::Deref, sync::Arc, time::Instant};
 
 use dashmap::DashMap;
+use tokio::sync::mpsc::Sender;
 use tokio_util::task::TaskTracker;
 use twilight_cache_inmemory::{InMemoryCache, ResourceType};
 use twilight_gateway::EventTypeFlags;
@@ -8,16 +9,26 @@ use twilight_model::{
     gateway::Intents,
     id::{
         Id,
-        marker::{GuildMarker, UserMarker},
+        marker::{ChannelMarker, GuildMarker, UserMarker},
     },
 };
-use xpd_common::{EventBusMessage, GuildConfig, RequiredDiscordResources, RoleReward};
+use xpd_common::{
+    AuditLogEvent, EventBusMessage, GuildConfig, LevelUpAnnouncement, LevelUpMessage, RankReward,
+    RequiredDiscordResources, RoleReward,
+};
 use xpd_database::PgPool;
 
 mod audit_log;
+mod level_up;
+mod members;
 mod message;
+mod mod_log;
+mod rank;
+mod rewards;
+mod role_queue;
 
-pub use audit_log::audit_log;
+use mod_log::ModLog;
+use role_queue::RoleQueue;
 
 #[macro_use]
 extern crate tracing;
@@ -32,8 +43,16 @@ impl XpdListener {
         cache: Arc<InMemoryCache>,
         tasks: TaskTracker,
         me: Id<UserMarker>,
+        level_up_cards: Sender<LevelUpAnnouncement>,
     ) -> Self {
-        Self(Arc::new(XpdListenerInner::new(db, http, cache, tasks, me)))
+        Self(Arc::new(XpdListenerInner::new(
+            db,
+            http,
+            cache,
+            tasks,
+            me,
+            level_up_cards,
+        )))
     }
 }
 
@@ -67,6 +86,15 @@ pub struct XpdListenerInner {
     task_tracker: TaskTracker,
     configs: DashMap<Id<GuildMarker>, Arc<GuildConfig>>,
     rewards: DashMap<Id<GuildMarker>, Arc<[RoleReward]>>,
+    rank_rewards: DashMap<Id<GuildMarker>, Arc<[RankReward]>>,
+    rank_standings: DashMap<Id<GuildMarker>, rank::Standings>,
+    level_up_messages: DashMap<Id<GuildMarker>, Arc<[LevelUpMessage]>>,
+    closed_dms: DashMap<Id<UserMarker>, Instant>,
+    dm_channels: DashMap<Id<UserMarker>, Id<ChannelMarker>>,
+    level_up_dm_settings: DashMap<Id<UserMarker>, (bool, Instant)>,
+    mod_log: ModLog,
+    role_queue: RoleQueue,
+    level_up_cards: Sender<LevelUpAnnouncement>,
     bot_id: Id<UserMarker>,
 }
 
@@ -77,15 +105,33 @@ impl XpdListenerInner {
         cache: Arc<InMemoryCache>,
         task_tracker: TaskTracker,
         bot_id: Id<UserMarker>,
+        level_up_cards: Sender<LevelUpAnnouncement>,
     ) -> Self {
         let configs = DashMap::new();
         let rewards = DashMap::new();
+        let rank_rewards = DashMap::new();
+        let rank_standings = DashMap::new();
+        let level_up_messages = DashMap::new();
+        let closed_dms = DashMap::new();
+        let dm_channels = DashMap::new();
+        let level_up_dm_settings = DashMap::new();
+        let mod_log = ModLog::new(http.clone(), cache.clone(), task_tracker.clone(), bot_id);
+        let role_queue = RoleQueue::new(db.clone(), http.clone(), task_tracker.clone());
 
         Self {
             db,
             http,
             configs,
             rewards,
+            rank_rewards,
+            rank_standings,
+            level_up_messages,
+            closed_dms,
+            dm_channels,
+            level_up_dm_settings,
+            mod_log,
+            role_queue,
+            level_up_cards,
             cache,
             task_tracker,
             bot_id,
@@ -96,6 +142,23 @@ impl XpdListenerInner {
         let res = match msg {
             EventBusMessage::InvalidateRewards(id) => self.invalidate_rewards(id).await,
             EventBusMessage::UpdateConfig(id, guild_config) => self.update_config(id, guild_config),
+            EventBusMessage::AuditLogEvent(event) => self.notify_mod_log(event).await,
+            EventBusMessage::RecheckRewards(id, user, level_dropped) => {
+                self.recheck_rewards(id, user, level_dropped).await
+            }
+            EventBusMessage::InvalidateLevelUpMessages(id) => {
+                self.invalidate_level_up_messages(id);
+                Ok(())
+            }
+            EventBusMessage::UpdateLevelUpDms(user, enabled) => {
+                self.level_up_dm_settings
+                    .insert(user, (enabled, Instant::now()));
+                Ok(())
+            }
+            EventBusMessage::DmsClosed(user) => {
+                self.close_dms(user);
+                Ok(())
+            }
         };
         match res {
             Ok(()) => {}
@@ -123,11 +186,38 @@ impl XpdListenerInner {
         Ok(config)
     }
 
+    /// Post an audit log event to its guild's mod-log channel, if it has one
+    pub async fn notify_mod_log(&self, event: AuditLogEvent) -> Result<(), Error> {
+        let config = self.get_guild_config(event.guild).await?;
+        if let Some(channel) = config.mod_log_channel {
+            self.mod_log.queue(channel, event);
+        }
+        Ok(())
+    }
+
     pub async fn invalidate_rewards(&self, guild: Id<GuildMarker>) -> Result<(), Error> {
         let mut new_rewards = xpd_database::guild_rewards(&self.db, guild).await?;
         new_rewards.sort_by(xpd_common::compare_rewards_requirement);
         self.rewards.insert(guild, new_rewards.into());
-        Ok(())
+
+        // Rank rewards take effect straight away, so anyone who held or now holds one is re-checked
+        self.rank_rewards.remove(&guild);
+        let old_standings = self
+            .rank_standings
+            .remove(&guild)
+            .map(|(_, standings)| standings)
+            .unwrap_or_default();
+        let rank_rewards = self.get_rank_rewards(guild).await?;
+        let mut users: Vec<Id<UserMarker>> = old_standings.iter().map(|v| v.0).collect();
+        if !rank_rewards.is_empty() {
+            self.reload_rank_standings(guild, &rank_rewards).await?;
+            if let Some(standings) = self.rank_standings.get(&guild) {
+                users.extend(standings.iter().map(|v| v.0));
+            }
+        }
+        users.sort_unstable();
+        users.dedup();
+        self.recheck_users(guild, &users).await
     }
 
     pub async fn get_guild_rewards(
@@ -148,7 +238,10 @@ impl XpdListenerInner {
 
 impl RequiredDiscordResources for XpdListenerInner {
     fn required_intents() -> Intents {
-        Intents::GUILDS | Intents::GUILD_MESSAGES | Intents::GUILD_MODERATION
+        Intents::GUILDS
+            | Intents::GUILD_MESSAGES
+            | Intents::GUILD_MODERATION
+            | Intents::GUILD_MEMBERS
     }
 
     fn required_events() -> EventTypeFlags {
@@ -167,6 +260,7 @@ impl RequiredDiscordResources for XpdListenerInner {
             | EventTypeFlags::THREAD_DELETE
             | EventTypeFlags::MESSAGE_CREATE
             | EventTypeFlags::GUILD_AUDIT_LOG_ENTRY_CREATE
+            | EventTypeFlags::MEMBER_ADD
     }
 
     fn required_cache_types() -> ResourceType {
@@ -182,6 +276,8 @@ impl RequiredDiscordResources for XpdListenerInner {
 pub enum Error {
     #[error("Discord error")]
     Twilight(Box<twilight_http::Error>),
+    #[error("Discord sent an unreadable response: {0}")]
+    TwilightDeserialize(#[from] twilight_http::response::DeserializeBodyError),
     #[error("database fetch fail: {0}")]
     DatabaseAbstraction(#[from] xpd_database::Error),
     #[error("simpleinterpolation failed")]
@@ -0,0 +1,50 @@
+use twilight_model::gateway::payload::incoming::MemberAdd;
+use xpd_common::{AuditLogEventKind, DISCORD_EPOCH_SECS};
+
+use crate::{Error, XpdListenerInner};
+
+impl XpdListenerInner {
+    /// Forget that a member left, which makes them visible again if the guild hides members
+    /// while they're gone, and give them back any XP set aside when they were kicked, and the
+    /// role rewards they'd earned.
+    pub async fn member_add(&self, member_add: MemberAdd) -> Result<(), Error> {
+        let guild_id = member_add.guild_id;
+        let member = member_add.member;
+        xpd_database::delete_user_guild_cleanup(&self.db, guild_id, member.user.id).await?;
+        if member.user.bot {
+            return Ok(());
+        }
+        let joined_at = member
+            .joined_at
+            .map_or(0, |joined_at| joined_at.as_secs() - DISCORD_EPOCH_SECS);
+        let restored = self
+            .restore_soft_deleted(
+                guild_id,
+                member.user.id,
+                self.bot_id,
+                joined_at,
+                AuditLogEventKind::RejoinRestore,
+            )
+            .await?;
+        if let Some(event) = restored {
+            self.notify_mod_log(event).await?;
+        }
+        let Some(xp) = xpd_database::user_xp(&self.db, guild_id, member.user.id).await? else {
+            return Ok(());
+        };
+        let level = mee6::LevelInfo::new(xp.try_into().unwrap_or(0)).level();
+        let guild_config = self.get_guild_config(guild_id).await?;
+        let rewards = self.get_guild_rewards(guild_id).await?;
+        debug!(user = ?member.user.id, ?guild_id, level, "Re-applying role rewards for returning member");
+        self.update_user_roles(
+            guild_id,
+            &guild_config,
+            member.user.id,
+            &member.roles,
+            &rewards,
+            level.try_into().unwrap_or(-1),
+            false,
+        )
+        .await
+    }
+}
@@ -0,0 +1,126 @@
+use std::{sync::Arc, time::Duration};
+
+use dashmap::{DashMap, mapref::entry::Entry};
+use tokio_util::task::TaskTracker;
+use twilight_cache_inmemory::InMemoryCache;
+use twilight_model::{
+    channel::message::Embed,
+    id::{
+        Id,
+        marker::{ChannelMarker, UserMarker},
+    },
+    util::Timestamp,
+};
+use twilight_util::builder::embed::EmbedBuilder;
+use xpd_common::{AuditLogCategory, AuditLogEvent, DISCORD_EPOCH_SECS};
+
+use crate::Error;
+
+/// How long events are collected for before they're posted, so that bursts of
+/// events become a single message.
+const BATCH_DELAY: Duration = Duration::from_secs(2);
+/// Discord allows at most 10 embeds per message
+const MAX_EMBEDS_PER_MESSAGE: usize = 10;
+
+/// Posts audit log events to mod-log channels.
+///
+/// Each channel gets at most one message every [`BATCH_DELAY`], holding up to
+/// [`MAX_EMBEDS_PER_MESSAGE`] events. Anything left over waits for the next message.
+#[derive(Clone)]
+pub struct ModLog(Arc<ModLogInner>);
+
+pub struct ModLogInner {
+    http: Arc<twilight_http::Client>,
+    cache: Arc<InMemoryCache>,
+    task_tracker: TaskTracker,
+    bot_id: Id<UserMarker>,
+    pending: DashMap<Id<ChannelMarker>, Vec<AuditLogEvent>>,
+}
+
+impl ModLog {
+    pub fn new(
+        http: Arc<twilight_http::Client>,
+        cache: Arc<InMemoryCache>,
+        task_tracker: TaskTracker,
+        bot_id: Id<UserMarker>,
+    ) -> Self {
+        Self(Arc::new(ModLogInner {
+            http,
+            cache,
+            task_tracker,
+            bot_id,
+            pending: DashMap::new(),
+        }))
+    }
+
+    /// Queue an event to be posted to `channel`. If nothing is waiting to be sent
+    /// to that channel yet, this starts a task to send it.
+    pub fn queue(&self, channel: Id<ChannelMarker>, event: AuditLogEvent) {
+        match self.0.pending.entry(channel) {
+            Entry::Occupied(mut pending) => pending.get_mut().push(event),
+            Entry::Vacant(pending) => {
+                pending.insert(vec![event]);
+                let mod_log = self.clone();
+                self.0.task_tracker.spawn(async move {
+                    mod_log.flush(channel).await;
+                });
+            }
+        }
+    }
+
+    async fn flush(&self, channel: Id<ChannelMarker>) {
+        loop {
+            tokio::time::sleep(BATCH_DELAY).await;
+            // The entry is removed while it's locked, so any event queued after this
+            // point starts a new flush task
+            let batch: Vec<AuditLogEvent> = match self.0.pending.entry(channel) {
+                Entry::Occupied(pending) if pending.get().is_empty() => {
+                    pending.remove();
+                    return;
+                }
+                Entry::Occupied(mut pending) => {
+                    let pending = pending.get_mut();
+                    let count = pending.len().min(MAX_EMBEDS_PER_MESSAGE);
+                    pending.drain(..count).collect()
+                }
+                Entry::Vacant(_) => return,
+            };
+            if let Err(source) = self.post(channel, &batch).await {
+                warn!(?source, ?channel, "Could not post to mod-log channel");
+            }
+        }
+    }
+
+    async fn post(
+        &self,
+        channel: Id<ChannelMarker>,
+        events: &[AuditLogEvent],
+    ) -> Result<(), Error> {
+        if !xpd_util::can_create_message(&self.0.cache, self.0.bot_id, channel)? {
+            debug!(?channel, "Missing permissions to post to mod-log channel");
+            return Ok(());
+        }
+        let embeds: Vec<Embed> = events.iter().map(event_embed).collect();
+        self.0.http.create_message(channel).embeds(&embeds).await?;
+        Ok(())
+    }
+}
+
+fn event_embed(event: &AuditLogEvent) -> Embed {
+    let (title, color) = match event.kind.category() {
+        AuditLogCategory::Xp => ("XP changed", 0x0058_65F2),
+        AuditLogCategory::Config => ("Config changed", 0x00FE_E75C),
+        AuditLogCategory::Rewards => ("Role rewards changed", 0x0057_F287),
+        AuditLogCategory::Data => ("Server XP imported or reset", 0x00ED_4245),
+        AuditLogCategory::Card => ("Server card changed", 0x00EB_459E),
+        AuditLogCategory::Compliance => ("User data deleted", 0x0099_AAB5),
+    };
+    let mut embed = EmbedBuilder::new()
+        .title(title)
+        .color(color)
+        .description(event.to_string());
+    if let Ok(timestamp) = Timestamp::from_secs(event.timestamp + DISCORD_EPOCH_SECS) {
+        embed = embed.timestamp(timestamp);
+    }
+    embed.build()
+}
@@ -0,0 +1,138 @@
+use std::{cmp::Reverse, sync::Arc};
+
+use twilight_model::id::{
+    Id,
+    marker::{GuildMarker, UserMarker},
+};
+use xpd_common::RankReward;
+
+use crate::{Error, XpdListenerInner};
+
+/// The top of a guild's leaderboard, as far down as its deepest rank reward reaches
+pub(crate) type Standings = Vec<(Id<UserMarker>, i64)>;
+
+impl XpdListenerInner {
+    pub async fn get_rank_rewards(
+        &self,
+        guild_id: Id<GuildMarker>,
+    ) -> Result<Arc<[RankReward]>, Error> {
+        if let Some(rewards) = self.rank_rewards.get(&guild_id) {
+            return Ok(rewards.clone());
+        }
+        let mut rewards = xpd_database::guild_rank_rewards(&self.db, guild_id).await?;
+        rewards.sort_by_key(|v| v.max_rank);
+
+        let new_copy: Arc<[RankReward]> = rewards.into();
+        self.rank_rewards.insert(guild_id, new_copy.clone());
+        Ok(new_copy)
+    }
+
+    /// Where a user sits in the guild's standings, or `None` if they're below every rank reward
+    pub(crate) async fn rank_position(
+        &self,
+        guild_id: Id<GuildMarker>,
+        user_id: Id<UserMarker>,
+    ) -> Result<Option<usize>, Error> {
+        let rank_rewards = self.get_rank_rewards(guild_id).await?;
+        if rank_rewards.is_empty() {
+            return Ok(None);
+        }
+        if !self.rank_standings.contains_key(&guild_id) {
+            self.reload_rank_standings(guild_id, &rank_rewards).await?;
+        }
+        Ok(self
+            .rank_standings
+            .get(&guild_id)
+            .and_then(|standings| standings.iter().position(|v| v.0 == user_id)))
+    }
+
+    /// Rebuild a guild's standings from the database.
+    /// Returns everyone whose rank rewards are different from the previous standings.
+    pub(crate) async fn reload_rank_standings(
+        &self,
+        guild_id: Id<GuildMarker>,
+        rank_rewards: &[RankReward],
+    ) -> Result<Vec<Id<UserMarker>>, Error> {
+        let mut standings: Standings = xpd_database::get_guild_leaderboard(&self.db, guild_id)
+            .await?
+            .into_iter()
+            // Match /leaderboard, which only ranks people who are still here
+            .filter(|v| self.cache.member(guild_id, v.id).is_some())
+            .map(|v| (v.id, v.xp))
+            .collect();
+        standings.sort_by_key(|v| Reverse(v.1));
+        standings.truncate(standings_size(rank_rewards));
+        let old = self
+            .rank_standings
+            .insert(guild_id, standings.clone())
+            .unwrap_or_default();
+        Ok(moved_users(rank_rewards, &old, &standings))
+    }
+
+    /// Account for a user gaining XP without going back to the database.
+    /// Returns everyone whose rank rewards changed, including the user themselves.
+    pub(crate) async fn raise_rank_standing(
+        &self,
+        guild_id: Id<GuildMarker>,
+        user_id: Id<UserMarker>,
+        xp: i64,
+    ) -> Result<Vec<Id<UserMarker>>, Error> {
+        let rank_rewards = self.get_rank_rewards(guild_id).await?;
+        if rank_rewards.is_empty() {
+            return Ok(Vec::new());
+        }
+        if !self.rank_standings.contains_key(&guild_id) {
+            return self.reload_rank_standings(guild_id, &rank_rewards).await;
+        }
+        let (old, new) = {
+            let Some(mut standings) = self.rank_standings.get_mut(&guild_id) else {
+                return Ok(Vec::new());
+            };
+            let old = standings.clone();
+            let size = standings_size(&rank_rewards);
+            if let Some(entry) = standings.iter_mut().find(|v| v.0 == user_id) {
+                entry.1 = xp;
+            } else if standings.len() < size || standings.last().is_some_and(|v| xp > v.1) {
+                standings.push((user_id, xp));
+            } else {
+                return Ok(Vec::new());
+            }
+            // Stable, so whoever got there first keeps a tied spot
+            standings.sort_by_key(|v| Reverse(v.1));
+            standings.truncate(size);
+            (old, standings.clone())
+        };
+        Ok(moved_users(&rank_rewards, &old, &new))
+    }
+}
+
+fn standings_size(rank_rewards: &[RankReward]) -> usize {
+    rank_rewards
+        .iter()
+        .map(|v| usize::try_from(v.max_rank).unwrap_or(0))
+        .max()
+        .unwrap_or(0)
+}
+
+/// Everyone in either standings whose rank rewards differ between them
+fn moved_users(
+    rank_rewards: &[RankReward],
+    old: &Standings,
+    new: &Standings,
+) -> Vec<Id<UserMarker>> {
+    let position =
+        |standings: &Standings, user: Id<UserMarker>| standings.iter().position(|v| v.0 == user);
+    let mut moved: Vec<Id<UserMarker>> = old
+        .iter()
+        .chain(new.iter())
+        .map(|v| v.0)
+        .filter(|user| {
+            xpd_util::rank_reward_roles(rank_rewards, position(old, *user)).ne(
+                xpd_util::rank_reward_roles(rank_rewards, position(new, *user)),
+            )
+        })
+        .collect();
+    moved.sort_unstable();
+    moved.dedup();
+    moved
+}
@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+
+use twilight_model::id::{
+    Id,
+    marker::{GuildMarker, RoleMarker, UserMarker},
+};
+use xpd_common::{GuildConfig, RewardGrant, RoleReward};
+use xpd_util::{LogError as _, RoleChangeList};
+
+use crate::{Error, XpdListenerInner, role_queue::PendingGrant};
+
+impl XpdListenerInner {
+    /// Bring a member's reward roles in line with their level and leaderboard position,
+    /// adding earned rewards and removing ones they no longer qualify for. Level rewards
+    /// above `user_level` are only removed with `remove_unearned`, see [`xpd_util::get_role_changes`].
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(skip(self, member_roles))]
+    pub(crate) async fn update_user_roles(
+        &self,
+        guild_id: Id<GuildMarker>,
+        guild_config: &GuildConfig,
+        user_id: Id<UserMarker>,
+        member_roles: &[Id<RoleMarker>],
+        rewards: &[RoleReward],
+        user_level: i64,
+        remove_unearned: bool,
+    ) -> Result<(), Error> {
+        let reward_idx = xpd_util::get_reward_idx(rewards, user_level);
+        let mut roles = xpd_util::get_role_changes(
+            guild_config,
+            member_roles,
+            rewards,
+            reward_idx,
+            remove_unearned,
+        );
+        // Time-limited rewards aren't given again once they've run out
+        let grants = self
+            .settle_reward_grants(guild_id, user_id, rewards, user_level)
+            .await?;
+        let expired: Vec<Id<RoleMarker>> = grants
+            .iter()
+            .filter(|grant| grant.expired)
+            .map(|grant| grant.role)
+            .collect();
+        roles.remove_roles(member_roles, &expired);
+        // Both kinds of reward go out in one request, so neither undoes the other
+        let rank_rewards = self.get_rank_rewards(guild_id).await?;
+        let position = self.rank_position(guild_id, user_id).await?;
+        let rank = xpd_util::get_rank_role_changes(&roles.total_roles, &rank_rewards, position);
+        roles.changed_roles.extend(rank.changed_roles);
+        roles.total_roles = rank.total_roles;
+        if roles.changed_roles.is_empty() {
+            return Ok(());
+        }
+
+        let new_grants = rewards
+            .iter()
+            .filter(|reward| {
+                roles.changed_roles.contains(&reward.id)
+                    && roles.total_roles.contains(&reward.id)
+                    && !grants.iter().any(|grant| grant.role == reward.id)
+            })
+            .filter_map(|reward| {
+                Some(PendingGrant {
+                    role: reward.id,
+                    duration_hours: reward.duration_hours?,
+                })
+            })
+            .collect();
+        self.apply_role_changes(guild_id, user_id, member_roles, &roles, new_grants)
+            .await?;
+        Ok(())
+    }
+
+    /// Queue a member's new role list to be sent to Discord, along with the time-limited
+    /// rewards it gives, if we're allowed to change every role in it. Returns whether the
+    /// update was queued.
+    pub(crate) async fn apply_role_changes(
+        &self,
+        guild_id: Id<GuildMarker>,
+        user_id: Id<UserMarker>,
+        member_roles: &[Id<RoleMarker>],
+        roles: &RoleChangeList,
+        grants: Vec<PendingGrant>,
+    ) -> Result<bool, Error> {
+        // make sure we don't make useless error requests to the API
+        let can_add_role = xpd_util::can_manage_roles(
+            &self.cache,
+            self.bot_id,
+            guild_id,
+            roles.changed_roles.as_slice(),
+        )?;
+        if can_add_role.can_update_roles() {
+            debug!(user = ?user_id, old = ?member_roles, new = ?roles, "Queueing role update for user");
+            self.role_queue
+                .queue(guild_id, user_id, roles.total_roles.clone(), grants);
+            Ok(true)
+        } else {
+            warn!(user = ?user_id, old = ?member_roles, new = ?roles, %can_add_role, "Could not update roles for user");
+            self.role_queue
+                .record_failure(guild_id, user_id, &can_add_role.to_string())
+                .await;
+            Ok(false)
+        }
+    }
+
+    /// Load a member's grants of time-limited rewards, forgetting the ones for levels they're
+    /// no longer at so they can be earned again.
+    async fn settle_reward_grants(
+        &self,
+        guild_id: Id<GuildMarker>,
+        user_id: Id<UserMarker>,
+        rewards: &[RoleReward],
+        user_level: i64,
+    ) -> Result<Vec<RewardGrant>, Error> {
+        if rewards.iter().all(|reward| reward.duration_hours.is_none()) {
+            return Ok(Vec::new());
+        }
+        let mut grants = xpd_database::user_reward_grants(&self.db, guild_id, user_id).await?;
+        let mut kept = Vec::with_capacity(grants.len());
+        for grant in grants.drain(..) {
+            let unearned = rewards
+                .iter()
+                .any(|reward| reward.id == grant.role && reward.requirement > user_level);
+            if unearned {
+                xpd_database::delete_reward_grant(&self.db, guild_id, user_id, grant.role).await?;
+            } else {
+                kept.push(grant);
+            }
+        }
+        Ok(kept)
+    }
+
+    /// Take away time-limited rewards that have run out, in every guild. Only one gateway
+    /// runs this job, so guilds it doesn't serve are handled without the cache.
+    pub async fn expire_reward_grants(&self) -> Result<(), Error> {
+        let due = xpd_database::due_reward_grants(&self.db).await?;
+        debug!(count = due.len(), "Expiring reward grants");
+        for grant in due {
+            let expired = if self.cache.guild(grant.guild).is_some() {
+                self.expire_reward_grant(grant).await
+            } else {
+                self.expire_uncached_reward_grant(grant).await
+            };
+            expired.log_error("Failed to expire reward grant");
+        }
+        Ok(())
+    }
+
+    /// Remove an expired reward directly, for a guild that isn't in our cache
+    async fn expire_uncached_reward_grant(&self, grant: RewardGrant) -> Result<(), Error> {
+        match self
+            .http
+            .remove_guild_member_role(grant.guild, grant.user, grant.role)
+            .await
+        {
+            Ok(_) => {}
+            // The member, the role or our access to the guild is gone, so there's nothing to take away
+            Err(source) if xpd_util::is_unknown_member_or_role(&source) => {
+                debug!(?grant, "Expired reward is already gone");
+            }
+            // Try again next time, in case the permissions get fixed
+            Err(source) => return Err(source.into()),
+        }
+        xpd_database::mark_reward_grant_removed(&self.db, grant.guild, grant.user, grant.role)
+            .await?;
+        Ok(())
+    }
+
+    async fn expire_reward_grant(&self, grant: RewardGrant) -> Result<(), Error> {
+        let member_roles = self
+            .cache
+            .member(grant.guild, grant.user)
+            .map(|member| member.roles().to_vec());
+        // If they've left or already lost the role, there's nothing to take away
+        if let Some(member_roles) = member_roles
+            && member_roles.contains(&grant.role)
+        {
+            let roles = RoleChangeList {
+                total_roles: member_roles
+                    .iter()
+                    .copied()
+                    .filter(|role| *role != grant.role)
+                    .collect(),
+                changed_roles: vec![grant.role],
+            };
+            if !self
+                .apply_role_changes(grant.guild, grant.user, &member_roles, &roles, Vec::new())
+                .await?
+            {
+                // Try again next time, in case the permissions get fixed
+                return Ok(());
+            }
+        }
+        xpd_database::mark_reward_grant_removed(&self.db, grant.guild, grant.user, grant.role)
+            .await?;
+        Ok(())
+    }
+
+    /// Re-evaluate role rewards after XP changed somewhere other than a message,
+    /// for one member or, if `user` is `None`, every cached member of the guild.
+    /// Only if `level_dropped` are their rewards above their level removed.
+    pub async fn recheck_rewards(
+        &self,
+        guild_id: Id<GuildMarker>,
+        user: Option<Id<UserMarker>>,
+        level_dropped: bool,
+    ) -> Result<(), Error> {
+        let guild_config = self.get_guild_config(guild_id).await?;
+        let rewards = self.get_guild_rewards(guild_id).await?;
+        let rank_rewards = self.get_rank_rewards(guild_id).await?;
+        if rewards.is_empty() && rank_rewards.is_empty() {
+            return Ok(());
+        }
+        // XP can go down here, which the standings can't follow on their own
+        let mut moved = if rank_rewards.is_empty() {
+            Vec::new()
+        } else {
+            self.reload_rank_standings(guild_id, &rank_rewards).await?
+        };
+
+        if let Some(user_id) = user {
+            let xp = xpd_database::user_xp(&self.db, guild_id, user_id)
+                .await?
+                .unwrap_or(0);
+            self.recheck_member(
+                guild_id,
+                &guild_config,
+                user_id,
+                &rewards,
+                xp,
+                level_dropped,
+            )
+            .await?;
+            // Everyone else only changed places
+            moved.retain(|moved| *moved != user_id);
+            return self.recheck_users(guild_id, &moved).await;
+        }
+
+        let levels: HashMap<Id<UserMarker>, i64> =
+            xpd_database::export_bulk_users(&self.db, guild_id)
+                .await?
+                .into_iter()
+                .map(|user| (user.id, user.xp))
+                .collect();
+        let members: Vec<Id<UserMarker>> = self
+            .cache
+            .guild_members(guild_id)
+            .map(|members| members.iter().copied().collect())
+            .unwrap_or_default();
+        debug!(
+            ?guild_id,
+            members = members.len(),
+            "Re-checking role rewards for guild"
+        );
+        for user_id in members {
+            let xp = levels.get(&user_id).copied().unwrap_or(0);
+            self.recheck_member(
+                guild_id,
+                &guild_config,
+                user_id,
+                &rewards,
+                xp,
+                level_dropped,
+            )
+            .await
+            .log_error("Failed to re-check role rewards for member");
+        }
+        Ok(())
+    }
+
+    /// Re-evaluate role rewards for a handful of members whose levels didn't change,
+    /// looking up their XP one by one
+    pub(crate) async fn recheck_users(
+        &self,
+        guild_id: Id<GuildMarker>,
+        users: &[Id<UserMarker>],
+    ) -> Result<(), Error> {
+        if users.is_empty() {
+            return Ok(());
+        }
+        let guild_config = self.get_guild_config(guild_id).await?;
+        let rewards = self.get_guild_rewards(guild_id).await?;
+        for user_id in users {
+            let xp = xpd_database::user_xp(&self.db, guild_id, *user_id)
+                .await?
+                .unwrap_or(0);
+            self.recheck_member(guild_id, &guild_config, *user_id, &rewards, xp, false)
+                .await
+                .log_error("Failed to re-check role rewards for member");
+        }
+        Ok(())
+    }
+
+    async fn recheck_member(
+        &self,
+        guild_id: Id<GuildMarker>,
+        guild_config: &GuildConfig,
+        user_id: Id<UserMarker>,
+        rewards: &[RoleReward],
+        xp: i64,
+        level_dropped: bool,
+    ) -> Result<(), Error> {
+        // Bots never earn XP, so anything they have was given to them on purpose
+        if self.cache.user(user_id).is_some_and(|user| user.bot) {
+            return Ok(());
+        }
+        let Some(member_roles) = self
+            .cache
+            .member(guild_id, user_id)
+            .map(|member| member.roles().to_vec())
+        else {
+            return Ok(());
+        };
+        let level = mee6::LevelInfo::new(xp.try_into().unwrap_or(0)).level();
+        self.update_user_roles(
+            guild_id,
+            guild_config,
+            user_id,
+            &member_roles,
+            rewards,
+            level.try_into().unwrap_or(-1),
+            level_dropped,
+        )
+        .await
+    }
+}
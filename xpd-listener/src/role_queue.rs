@@ -0,0 +1,227 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use dashmap::{DashMap, mapref::entry::Entry};
+use tokio_util::task::TaskTracker;
+use twilight_http::error::ErrorType;
+use twilight_model::id::{
+    Id,
+    marker::{GuildMarker, RoleMarker, UserMarker},
+};
+use xpd_database::PgPool;
+use xpd_util::{LogError as _, RoleList};
+
+/// How long updates are collected for before they're sent, so that a member who
+/// levels up and gets overtaken at once only gets one request.
+const BATCH_DELAY: Duration = Duration::from_secs(1);
+/// How long to wait before the first retry. Each retry after that waits twice as long.
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+/// How many times to try an update before giving up on it
+const MAX_ATTEMPTS: u32 = 5;
+/// How long a recorded failure stands before the same member's next one is written again,
+/// so a member who can't be updated doesn't cost a write on every message
+const FAILURE_RECORD_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// A time-limited reward in a queued update. Its clock only starts once Discord has taken the update.
+#[derive(Debug, Clone, Copy)]
+pub struct PendingGrant {
+    pub role: Id<RoleMarker>,
+    pub duration_hours: i64,
+}
+
+/// A member's newest role list that hasn't been sent yet
+struct PendingUpdate {
+    roles: RoleList,
+    grants: Vec<PendingGrant>,
+}
+
+type PendingUpdates = HashMap<Id<UserMarker>, PendingUpdate>;
+
+/// Sends role updates to Discord, one guild at a time.
+///
+/// Queued updates for a member replace any that haven't been sent yet, since each one holds
+/// the member's whole role list. Rate limits and server errors are retried with backoff, and
+/// anything that still fails is recorded for `/config perms_checkup` to show.
+#[derive(Clone)]
+pub struct RoleQueue(Arc<RoleQueueInner>);
+
+pub struct RoleQueueInner {
+    db: PgPool,
+    http: Arc<twilight_http::Client>,
+    task_tracker: TaskTracker,
+    pending: DashMap<Id<GuildMarker>, PendingUpdates>,
+    recorded_failures: DashMap<(Id<GuildMarker>, Id<UserMarker>), Instant>,
+}
+
+impl RoleQueue {
+    pub fn new(db: PgPool, http: Arc<twilight_http::Client>, task_tracker: TaskTracker) -> Self {
+        Self(Arc::new(RoleQueueInner {
+            db,
+            http,
+            task_tracker,
+            pending: DashMap::new(),
+            recorded_failures: DashMap::new(),
+        }))
+    }
+
+    /// Queue `user`'s new role list, and the time-limited rewards it gives them.
+    /// If nothing is waiting to be sent for that guild yet, this starts a task to send it.
+    pub fn queue(
+        &self,
+        guild: Id<GuildMarker>,
+        user: Id<UserMarker>,
+        roles: RoleList,
+        mut grants: Vec<PendingGrant>,
+    ) {
+        match self.0.pending.entry(guild) {
+            Entry::Occupied(mut pending) => {
+                // The replaced update was never sent, so rewards it gave that this one keeps
+                // still need their clocks started
+                if let Some(replaced) = pending.get_mut().remove(&user) {
+                    for old in replaced.grants {
+                        if roles.contains(&old.role)
+                            && !grants.iter().any(|new| new.role == old.role)
+                        {
+                            grants.push(old);
+                        }
+                    }
+                }
+                pending
+                    .get_mut()
+                    .insert(user, PendingUpdate { roles, grants });
+            }
+            Entry::Vacant(pending) => {
+                pending.insert(HashMap::from([(user, PendingUpdate { roles, grants })]));
+                let queue = self.clone();
+                self.0.task_tracker.spawn(async move {
+                    queue.flush(guild).await;
+                });
+            }
+        }
+    }
+
+    async fn flush(&self, guild: Id<GuildMarker>) {
+        loop {
+            tokio::time::sleep(BATCH_DELAY).await;
+            // The entry is removed while it's locked, so any update queued after this
+            // point starts a new flush task
+            let batch: Vec<(Id<UserMarker>, PendingUpdate)> = match self.0.pending.entry(guild) {
+                Entry::Occupied(pending) if pending.get().is_empty() => {
+                    pending.remove();
+                    return;
+                }
+                Entry::Occupied(mut pending) => pending.get_mut().drain().collect(),
+                Entry::Vacant(_) => return,
+            };
+            for (user, update) in batch {
+                self.send(guild, user, &update).await;
+            }
+        }
+    }
+
+    async fn send(&self, guild: Id<GuildMarker>, user: Id<UserMarker>, update: &PendingUpdate) {
+        let mut delay = RETRY_BASE_DELAY;
+        for attempt in 1..=MAX_ATTEMPTS {
+            let source = match self
+                .0
+                .http
+                .update_guild_member(guild, user)
+                .roles(&update.roles)
+                .await
+            {
+                Ok(_) => {
+                    self.0.recorded_failures.remove(&(guild, user));
+                    xpd_database::clear_role_update_failure(&self.0.db, guild, user)
+                        .await
+                        .log_error("Failed to clear role update failure");
+                    for grant in &update.grants {
+                        xpd_database::add_reward_grant(
+                            &self.0.db,
+                            guild,
+                            user,
+                            grant.role,
+                            grant.duration_hours,
+                        )
+                        .await
+                        .log_error("Failed to record reward grant");
+                    }
+                    return;
+                }
+                Err(source) => source,
+            };
+            if !is_retryable(&source) || attempt == MAX_ATTEMPTS {
+                warn!(?source, ?guild, ?user, attempt, "Giving up on role update");
+                self.record_failure(guild, user, &failure_reason(&source))
+                    .await;
+                return;
+            }
+            // A newer update for this member makes this one pointless
+            if self
+                .0
+                .pending
+                .get(&guild)
+                .is_some_and(|pending| pending.contains_key(&user))
+            {
+                debug!(
+                    ?guild,
+                    ?user,
+                    "Dropping role update superseded during retry"
+                );
+                return;
+            }
+            debug!(
+                ?source,
+                ?guild,
+                ?user,
+                attempt,
+                ?delay,
+                "Retrying role update"
+            );
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+    }
+
+    /// Remember that we couldn't update `user`'s roles, until a later update succeeds.
+    /// Repeats within [`FAILURE_RECORD_INTERVAL`] are skipped.
+    pub async fn record_failure(&self, guild: Id<GuildMarker>, user: Id<UserMarker>, reason: &str) {
+        match self.0.recorded_failures.entry((guild, user)) {
+            Entry::Occupied(recorded) if recorded.get().elapsed() < FAILURE_RECORD_INTERVAL => {
+                return;
+            }
+            Entry::Occupied(mut recorded) => {
+                recorded.insert(Instant::now());
+            }
+            Entry::Vacant(recorded) => {
+                recorded.insert(Instant::now());
+            }
+        }
+        self.0
+            .recorded_failures
+            .retain(|_, recorded_at| recorded_at.elapsed() < FAILURE_RECORD_INTERVAL);
+        xpd_database::record_role_update_failure(&self.0.db, guild, user, reason)
+            .await
+            .log_error("Failed to record role update failure");
+    }
+}
+
+/// Rate limits, server errors and network trouble might go away if we wait
+fn is_retryable(error: &twilight_http::Error) -> bool {
+    match error.kind() {
+        ErrorType::Response { status, .. } => status.get() == 429 || status.is_server_error(),
+        ErrorType::RequestError | ErrorType::RequestTimedOut => true,
+        _ => false,
+    }
+}
+
+fn failure_reason(error: &twilight_http::Error) -> String {
+    match error.kind() {
+        ErrorType::Response { status, error, .. } => {
+            format!("Discord returned {}: {error}", status.get())
+        }
+        _ => error.to_string(),
+    }
+}
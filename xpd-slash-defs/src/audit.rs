@@ -1,4 +1,4 @@
-use twilight_interactions::command::{CommandModel, CreateCommand};
+use twilight_interactions::command::{CommandModel, CommandOption, CreateCommand, CreateOption};
 use twilight_model::{
     guild::Permissions,
     id::{Id, marker::UserMarker},
@@ -7,15 +7,75 @@ use twilight_model::{
 #[derive(CommandModel, CreateCommand)]
 #[command(
     name = "audit",
-    desc = "Download audit logs for your server",
+    desc = "Browse and revert audit logs for your server",
     dm_permission = false,
     default_permissions = "Self::default_permissions"
 )]
-pub struct AuditLogCommand {
+pub enum AuditLogCommand {
+    #[command(name = "view")]
+    View(AuditLogCommandView),
+    #[command(name = "revert")]
+    Revert(AuditLogCommandRevert),
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "view", desc = "Browse audit logs for your server")]
+pub struct AuditLogCommandView {
     #[command(desc = "Fetch audit logs triggered by this moderator")]
     pub moderator: Option<Id<UserMarker>>,
     #[command(desc = "Fetch audit logs acting on this user")]
     pub user: Option<Id<UserMarker>>,
+    #[command(desc = "Only fetch audit logs of this kind")]
+    pub category: Option<AuditLogCategoryOption>,
+    #[command(
+        desc = "Only fetch audit logs from the last this many days",
+        min_value = 1,
+        max_value = 3650
+    )]
+    pub newer_than_days: Option<i64>,
+    #[command(
+        desc = "Only fetch audit logs from more than this many days ago",
+        min_value = 0,
+        max_value = 3650
+    )]
+    pub older_than_days: Option<i64>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "revert",
+    desc = "Undo an XP change, or every XP change a moderator made recently"
+)]
+pub struct AuditLogCommandRevert {
+    #[command(
+        desc = "ID of the event to undo, as shown in /audit view",
+        min_value = 1
+    )]
+    pub event: Option<i64>,
+    #[command(desc = "Undo every XP change made by this moderator")]
+    pub moderator: Option<Id<UserMarker>>,
+    #[command(
+        desc = "How far back to undo this moderator's changes, defaults to 24 hours",
+        min_value = 1,
+        max_value = 720
+    )]
+    pub newer_than_hours: Option<i64>,
+}
+
+#[derive(CommandOption, CreateOption, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditLogCategoryOption {
+    #[option(name = "XP changes", value = "xp")]
+    Xp,
+    #[option(name = "Config changes", value = "config")]
+    Config,
+    #[option(name = "Role rewards", value = "rewards")]
+    Rewards,
+    #[option(name = "Imports and resets", value = "data")]
+    Data,
+    #[option(name = "Server rank card", value = "card")]
+    Card,
+    #[option(name = "GDPR deletions", value = "compliance")]
+    Compliance,
 }
 
 impl AuditLogCommand {
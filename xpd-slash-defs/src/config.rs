@@ -1,4 +1,4 @@
-use twilight_interactions::command::{CommandModel, CreateCommand};
+use twilight_interactions::command::{CommandModel, CommandOption, CreateCommand, CreateOption};
 use twilight_model::{application::interaction::InteractionChannel, guild::Permissions};
 
 #[derive(CommandModel, CreateCommand)]
@@ -18,10 +18,18 @@ pub enum ConfigCommand {
     Rewards(ConfigCommandRewards),
     #[command(name = "levels")]
     Levels(ConfigCommandLevels),
+    #[command(name = "level_up_embed")]
+    LevelUpEmbed(ConfigCommandLevelUpEmbed),
     #[command(name = "rank_card")]
     RankCard(ConfigCommandRankCard),
     #[command(name = "perms_checkup")]
     PermsCheckup(ConfigCommandPermsCheckup),
+    #[command(name = "schedule")]
+    Schedule(ConfigCommandSchedule),
+    #[command(name = "audit")]
+    Audit(ConfigCommandAudit),
+    #[command(name = "members")]
+    Members(ConfigCommandMembers),
 }
 
 impl ConfigCommand {
@@ -66,6 +74,57 @@ pub struct ConfigCommandLevels {
         max_value = 28800
     )]
     pub message_cooldown: Option<i64>,
+    #[command(desc = "Where to send level-up messages")]
+    pub delivery: Option<ConfigLevelUpDeliveryOption>,
+    #[command(desc = "Go back to the default level-up message")]
+    pub clear_level_up_message: Option<bool>,
+    #[command(desc = "Send level-up messages wherever the member leveled up")]
+    pub clear_level_up_channel: Option<bool>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "level_up_embed",
+    desc = "Configure level-up announcements sent as embeds",
+    dm_permission = false
+)]
+pub struct ConfigCommandLevelUpEmbed {
+    #[command(desc = "Send level-up announcements as an embed instead of plain text")]
+    pub enabled: Option<bool>,
+    #[command(
+        desc = "Embed title. Uses the same variables as the level-up message",
+        max_length = 256,
+        min_length = 1
+    )]
+    pub title: Option<String>,
+    #[command(
+        desc = "Embed description. Defaults to the level-up message",
+        max_length = 1024,
+        min_length = 1
+    )]
+    pub description: Option<String>,
+    #[command(
+        desc = "Embed color, as a hex code like #FF8800",
+        max_length = 7,
+        min_length = 6
+    )]
+    pub color: Option<String>,
+    #[command(
+        desc = "Embed footer. Uses the same variables as the level-up message",
+        max_length = 256,
+        min_length = 1
+    )]
+    pub footer: Option<String>,
+    #[command(desc = "Attach the member's rank card, showing their new level")]
+    pub rank_card: Option<bool>,
+    #[command(desc = "Remove the embed title")]
+    pub clear_title: Option<bool>,
+    #[command(desc = "Go back to using the level-up message as the embed description")]
+    pub clear_description: Option<bool>,
+    #[command(desc = "Remove the embed color")]
+    pub clear_color: Option<bool>,
+    #[command(desc = "Remove the embed footer")]
+    pub clear_footer: Option<bool>,
 }
 
 #[derive(CommandModel, CreateCommand)]
@@ -79,12 +138,90 @@ pub struct ConfigCommandRewards {
     pub one_at_a_time: Option<bool>,
 }
 
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "audit",
+    desc = "Configure how long audit logs are kept",
+    dm_permission = false
+)]
+pub struct ConfigCommandAudit {
+    #[command(
+        desc = "How many days to keep audit log events for (Default 365)",
+        min_value = 1,
+        max_value = 3650
+    )]
+    pub retention_days: Option<i64>,
+    #[command(desc = "Also delete compliance records, like GDPR deletions, once they expire")]
+    pub prune_compliance: Option<bool>,
+    #[command(
+        desc = "Channel to post every audit log event in as it happens",
+        channel_types = "guild_text"
+    )]
+    pub mod_log_channel: Option<InteractionChannel>,
+    #[command(desc = "Go back to keeping audit log events for 365 days")]
+    pub clear_retention_days: Option<bool>,
+    #[command(desc = "Go back to keeping compliance records forever")]
+    pub clear_prune_compliance: Option<bool>,
+    #[command(desc = "Stop posting audit log events in a channel")]
+    pub clear_mod_log_channel: Option<bool>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "members",
+    desc = "Configure what happens to XP when members are removed",
+    dm_permission = false
+)]
+pub struct ConfigCommandMembers {
+    #[command(desc = "What to do with a member's XP when they're kicked or banned")]
+    pub kick_ban_xp: Option<ConfigKickBanXpOption>,
+    #[command(desc = "What to do with a member's XP while they aren't in the server")]
+    pub leave_xp: Option<ConfigLeaveXpOption>,
+    #[command(
+        desc = "How many days after leaving to delete a member's XP, if set to delete (Default 30)",
+        min_value = 1,
+        max_value = 3650
+    )]
+    pub leave_delete_after_days: Option<i64>,
+}
+
+#[derive(CommandOption, CreateOption, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigLevelUpDeliveryOption {
+    #[option(name = "In chat (default)", value = "channel")]
+    Channel,
+    #[option(name = "In a DM to the member", value = "dm")]
+    Dm,
+}
+
+#[derive(CommandOption, CreateOption, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigLeaveXpOption {
+    #[option(name = "Keep it forever (default)", value = "keep")]
+    Keep,
+    #[option(name = "Delete it after they've been gone a while", value = "delete")]
+    Delete,
+    #[option(name = "Hide them from rankings until they rejoin", value = "hide")]
+    Hide,
+}
+
+#[derive(CommandOption, CreateOption, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigKickBanXpOption {
+    #[option(name = "Reset their XP (default)", value = "reset")]
+    Reset,
+    #[option(name = "Keep their XP", value = "keep")]
+    Keep,
+    #[option(
+        name = "Remove their XP, and restore it if they're unbanned or rejoin",
+        value = "soft_delete"
+    )]
+    SoftDelete,
+}
+
 #[derive(CommandModel, CreateCommand)]
 #[command(name = "reset", desc = "Reset your guild's configuration")]
 pub struct ConfigCommandReset;
 
 #[derive(CommandModel, CreateCommand)]
-#[command(name = "get", desc = "Get your guild's configuration")]
+#[command(name = "get", desc = "View and edit your guild's configuration")]
 pub struct ConfigCommandGet;
 
 #[derive(CommandModel, CreateCommand)]
@@ -100,3 +237,29 @@ pub struct ConfigCommandRankCard {
     #[command(desc = "Show off the card to all users by default")]
     pub show_off_by_default: Option<bool>,
 }
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "schedule",
+    desc = "Automatically post the top 10 leaderboard to a channel"
+)]
+pub struct ConfigCommandSchedule {
+    #[command(desc = "How often to post the leaderboard")]
+    pub period: ConfigScheduleOption,
+    #[command(desc = "Where to post the leaderboard", channel_types = "guild_text")]
+    pub channel: Option<InteractionChannel>,
+    #[command(desc = "Attach the rank card of the member in first place")]
+    pub winner_card: Option<bool>,
+    #[command(desc = "Mention the top three members when posting")]
+    pub mention_winners: Option<bool>,
+}
+
+#[derive(CommandOption, CreateOption, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigScheduleOption {
+    #[option(name = "Weekly", value = "weekly")]
+    Weekly,
+    #[option(name = "Monthly", value = "monthly")]
+    Monthly,
+    #[option(name = "Disabled", value = "disabled")]
+    Disabled,
+}
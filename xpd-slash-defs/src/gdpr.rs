@@ -1,5 +1,4 @@
-use twilight_interactions::command::{CommandModel, CreateCommand};
-use twilight_model::id::{Id, marker::UserMarker};
+use twilight_interactions::command::{CommandModel, CommandOption, CreateCommand, CreateOption};
 
 #[derive(CommandModel, CreateCommand)]
 #[command(
@@ -16,14 +15,22 @@ pub enum GdprCommand {
 
 #[derive(CommandModel, CreateCommand)]
 #[command(name = "delete", desc = "Delete all of your data from Experienced")]
-pub struct GdprCommandDelete {
-    #[command(desc = "Your @username (for confirmation)")]
-    pub user: Id<UserMarker>,
-}
+pub struct GdprCommandDelete;
 
 #[derive(CommandModel, CreateCommand)]
 #[command(
     name = "download",
     desc = "Download all of your data stored by Experienced"
 )]
-pub struct GdprCommandDownload;
+pub struct GdprCommandDownload {
+    #[command(desc = "What kind of files to send (Default JSON)")]
+    pub format: Option<GdprDownloadFormat>,
+}
+
+#[derive(CommandOption, CreateOption, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GdprDownloadFormat {
+    #[option(name = "One JSON file (default)", value = "json")]
+    Json,
+    #[option(name = "A CSV file for each kind of data", value = "csv")]
+    Csv,
+}
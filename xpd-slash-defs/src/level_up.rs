@@ -0,0 +1,66 @@
+use twilight_interactions::command::{CommandModel, CreateCommand};
+use twilight_model::guild::Permissions;
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "level-up",
+    desc = "Manage level-up messages for specific levels",
+    dm_permission = false,
+    default_permissions = "Self::default_permissions"
+)]
+pub enum LevelUpCommand {
+    #[command(name = "add")]
+    Add(LevelUpCommandAdd),
+    #[command(name = "remove")]
+    Remove(LevelUpCommandRemove),
+    #[command(name = "list")]
+    List(LevelUpCommandList),
+}
+
+impl LevelUpCommand {
+    #[inline]
+    const fn default_permissions() -> Permissions {
+        Permissions::ADMINISTRATOR
+    }
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "add",
+    desc = "Add a level-up message. Messages for the same levels are picked from at random",
+    dm_permission = false
+)]
+pub struct LevelUpCommandAdd {
+    #[command(
+        desc = "Message to send. Uses the same variables as the level-up message",
+        max_length = 512,
+        min_length = 1
+    )]
+    pub message: String,
+    #[command(desc = "Lowest level to use this message for", min_value = 1)]
+    pub min_level: Option<i64>,
+    #[command(desc = "Highest level to use this message for", min_value = 1)]
+    pub max_level: Option<i64>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "remove",
+    desc = "Remove a level-up message",
+    dm_permission = false
+)]
+pub struct LevelUpCommandRemove {
+    #[command(
+        desc = "ID of the message to remove, from /level-up list",
+        min_value = 1
+    )]
+    pub id: i64,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "list",
+    desc = "List this server's level-up messages",
+    dm_permission = false
+)]
+pub struct LevelUpCommandList;
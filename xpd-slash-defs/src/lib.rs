@@ -4,13 +4,17 @@ pub mod card;
 pub mod config;
 pub mod experience;
 pub mod gdpr;
+pub mod level_up;
 pub mod levels;
 pub mod manage;
 pub mod rewards;
+pub mod settings;
 
 use admin::AdminCommand;
 use audit::AuditLogCommand;
+use level_up::LevelUpCommand;
 use rewards::RewardsCommand;
+use settings::SettingsCommand;
 use twilight_interactions::command::{CommandModel, CreateCommand};
 use twilight_model::{
     application::command::{Command, CommandType},
@@ -46,6 +50,8 @@ pub fn get_commands() -> Vec<Command> {
         GuildCardCommand::create_command().into(),
         LeaderboardCommand::create_command().into(),
         RewardsCommand::create_command().into(),
+        LevelUpCommand::create_command().into(),
+        SettingsCommand::create_command().into(),
         AuditLogCommand::create_command().into(),
         context_cmd("Get level", CommandType::User),
         context_cmd("Get author level", CommandType::Message),
@@ -94,6 +100,20 @@ fn ensure_limits_match() {
         cooldown_value,
         CommandOptionValue::Integer(xpd_common::MAX_MESSAGE_COOLDOWN.into())
     );
+    let audit_cmd = cmd.options.iter().find(|v| v.name == "audit").unwrap();
+    let retention_value = audit_cmd
+        .options
+        .as_ref()
+        .unwrap()
+        .iter()
+        .find(|v| v.name == "retention_days")
+        .unwrap()
+        .max_value
+        .unwrap();
+    assert_eq!(
+        retention_value,
+        CommandOptionValue::Integer(xpd_common::MAX_AUDIT_LOG_RETENTION_DAYS.into())
+    );
 }
 
 #[test]
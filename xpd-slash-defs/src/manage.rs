@@ -25,20 +25,13 @@ impl ManageCommand {
     }
 }
 
-pub const CONFIRMATION_STRING: &str = "I Understand The Risks";
-
 #[derive(CommandModel, CreateCommand)]
 #[command(
     name = "reset",
     desc = "DANGER: Reset ALL the leveling data for your guild! This is IRREVERSIBLE!",
     dm_permission = false
 )]
-pub struct ManageCommandResetGuild {
-    #[command(
-        desc = "\"I Understand The Risks\", to ensure you know this will delete ALL YOUR DATA"
-    )]
-    pub confirm_message: String,
-}
+pub struct ManageCommandResetGuild;
 
 #[derive(CommandModel, CreateCommand)]
 #[command(
@@ -14,10 +14,14 @@ use twilight_model::{
 pub enum RewardsCommand {
     #[command(name = "add")]
     Add(RewardsCommandAdd),
+    #[command(name = "add-rank")]
+    AddRank(RewardsCommandAddRank),
     #[command(name = "remove")]
     Remove(RewardsCommandRemove),
     #[command(name = "list")]
     List(RewardsCommandList),
+    #[command(name = "sync")]
+    Sync(RewardsCommandSync),
 }
 
 impl RewardsCommand {
@@ -38,6 +42,29 @@ pub struct RewardsCommandAdd {
     pub level: i64,
     #[command(desc = "What role to grant", min_value = 1)]
     pub role: Role,
+    #[command(
+        desc = "Take the role away again this many hours after it's given",
+        min_value = 1,
+        max_value = 8760
+    )]
+    pub duration_hours: Option<i64>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "add-rank",
+    desc = "Add a role that follows the top of the leaderboard",
+    dm_permission = false
+)]
+pub struct RewardsCommandAddRank {
+    #[command(
+        desc = "How far down the leaderboard the role reaches, 1 for only first place",
+        min_value = 1,
+        max_value = 100
+    )]
+    pub rank: i64,
+    #[command(desc = "What role to grant")]
+    pub role: Role,
 }
 
 #[derive(CommandModel, CreateCommand)]
@@ -60,3 +87,14 @@ pub struct RewardsCommandRemove {
     dm_permission = false
 )]
 pub struct RewardsCommandList;
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "sync",
+    desc = "Give every ranked member the role rewards they have earned",
+    dm_permission = false
+)]
+pub struct RewardsCommandSync {
+    #[command(desc = "Only report what would change, without editing any roles")]
+    pub dry_run: Option<bool>,
+}
@@ -0,0 +1,44 @@
+use twilight_interactions::command::{CommandModel, CreateCommand};
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "settings",
+    desc = "Change your personal settings for Experienced",
+    dm_permission = true
+)]
+pub enum SettingsCommand {
+    #[command(name = "notifications")]
+    Notifications(SettingsCommandNotifications),
+    #[command(name = "leveling")]
+    Leveling(SettingsCommandLeveling),
+    #[command(name = "privacy")]
+    Privacy(SettingsCommandPrivacy),
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "notifications",
+    desc = "Choose whether servers may DM you when you level up"
+)]
+pub struct SettingsCommandNotifications {
+    #[command(desc = "Receive level-up DMs from servers that send them (Default true)")]
+    pub level_up_dms: Option<bool>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "leveling", desc = "Choose whether you earn XP")]
+pub struct SettingsCommandLeveling {
+    #[command(desc = "Earn XP from your messages (Default true)")]
+    pub enabled: bool,
+    #[command(desc = "Only change this for the server you're in (Default false)")]
+    pub this_server_only: Option<bool>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "privacy", desc = "Choose who can see your levels")]
+pub struct SettingsCommandPrivacy {
+    #[command(desc = "Leave yourself off of server leaderboards (Default false)")]
+    pub hide_from_leaderboard: Option<bool>,
+    #[command(desc = "Stop other people from looking up your rank (Default false)")]
+    pub hide_from_rank: Option<bool>,
+}
@@ -1,45 +1,716 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use serde::Serialize;
 use twilight_model::{
+    channel::message::{
+        AllowedMentions, Component, EmojiReactionType,
+        component::{ActionRow, Button, ButtonStyle},
+    },
     http::{attachment::Attachment, interaction::InteractionResponseType},
-    id::{Id, marker::GuildMarker},
+    id::{
+        Id,
+        marker::{GuildMarker, InteractionMarker, UserMarker},
+    },
+};
+use twilight_util::builder::embed::{EmbedBuilder, EmbedFooterBuilder};
+use xpd_common::{
+    AuditLogCategory, AuditLogChange, AuditLogEntry, AuditLogEvent, AuditLogEventKind, GuildConfig,
+};
+use xpd_database::AcquireWrapper as _;
+use xpd_slash_defs::audit::{
+    AuditLogCategoryOption, AuditLogCommand, AuditLogCommandRevert, AuditLogCommandView,
 };
-use xpd_slash_defs::audit::AuditLogCommand;
+use xpd_util::snowflake_to_timestamp;
 
-use crate::{Error, SlashState, XpdInteractionData, response::XpdInteractionResponse};
+use crate::{
+    Error, SlashState, XpdInteractionData, experience::level_dropped,
+    response::XpdInteractionResponse,
+};
+
+/// Who made a change, and the interaction they made it with
+#[derive(Clone, Copy, Debug)]
+pub struct AuditData {
+    pub interaction: Id<InteractionMarker>,
+    pub invoker: Id<UserMarker>,
+}
 
-pub async fn process_audit_logs(
+impl AuditData {
+    pub fn timestamp(&self) -> i64 {
+        snowflake_to_timestamp(self.interaction)
+    }
+
+    /// An event for a change which doesn't touch any one user's XP.
+    /// `before` and `after` are stored as JSON.
+    pub fn change_event(
+        &self,
+        guild: Id<GuildMarker>,
+        kind: AuditLogEventKind,
+        before: &impl Serialize,
+        after: &impl Serialize,
+    ) -> Result<AuditLogEvent, Error> {
+        Ok(AuditLogEvent {
+            guild,
+            target: None,
+            moderator: self.invoker,
+            timestamp: self.timestamp(),
+            previous: 0,
+            delta: 0,
+            kind,
+            details: Some(AuditLogChange {
+                before: serde_json::to_value(before)?,
+                after: serde_json::to_value(after)?,
+            }),
+        })
+    }
+}
+
+const EVENTS_PER_PAGE: usize = 10;
+const HOUR: i64 = 60 * 60;
+const DAY: i64 = 24 * HOUR;
+/// How far back `/audit revert` looks for a moderator's changes, if not told otherwise
+const DEFAULT_REVERT_WINDOW_HOURS: i64 = 24;
+
+pub async fn process_audit(
     command: AuditLogCommand,
     guild_id: Id<GuildMarker>,
+    audit: AuditData,
     state: SlashState,
 ) -> Result<XpdInteractionResponse, Error> {
+    match command {
+        AuditLogCommand::View(view) => process_audit_logs(view, guild_id, audit, state).await,
+        AuditLogCommand::Revert(revert) => {
+            let contents = process_revert(revert, guild_id, audit, state).await?;
+            Ok(XpdInteractionData::new()
+                .allowed_mentions_o(Some(AllowedMentions::default()))
+                .ephemeral(true)
+                .embeds([EmbedBuilder::new().description(contents).build()])
+                .into_interaction_response(InteractionResponseType::ChannelMessageWithSource))
+        }
+    }
+}
+
+async fn process_audit_logs(
+    command: AuditLogCommandView,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+    state: SlashState,
+) -> Result<XpdInteractionResponse, Error> {
+    let now = audit.timestamp();
+    let filter = AuditLogFilter {
+        moderator: command.moderator,
+        user: command.user,
+        category: command.category.map(category_from_option),
+        since: command.newer_than_days.map(|days| now - days * DAY),
+        until: command.older_than_days.map(|days| now - days * DAY),
+    };
+    Ok(XpdInteractionResponse::new(
+        InteractionResponseType::ChannelMessageWithSource,
+        gen_audit_page(&state, guild_id, &filter, 0).await?,
+    ))
+}
+
+pub async fn process_audit_component(
+    custom_id: &str,
+    guild_id: Id<GuildMarker>,
+    state: SlashState,
+) -> Result<XpdInteractionResponse, Error> {
+    // The audit log browser is always ephemeral, so only the moderator who
+    // opened it can press its buttons.
+    let (action, filter) = parse_custom_id(custom_id).ok_or(Error::InvalidComponentId)?;
+    match action {
+        AuditLogAction::Page(zpage) => Ok(XpdInteractionResponse::new(
+            InteractionResponseType::UpdateMessage,
+            gen_audit_page(&state, guild_id, &filter, zpage).await?,
+        )),
+        AuditLogAction::Export(format) => {
+            let events = filtered_events(&state, guild_id, &filter).await?;
+            let (file, filename) = match format {
+                ExportFormat::Csv => (audit_csv(events)?, "audit_log.csv"),
+                ExportFormat::Json => (serde_json::to_vec_pretty(&events)?, "audit_log.json"),
+            };
+            let attachment = Attachment {
+                description: Some("Audit logs for this server".to_owned()),
+                file,
+                filename: filename.to_string(),
+                id: 0,
+            };
+            Ok(XpdInteractionData::new()
+                .attachments([attachment])
+                .ephemeral(true)
+                .into_interaction_response(InteractionResponseType::ChannelMessageWithSource))
+        }
+    }
+}
+
+/// Undo either one event, or every revertable event by a moderator in a time window.
+/// Each undone event is logged as a [`AuditLogEventKind::Revert`].
+///
+/// Reverts apply the inverse of each event's delta, so XP earned by chatting since then
+/// is kept. If any later event touched the same user, undoing the older one would
+/// silently clobber it, so the whole revert is refused instead.
+async fn process_revert(
+    command: AuditLogCommandRevert,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+    state: SlashState,
+) -> Result<String, Error> {
+    let mut txn = state.db.xbegin().await?;
+    let entries = xpd_database::get_audit_log_events(txn.as_mut(), guild_id, None, None).await?;
+    let (mut to_revert, skipped) = select_reverts(&command, &entries, audit.timestamp())?;
+
+    let conflicts = revert_conflicts(&entries, &to_revert);
+    if !conflicts.is_empty() {
+        let conflicts: Vec<String> = conflicts.iter().map(|id| format!("`#{id}`")).collect();
+        return Err(Error::RevertConflict(conflicts.join(", ")));
+    }
+
+    // Newest first, so each event is undone from the state it left behind
+    to_revert.sort_by_key(|v| std::cmp::Reverse((v.event.timestamp, v.id)));
+    let mut events = Vec::with_capacity(to_revert.len());
+    let mut xp: i64 = 0;
+    for entry in &to_revert {
+        let target = entry.event.target.ok_or(Error::EventNotRevertable)?;
+        // Applied relatively, so XP earned by chatting while this runs isn't overwritten
+        xp = xpd_database::add_xp(txn.as_mut(), target, guild_id, -entry.event.delta).await?;
+        if xp.is_negative() {
+            txn.rollback().await?;
+            return Err(Error::XpWouldBeNegative);
+        }
+        let previous = xp + entry.event.delta;
+        // XP given back by undoing a kick or ban isn't given back again when they return
+        if matches!(
+            entry.event.kind,
+            AuditLogEventKind::KickSoftDelete | AuditLogEventKind::BanSoftDelete
+        ) {
+            xpd_database::reduce_soft_deleted_levels(
+                txn.as_mut(),
+                target,
+                guild_id,
+                -entry.event.delta,
+            )
+            .await?;
+        }
+        let event = AuditLogEvent {
+            guild: guild_id,
+            target: Some(target),
+            moderator: audit.invoker,
+            timestamp: audit.timestamp(),
+            previous,
+            delta: -entry.event.delta,
+            kind: AuditLogEventKind::Revert,
+            details: Some(AuditLogChange {
+                before: serde_json::Value::Null,
+                after: serde_json::json!({ "reverted": entry.id }),
+            }),
+        };
+        xpd_database::add_audit_log_event(txn.as_mut(), event.clone()).await?;
+        events.push(event);
+    }
+    txn.commit().await?;
+    // Each target's first revert saw their XP from before, and their last one left it as it is now
+    let mut targets: HashMap<Id<UserMarker>, (i64, i64)> = HashMap::new();
+    for event in &events {
+        let Some(target) = event.target else {
+            continue;
+        };
+        let after = event.previous + event.delta;
+        targets
+            .entry(target)
+            .and_modify(|(_, current)| *current = after)
+            .or_insert((event.previous, after));
+    }
+    state.notify_audit_log(events).await;
+    for (target, (before, after)) in targets {
+        state
+            .recheck_rewards(guild_id, Some(target), level_dropped(before, after))
+            .await;
+    }
+
+    let mut message = match (command.moderator, to_revert.as_slice()) {
+        (None, [entry]) => format!(
+            "Reverted `#{}`: {}\n<@{}> now has {xp} XP.",
+            entry.id,
+            entry.event,
+            entry.event.target.map_or(0, Id::get)
+        ),
+        (moderator, _) => format!(
+            "Reverted {} XP changes by <@{}>.",
+            to_revert.len(),
+            moderator.map_or(0, Id::get)
+        ),
+    };
+    if skipped > 0 {
+        write!(
+            message,
+            "\n{skipped} other actions by them in that time can't be reverted, like config changes."
+        )?;
+    }
+    Ok(message)
+}
+
+/// The events `command` asks to revert, and how many of the moderator's events in the
+/// window were skipped because they can't be reverted
+fn select_reverts<'a>(
+    command: &AuditLogCommandRevert,
+    entries: &'a [AuditLogEntry],
+    now: i64,
+) -> Result<(Vec<&'a AuditLogEntry>, usize), Error> {
+    let already_reverted: HashSet<i64> = entries.iter().filter_map(reverted_event_id).collect();
+
+    match (command.event, command.moderator) {
+        (Some(id), None) => {
+            let entry = entries
+                .iter()
+                .find(|v| v.id == id)
+                .ok_or(Error::UnknownAuditLogEvent)?;
+            if !entry.event.kind.is_revertable() {
+                return Err(Error::EventNotRevertable);
+            }
+            if already_reverted.contains(&id) {
+                return Err(Error::AlreadyReverted);
+            }
+            Ok((vec![entry], 0))
+        }
+        (None, Some(moderator)) => {
+            let hours = command
+                .newer_than_hours
+                .unwrap_or(DEFAULT_REVERT_WINDOW_HOURS);
+            let since = now - hours * HOUR;
+            let (revertable, skipped): (Vec<&AuditLogEntry>, Vec<&AuditLogEntry>) = entries
+                .iter()
+                .filter(|v| {
+                    v.event.moderator == moderator
+                        && v.event.timestamp >= since
+                        && !already_reverted.contains(&v.id)
+                })
+                .partition(|v| v.event.kind.is_revertable());
+            if revertable.is_empty() {
+                return Err(Error::NothingToRevert);
+            }
+            Ok((revertable, skipped.len()))
+        }
+        _ => Err(Error::RevertNeedsEventOrModerator),
+    }
+}
+
+/// IDs of events which aren't being reverted, but happened after one that is and
+/// touched the same user's XP
+fn revert_conflicts(entries: &[AuditLogEntry], to_revert: &[&AuditLogEntry]) -> Vec<i64> {
+    entries
+        .iter()
+        .filter(|later| {
+            !to_revert.iter().any(|v| v.id == later.id)
+                && to_revert.iter().any(|v| conflicts_with(later, v))
+        })
+        .map(|v| v.id)
+        .collect()
+}
+
+/// Whether `later` happened after `earlier` and touched the same user's XP,
+/// either directly or as part of a server-wide import or reset
+fn conflicts_with(later: &AuditLogEntry, earlier: &AuditLogEntry) -> bool {
+    (later.event.timestamp, later.id) > (earlier.event.timestamp, earlier.id)
+        && (later.event.target == earlier.event.target
+            || later.event.kind.category() == AuditLogCategory::Data)
+}
+
+/// The ID of the event which `entry` undid, if it's a revert
+fn reverted_event_id(entry: &AuditLogEntry) -> Option<i64> {
+    if entry.event.kind != AuditLogEventKind::Revert {
+        return None;
+    }
+    entry.event.details.as_ref()?.after["reverted"].as_i64()
+}
+
+/// Events matching the filter, newest first
+async fn filtered_events(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+    filter: &AuditLogFilter,
+) -> Result<Vec<AuditLogEntry>, Error> {
     let mut logs =
-        xpd_database::get_audit_log_events(&state.db, guild_id, command.user, command.moderator)
+        xpd_database::get_audit_log_events(&state.db, guild_id, filter.user, filter.moderator)
             .await?;
-    logs.sort_by_key(|a| a.timestamp);
+    logs.retain(|log| filter.matches(&log.event));
+    logs.sort_by_key(|log| std::cmp::Reverse((log.event.timestamp, log.id)));
+    Ok(logs)
+}
+
+async fn gen_audit_page(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+    filter: &AuditLogFilter,
+    zpage: usize,
+) -> Result<XpdInteractionData, Error> {
+    let events = filtered_events(state, guild_id, filter).await?;
+    let config = xpd_database::guild_config(&state.db, guild_id)
+        .await?
+        .unwrap_or_default();
+    let retention = retention_notice(&config);
+
+    if events.is_empty() {
+        return Ok(XpdInteractionData::new()
+            .content(format!(
+                "No matching moderator actions recorded, audit log is empty.\n{retention}"
+            ))
+            .components([])
+            .embeds([])
+            .ephemeral(true));
+    }
+
+    let page_count = events.len().div_ceil(EVENTS_PER_PAGE);
+    let first_event_idx = zpage * EVENTS_PER_PAGE;
+    let Some(page_events) = events.get(first_event_idx..) else {
+        return Err(Error::PageDoesNotExist);
+    };
+
+    let mut description = String::with_capacity(EVENTS_PER_PAGE * 128);
+    for entry in page_events.iter().take(EVENTS_PER_PAGE) {
+        writeln!(description, "- `#{}` {}", entry.id, entry.event)?;
+    }
+    let embed = EmbedBuilder::new()
+        .title("Audit log")
+        .description(description)
+        .footer(EmbedFooterBuilder::new(format!(
+            "Page {} of {page_count} ({} events)",
+            zpage + 1,
+            events.len()
+        )))
+        .build();
+
+    let components = Component::ActionRow(ActionRow {
+        components: control_options(filter, zpage, zpage + 1 < page_count).to_vec(),
+        id: None,
+    });
+
+    Ok(XpdInteractionData::new()
+        .allowed_mentions(AllowedMentions::default())
+        .content(retention)
+        .embeds([embed])
+        .components([components])
+        .ephemeral(true))
+}
+
+fn control_options(
+    filter: &AuditLogFilter,
+    zpage: usize,
+    next_page_exists: bool,
+) -> [Component; 5] {
+    let button = |action: AuditLogAction, label: &str, disabled: bool| Button {
+        custom_id: Some(to_custom_id(action, filter)),
+        disabled,
+        emoji: None,
+        label: Some(label.to_string()),
+        style: ButtonStyle::Primary,
+        url: None,
+        sku_id: None,
+        id: None,
+    };
+    [
+        Button {
+            emoji: Some(EmojiReactionType::Unicode {
+                name: "⬅".to_string(),
+            }),
+            ..button(
+                AuditLogAction::Page(zpage.saturating_sub(1)),
+                "Previous",
+                zpage == 0,
+            )
+        },
+        Button {
+            custom_id: Some(format!("{CUSTOM_ID_PREFIX}indicator")),
+            style: ButtonStyle::Secondary,
+            ..button(
+                AuditLogAction::Page(zpage),
+                &format!("Page {}", zpage + 1),
+                true,
+            )
+        },
+        Button {
+            emoji: Some(EmojiReactionType::Unicode {
+                name: "➡️".to_string(),
+            }),
+            ..button(AuditLogAction::Page(zpage + 1), "Next", !next_page_exists)
+        },
+        Button {
+            style: ButtonStyle::Secondary,
+            ..button(
+                AuditLogAction::Export(ExportFormat::Csv),
+                "Export CSV",
+                false,
+            )
+        },
+        Button {
+            style: ButtonStyle::Secondary,
+            ..button(
+                AuditLogAction::Export(ExportFormat::Json),
+                "Export JSON",
+                false,
+            )
+        },
+    ]
+    .map(Component::Button)
+}
 
-    let mut file = Vec::with_capacity(logs.len() * 128);
+fn audit_csv(events: Vec<AuditLogEntry>) -> Result<Vec<u8>, Error> {
+    let mut file = Vec::with_capacity(events.len() * 128);
     {
         let mut csv_writer = csv::Writer::from_writer(&mut file);
-        for log in logs {
-            csv_writer.serialize(log)?;
+        for event in events {
+            csv_writer.serialize(AuditLogRow::try_from(event)?)?;
         }
         csv_writer.flush()?;
     }
+    Ok(file)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct AuditLogFilter {
+    moderator: Option<Id<UserMarker>>,
+    user: Option<Id<UserMarker>>,
+    category: Option<AuditLogCategory>,
+    /// Earliest timestamp to include, in seconds since the discord epoch
+    since: Option<i64>,
+    /// Latest timestamp to include, in seconds since the discord epoch
+    until: Option<i64>,
+}
+
+impl AuditLogFilter {
+    fn matches(&self, event: &AuditLogEvent) -> bool {
+        self.category.is_none_or(|v| event.kind.category() == v)
+            && self.since.is_none_or(|v| event.timestamp >= v)
+            && self.until.is_none_or(|v| event.timestamp <= v)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AuditLogAction {
+    Page(usize),
+    Export(ExportFormat),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// Every audit log browser component's custom ID starts with this
+pub const CUSTOM_ID_PREFIX: &str = "audit:";
+
+// Like the leaderboard, the browser keeps no state of its own. Every button
+// carries the filters it was made with, in the form
+// `audit:<action>:<moderator>:<user>:<category>:<since>:<until>`,
+// where unset filters are left empty. The longest possible ID is still well
+// under Discord's 100 character limit.
+fn to_custom_id(action: AuditLogAction, filter: &AuditLogFilter) -> String {
+    fn opt<T: std::fmt::Display>(v: Option<T>) -> String {
+        v.map(|v| v.to_string()).unwrap_or_default()
+    }
+    let action = match action {
+        AuditLogAction::Page(zpage) => zpage.to_string(),
+        AuditLogAction::Export(ExportFormat::Csv) => "csv".to_string(),
+        AuditLogAction::Export(ExportFormat::Json) => "json".to_string(),
+    };
+    format!(
+        "{CUSTOM_ID_PREFIX}{action}:{}:{}:{}:{}:{}",
+        opt(filter.moderator),
+        opt(filter.user),
+        opt(filter.category.map(AuditLogCategory::to_i16)),
+        opt(filter.since),
+        opt(filter.until)
+    )
+}
 
-    if file.is_empty() {
-        Ok(XpdInteractionData::new()
-            .content("No moderator actions recorded yet, audit log is empty.".to_string())
-            .ephemeral(true)
-            .into_interaction_response(InteractionResponseType::ChannelMessageWithSource))
+fn parse_custom_id(custom_id: &str) -> Option<(AuditLogAction, AuditLogFilter)> {
+    fn opt<T: std::str::FromStr>(v: &str) -> Result<Option<T>, T::Err> {
+        if v.is_empty() {
+            Ok(None)
+        } else {
+            v.parse().map(Some)
+        }
+    }
+    let mut parts = custom_id.strip_prefix(CUSTOM_ID_PREFIX)?.split(':');
+    let action = match parts.next()? {
+        "csv" => AuditLogAction::Export(ExportFormat::Csv),
+        "json" => AuditLogAction::Export(ExportFormat::Json),
+        zpage => AuditLogAction::Page(zpage.parse().ok()?),
+    };
+    let filter = AuditLogFilter {
+        moderator: opt(parts.next()?).ok()?,
+        user: opt(parts.next()?).ok()?,
+        category: match opt(parts.next()?).ok()? {
+            Some(category) => Some(AuditLogCategory::from_i16(category)?),
+            None => None,
+        },
+        since: opt(parts.next()?).ok()?,
+        until: opt(parts.next()?).ok()?,
+    };
+    Some((action, filter))
+}
+
+fn retention_notice(config: &GuildConfig) -> String {
+    let compliance = if config.prune_compliance_audit_logs.unwrap_or(false) {
+        "including"
     } else {
-        let attachment = Attachment {
-            description: Some("Audit logs this server".to_owned()),
-            file,
-            filename: "audit_log.txt".to_string(),
-            id: 0,
+        "except for"
+    };
+    format!(
+        "Audit log events are kept for {} days, {compliance} compliance records like GDPR deletions.",
+        config.audit_log_retention_days()
+    )
+}
+
+const fn category_from_option(option: AuditLogCategoryOption) -> AuditLogCategory {
+    match option {
+        AuditLogCategoryOption::Xp => AuditLogCategory::Xp,
+        AuditLogCategoryOption::Config => AuditLogCategory::Config,
+        AuditLogCategoryOption::Rewards => AuditLogCategory::Rewards,
+        AuditLogCategoryOption::Data => AuditLogCategory::Data,
+        AuditLogCategoryOption::Card => AuditLogCategory::Card,
+        AuditLogCategoryOption::Compliance => AuditLogCategory::Compliance,
+    }
+}
+
+/// CSV can't hold nested data, so the details are flattened to JSON strings
+#[derive(Serialize)]
+struct AuditLogRow {
+    id: i64,
+    guild: Id<GuildMarker>,
+    target: Option<Id<UserMarker>>,
+    moderator: Id<UserMarker>,
+    timestamp: i64,
+    previous: i64,
+    delta: i64,
+    kind: AuditLogEventKind,
+    before: Option<String>,
+    after: Option<String>,
+}
+
+impl TryFrom<AuditLogEntry> for AuditLogRow {
+    type Error = serde_json::Error;
+
+    fn try_from(AuditLogEntry { id, event }: AuditLogEntry) -> Result<Self, Self::Error> {
+        let (before, after) = match event.details {
+            Some(details) => (
+                Some(serde_json::to_string(&details.before)?),
+                Some(serde_json::to_string(&details.after)?),
+            ),
+            None => (None, None),
+        };
+        Ok(Self {
+            id,
+            guild: event.guild,
+            target: event.target,
+            moderator: event.moderator,
+            timestamp: event.timestamp,
+            previous: event.previous,
+            delta: event.delta,
+            kind: event.kind,
+            before,
+            after,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_id_roundtrip() {
+        let filter = AuditLogFilter {
+            moderator: Some(Id::new(u64::MAX)),
+            user: Some(Id::new(u64::MAX)),
+            category: Some(AuditLogCategory::Compliance),
+            since: Some(i64::from(i32::MAX)),
+            until: Some(i64::from(i32::MAX)),
+        };
+        for action in [
+            AuditLogAction::Page(9999),
+            AuditLogAction::Export(ExportFormat::Json),
+        ] {
+            let id = to_custom_id(action, &filter);
+            assert!(id.len() <= 100, "{id} is too long");
+            assert_eq!(parse_custom_id(&id), Some((action, filter)));
+        }
+
+        let empty = AuditLogFilter {
+            moderator: None,
+            user: None,
+            category: None,
+            since: None,
+            until: None,
+        };
+        let id = to_custom_id(AuditLogAction::Page(0), &empty);
+        assert_eq!(parse_custom_id(&id), Some((AuditLogAction::Page(0), empty)));
+        assert_eq!(parse_custom_id("audit:nonsense"), None);
+    }
+
+    #[test]
+    fn revert_selection_and_conflicts() {
+        let entry = |id, moderator, target: Option<u64>, timestamp, kind| AuditLogEntry {
+            id,
+            event: AuditLogEvent {
+                guild: Id::new(1),
+                target: target.map(Id::new),
+                moderator: Id::new(moderator),
+                timestamp,
+                previous: 0,
+                delta: 100,
+                kind,
+                details: None,
+            },
         };
-        Ok(XpdInteractionData::new()
-            .attachments([attachment])
-            .ephemeral(true)
-            .into_interaction_response(InteractionResponseType::ChannelMessageWithSource))
+        let entries = [
+            entry(1, 10, Some(20), 1000, AuditLogEventKind::AddOrSub),
+            entry(2, 10, None, 1100, AuditLogEventKind::ConfigUpdate),
+            entry(3, 10, Some(21), 1200, AuditLogEventKind::Set),
+            entry(4, 11, Some(21), 1300, AuditLogEventKind::AddOrSub),
+        ];
+        let revert = |event, moderator: Option<u64>, newer_than_hours| AuditLogCommandRevert {
+            event,
+            moderator: moderator.map(Id::new),
+            newer_than_hours,
+        };
+
+        let (selected, skipped) = select_reverts(&revert(None, Some(10), Some(1)), &entries, 1500)
+            .expect("moderator 10 has revertable events");
+        let selected_ids: Vec<i64> = selected.iter().map(|v| v.id).collect();
+        assert_eq!((selected_ids.as_slice(), skipped), ([1, 3].as_slice(), 1));
+        // Moderator 11 changed user 21 after moderator 10 did
+        assert_eq!(revert_conflicts(&entries, &selected), [4]);
+
+        let (selected, _) = select_reverts(&revert(Some(1), None, None), &entries, 1500)
+            .expect("event 1 is revertable");
+        assert!(revert_conflicts(&entries, &selected).is_empty());
+
+        assert!(matches!(
+            select_reverts(&revert(Some(2), None, None), &entries, 1500),
+            Err(Error::EventNotRevertable)
+        ));
+        assert!(matches!(
+            select_reverts(&revert(Some(1), Some(10), None), &entries, 1500),
+            Err(Error::RevertNeedsEventOrModerator)
+        ));
+
+        let mut reverted = entries.to_vec();
+        reverted.push(AuditLogEntry {
+            id: 5,
+            event: AuditLogEvent {
+                details: Some(AuditLogChange {
+                    before: serde_json::Value::Null,
+                    after: serde_json::json!({ "reverted": 1 }),
+                }),
+                ..entry(5, 11, Some(20), 1400, AuditLogEventKind::Revert).event
+            },
+        });
+        assert!(matches!(
+            select_reverts(&revert(Some(1), None, None), &reverted, 1500),
+            Err(Error::AlreadyReverted)
+        ));
     }
 }
@@ -1,6 +1,9 @@
+use std::fmt::Write as _;
+
 use simpleinterpolation::Interpolation;
 use twilight_model::{
     channel::{ChannelType, message::MessageFlags},
+    guild::Permissions,
     http::interaction::InteractionResponseType,
     id::{
         Id,
@@ -8,31 +11,50 @@ use twilight_model::{
     },
 };
 use xpd_common::{
-    DEFAULT_MAX_XP_PER_MESSAGE, DEFAULT_MIN_XP_PER_MESSAGE, GuildConfig, TEMPLATE_VARIABLES,
+    AuditLogEventKind, DEFAULT_MAX_XP_PER_MESSAGE, DEFAULT_MIN_XP_PER_MESSAGE, GuildConfig,
+    KickBanXpPolicy, LeaderboardSchedule, LeaderboardSchedulePeriod, LeavePolicy, LevelUpDelivery,
+    LevelUpMessage, RoleUpdateFailure, ScheduledJobKind, TEMPLATE_VARIABLES,
 };
 use xpd_database::{AcquireWrapper as _, UpdateGuildConfig};
 use xpd_slash_defs::config::{
-    ConfigCommand, ConfigCommandLevels, ConfigCommandRankCard, ConfigCommandRewards,
+    ConfigCommand, ConfigCommandAudit, ConfigCommandLevelUpEmbed, ConfigCommandLevels,
+    ConfigCommandMembers, ConfigCommandRankCard, ConfigCommandRewards, ConfigCommandSchedule,
+    ConfigKickBanXpOption, ConfigLeaveXpOption, ConfigLevelUpDeliveryOption, ConfigScheduleOption,
 };
-use xpd_util::CanAddRole;
+use xpd_util::{ChannelAccess, RoleProblem};
 
-use crate::{Error, SlashState, XpdInteractionData, response::XpdInteractionResponse};
+use crate::{
+    Error, SlashState, XpdInteractionData, audit::AuditData, confirm::ConfirmAction,
+    response::XpdInteractionResponse,
+};
 
 pub async fn process_config(
     command: ConfigCommand,
     guild: Id<GuildMarker>,
+    audit: AuditData,
     state: SlashState,
 ) -> Result<XpdInteractionResponse, Error> {
     match command {
-        ConfigCommand::Reset(_) => reset_config(state, guild).await,
-        ConfigCommand::Get(_) => xpd_database::guild_config(&state.db, guild)
-            .await
-            .map(|v| v.unwrap_or_default().to_string())
-            .map_err(Into::into),
-        ConfigCommand::Rewards(r) => process_rewards_config(state, guild, r).await,
-        ConfigCommand::Levels(l) => process_levels_config(state, guild, l).await,
-        ConfigCommand::RankCard(l) => process_rank_card_config(state, guild, l).await,
+        ConfigCommand::Get(_) => return crate::config_panel::open_panel(&state, guild).await,
+        ConfigCommand::Reset(_) => {
+            return Ok(crate::confirm::prompt(
+                &state,
+                audit.invoker,
+                ConfirmAction::ResetConfig(guild),
+                "This will reset all of this server's settings to their defaults. \
+                Rewards, level-up messages and XP are kept.",
+            ));
+        }
+        ConfigCommand::Rewards(r) => process_rewards_config(state, guild, audit, r).await,
+        ConfigCommand::Levels(l) => process_levels_config(state, guild, audit, l).await,
+        ConfigCommand::LevelUpEmbed(e) => {
+            process_level_up_embed_config(state, guild, audit, e).await
+        }
+        ConfigCommand::RankCard(l) => process_rank_card_config(state, guild, audit, l).await,
         ConfigCommand::PermsCheckup(_) => process_perm_checkup(state, guild).await,
+        ConfigCommand::Schedule(s) => process_schedule_config(state, guild, audit, s).await,
+        ConfigCommand::Audit(a) => process_audit_config(state, guild, audit, a).await,
+        ConfigCommand::Members(m) => process_members_config(state, guild, audit, m).await,
     }
     .map(|s| {
         XpdInteractionData::with_embed_text(s)
@@ -41,16 +63,39 @@ pub async fn process_config(
     })
 }
 
+/// Update the guild config, and record what changed in the audit log.
+pub async fn update_config_audited(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+    new_cfg: UpdateGuildConfig,
+) -> Result<GuildConfig, Error> {
+    let mut txn = state.db.xbegin().await?;
+    let old_config = xpd_database::guild_config(&mut txn, guild_id)
+        .await?
+        .unwrap_or_default();
+    let config = xpd_database::update_guild_config(&mut txn, guild_id, new_cfg).await?;
+    validate_config(&config)?;
+    let event = audit.change_event(
+        guild_id,
+        AuditLogEventKind::ConfigUpdate,
+        &old_config,
+        &config,
+    )?;
+    xpd_database::add_audit_log_event(&mut txn, event.clone()).await?;
+    txn.commit().await?;
+    state.notify_audit_log([event]).await;
+    Ok(config)
+}
+
 async fn process_rewards_config(
     state: SlashState,
     guild_id: Id<GuildMarker>,
+    audit: AuditData,
     options: ConfigCommandRewards,
 ) -> Result<String, Error> {
     let new_cfg = UpdateGuildConfig::new().one_at_a_time(options.one_at_a_time);
-    let mut update_txn = state.db.xbegin().await?;
-    let config = xpd_database::update_guild_config(&mut update_txn, guild_id, new_cfg).await?;
-    validate_config(&config)?;
-    update_txn.commit().await?;
+    let config = update_config_audited(&state, guild_id, audit, new_cfg).await?;
     state.update_config(guild_id, config).await;
     Ok("Updated rewards config!".to_string())
 }
@@ -58,6 +103,7 @@ async fn process_rewards_config(
 async fn process_levels_config(
     state: SlashState,
     guild_id: Id<GuildMarker>,
+    audit: AuditData,
     options: ConfigCommandLevels,
 ) -> Result<String, Error> {
     let level_up_message = options.level_up_message.map(|s| s.replace("\\n", "\n"));
@@ -65,12 +111,7 @@ async fn process_levels_config(
         if interp_template.len() > 512 {
             return Err(Error::LevelUpMessageTooLong);
         }
-        let interp = Interpolation::new(interp_template.clone())?;
-        for item in interp.variables_used() {
-            if !TEMPLATE_VARIABLES.contains(&item) {
-                return Err(Error::UnknownInterpolationVariable(item.to_string()));
-            }
-        }
+        validate_template(interp_template)?;
     }
 
     if options
@@ -86,45 +127,341 @@ async fn process_levels_config(
     let message_cooldown = safecast_to_i16(options.message_cooldown)?;
 
     let new_cfg = UpdateGuildConfig {
-        level_up_message,
-        level_up_channel: options.level_up_channel.map(|v| v.id),
+        level_up_message: set_or_clear(
+            level_up_message,
+            options.clear_level_up_message,
+            "level-up message",
+        )?,
+        level_up_channel: set_or_clear(
+            options.level_up_channel.map(|v| v.id),
+            options.clear_level_up_channel,
+            "level-up channel",
+        )?,
         ping_users: options.ping_users,
         max_xp_per_message,
         min_xp_per_message,
         message_cooldown,
         one_at_a_time: None,
         guild_card_default_show_off: None,
+        audit_log_retention_days: None,
+        prune_compliance_audit_logs: None,
+        mod_log_channel: None,
+        kick_ban_xp_policy: None,
+        leave_policy: None,
+        leave_delete_after_days: None,
+        level_up_embed: None,
+        level_up_embed_title: None,
+        level_up_embed_description: None,
+        level_up_embed_color: None,
+        level_up_embed_footer: None,
+        level_up_rank_card: None,
+        level_up_delivery: options.delivery.map(|v| match v {
+            ConfigLevelUpDeliveryOption::Channel => LevelUpDelivery::Channel,
+            ConfigLevelUpDeliveryOption::Dm => LevelUpDelivery::Dm,
+        }),
     };
-    let mut validate_txn = state.db.xbegin().await?;
-    let config = xpd_database::update_guild_config(&mut validate_txn, guild_id, new_cfg).await?;
-    validate_config(&config)?;
-    validate_txn.commit().await?;
+    let config = update_config_audited(&state, guild_id, audit, new_cfg).await?;
     let msg = config.to_string();
     state.update_config(guild_id, config).await;
 
     Ok(msg)
 }
 
+/// Combine a setting's option with its `clear_` option into an update which can unset it
+#[allow(clippy::option_option)]
+fn set_or_clear<T>(
+    value: Option<T>,
+    clear: Option<bool>,
+    name: &'static str,
+) -> Result<Option<Option<T>>, Error> {
+    match (value, clear.unwrap_or(false)) {
+        (Some(_), true) => Err(Error::SetAndClear(name)),
+        (None, true) => Ok(Some(None)),
+        (value, false) => Ok(value.map(Some)),
+    }
+}
+
+/// Make sure a level-up template parses, and only uses variables we fill in
+pub fn validate_template(template: &str) -> Result<(), Error> {
+    let interp = Interpolation::new(template)?;
+    for item in interp.variables_used() {
+        if !TEMPLATE_VARIABLES.contains(&item) {
+            return Err(Error::UnknownInterpolationVariable(item.to_string()));
+        }
+    }
+    Ok(())
+}
+
+async fn process_level_up_embed_config(
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+    options: ConfigCommandLevelUpEmbed,
+) -> Result<String, Error> {
+    let [title, description, footer] = [
+        (options.title, "title", xpd_util::MAX_EMBED_TITLE_LENGTH),
+        (
+            options.description,
+            "description",
+            xpd_util::MAX_EMBED_DESCRIPTION_LENGTH,
+        ),
+        (options.footer, "footer", xpd_util::MAX_EMBED_FOOTER_LENGTH),
+    ]
+    .map(|(template, field, limit)| {
+        template
+            .map(|s| s.replace("\\n", "\n"))
+            .map(|s| validate_embed_template(&s, field, limit).map(|()| s))
+            .transpose()
+    });
+    let color = options
+        .color
+        .as_deref()
+        .map(parse_embed_color)
+        .transpose()?;
+
+    let new_cfg = UpdateGuildConfig::new()
+        .level_up_embed(options.enabled)
+        .level_up_embed_title(set_or_clear(title?, options.clear_title, "embed title")?)
+        .level_up_embed_description(set_or_clear(
+            description?,
+            options.clear_description,
+            "embed description",
+        )?)
+        .level_up_embed_color(set_or_clear(color, options.clear_color, "embed color")?)
+        .level_up_embed_footer(set_or_clear(footer?, options.clear_footer, "embed footer")?)
+        .level_up_rank_card(options.rank_card);
+    let config = update_config_audited(&state, guild_id, audit, new_cfg).await?;
+    let msg = config.to_string();
+    state.update_config(guild_id, config).await;
+    Ok(msg)
+}
+
+/// Like [`validate_template`], but also makes sure the embed `field` fits in Discord's `limit`
+/// however long the variables turn out
+fn validate_embed_template(template: &str, field: &'static str, limit: usize) -> Result<(), Error> {
+    validate_template(template)?;
+    let max_len = xpd_util::max_level_up_message_len(&Interpolation::new(template)?);
+    if max_len > limit {
+        return Err(Error::EmbedTemplateTooLong(field, max_len, limit));
+    }
+    Ok(())
+}
+
+/// Parses colors like `#FF8800`, with or without the `#`
+fn parse_embed_color(color: &str) -> Result<u32, Error> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(Error::InvalidEmbedColor);
+    }
+    u32::from_str_radix(hex, 16).map_err(|_| Error::InvalidEmbedColor)
+}
+
 async fn process_rank_card_config(
     state: SlashState,
     guild_id: Id<GuildMarker>,
+    audit: AuditData,
     options: ConfigCommandRankCard,
 ) -> Result<String, Error> {
     let new_cfg = UpdateGuildConfig::new().guild_card_default_show_off(options.show_off_by_default);
-    let mut update_txn = state.db.xbegin().await?;
-    let config = xpd_database::update_guild_config(&mut update_txn, guild_id, new_cfg).await?;
-    validate_config(&config)?;
-    update_txn.commit().await?;
+    let config = update_config_audited(&state, guild_id, audit, new_cfg).await?;
     state.update_config(guild_id, config).await;
     Ok("Updated rank card config!".to_string())
 }
 
+async fn process_audit_config(
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+    options: ConfigCommandAudit,
+) -> Result<String, Error> {
+    if options
+        .mod_log_channel
+        .as_ref()
+        .is_some_and(|v| !matches!(v.kind, ChannelType::GuildText))
+    {
+        return Err(Error::ModLogChannelMustBeText);
+    }
+    let retention_days = options.retention_days.map(TryInto::try_into).transpose()?;
+    let new_cfg = UpdateGuildConfig::new()
+        .audit_log_retention_days(set_or_clear(
+            retention_days,
+            options.clear_retention_days,
+            "retention days",
+        )?)
+        .prune_compliance_audit_logs(set_or_clear(
+            options.prune_compliance,
+            options.clear_prune_compliance,
+            "compliance record pruning",
+        )?)
+        .mod_log_channel(set_or_clear(
+            options.mod_log_channel.map(|v| v.id),
+            options.clear_mod_log_channel,
+            "mod-log channel",
+        )?);
+    let config = update_config_audited(&state, guild_id, audit, new_cfg).await?;
+    let mut msg = format!(
+        "Updated audit log config! Events are kept for {} days.",
+        config.audit_log_retention_days()
+    );
+    if let Some(channel) = config.mod_log_channel
+        && !xpd_util::can_create_message(&state.cache, state.bot_id, channel)?
+    {
+        msg.push_str(
+            "\n⚠️ I can't send messages in the mod-log channel, so events won't be posted!",
+        );
+    }
+    state.update_config(guild_id, config).await;
+    Ok(msg)
+}
+
+async fn process_members_config(
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+    options: ConfigCommandMembers,
+) -> Result<String, Error> {
+    let kick_ban_xp_policy = options.kick_ban_xp.map(|v| match v {
+        ConfigKickBanXpOption::Reset => KickBanXpPolicy::Reset,
+        ConfigKickBanXpOption::Keep => KickBanXpPolicy::Keep,
+        ConfigKickBanXpOption::SoftDelete => KickBanXpPolicy::SoftDelete,
+    });
+    let leave_policy = options.leave_xp.map(|v| match v {
+        ConfigLeaveXpOption::Keep => LeavePolicy::Keep,
+        ConfigLeaveXpOption::Delete => LeavePolicy::DeleteAfter,
+        ConfigLeaveXpOption::Hide => LeavePolicy::Hide,
+    });
+    let leave_delete_after_days = options
+        .leave_delete_after_days
+        .map(TryInto::try_into)
+        .transpose()?;
+    let new_cfg = UpdateGuildConfig::new()
+        .kick_ban_xp_policy(kick_ban_xp_policy)
+        .leave_policy(leave_policy)
+        .leave_delete_after_days(leave_delete_after_days);
+    let config = update_config_audited(&state, guild_id, audit, new_cfg).await?;
+    let msg = config.to_string();
+    state.update_config(guild_id, config).await;
+    Ok(msg)
+}
+
+async fn process_schedule_config(
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+    options: ConfigCommandSchedule,
+) -> Result<String, Error> {
+    let existing = xpd_database::leaderboard_schedule(&state.db, guild_id).await?;
+    let period = match options.period {
+        ConfigScheduleOption::Weekly => LeaderboardSchedulePeriod::Weekly,
+        ConfigScheduleOption::Monthly => LeaderboardSchedulePeriod::Monthly,
+        ConfigScheduleOption::Disabled => {
+            let mut txn = state.db.xbegin().await?;
+            let existed = xpd_database::delete_leaderboard_schedule(&mut txn, guild_id).await?;
+            xpd_database::delete_scheduled_job(
+                &mut txn,
+                ScheduledJobKind::LeaderboardPost,
+                Some(guild_id),
+            )
+            .await?;
+            let mut events = Vec::new();
+            if existed {
+                let event = audit.change_event(
+                    guild_id,
+                    AuditLogEventKind::ConfigUpdate,
+                    &existing,
+                    &None::<LeaderboardSchedule>,
+                )?;
+                xpd_database::add_audit_log_event(&mut txn, event.clone()).await?;
+                events.push(event);
+            }
+            txn.commit().await?;
+            state.notify_audit_log(events).await;
+            return Ok(if existed {
+                "Disabled scheduled leaderboard posts.".to_string()
+            } else {
+                "Scheduled leaderboard posts were already disabled.".to_string()
+            });
+        }
+    };
+
+    let channel = match (&options.channel, &existing) {
+        (Some(channel), _) => {
+            if !matches!(channel.kind, ChannelType::GuildText) {
+                return Err(Error::ScheduleChannelMustBeText);
+            }
+            channel.id
+        }
+        (None, Some(existing)) => existing.channel,
+        (None, None) => {
+            return Err(Error::WrongArgumentCount(
+                "`/config schedule` requires a channel the first time it is used!",
+            ));
+        }
+    };
+
+    let schedule = LeaderboardSchedule {
+        guild: guild_id,
+        channel,
+        period,
+        winner_card: options
+            .winner_card
+            .or_else(|| existing.map(|v| v.winner_card))
+            .unwrap_or(false),
+        mention_winners: options
+            .mention_winners
+            .or_else(|| existing.map(|v| v.mention_winners))
+            .unwrap_or(false),
+    };
+    let mut txn = state.db.xbegin().await?;
+    xpd_database::set_leaderboard_schedule(&mut txn, schedule).await?;
+    xpd_database::set_scheduled_job(
+        &mut txn,
+        ScheduledJobKind::LeaderboardPost,
+        Some(guild_id),
+        period.job_interval(),
+    )
+    .await?;
+    let event = audit.change_event(
+        guild_id,
+        AuditLogEventKind::ConfigUpdate,
+        &existing,
+        &Some(schedule),
+    )?;
+    xpd_database::add_audit_log_event(&mut txn, event.clone()).await?;
+    txn.commit().await?;
+    state.notify_audit_log([event]).await;
+
+    let mut msg = format!("Scheduled leaderboard updated! {schedule}.");
+    if !xpd_util::can_create_message(&state.cache, state.bot_id, channel)? {
+        msg.push_str("\n⚠️ I can't send messages in that channel, so posts will be skipped!");
+    }
+    Ok(msg)
+}
+
 fn safecast_to_i16(ou16: Option<i64>) -> Result<Option<i16>, Error> {
     ou16.map(TryInto::try_into).transpose().map_err(Into::into)
 }
 
-async fn reset_config(state: SlashState, guild_id: Id<GuildMarker>) -> Result<String, Error> {
-    xpd_database::delete_guild_config(&state.db, guild_id).await?;
+pub async fn reset_config(
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+) -> Result<String, Error> {
+    let mut txn = state.db.xbegin().await?;
+    let old_config = xpd_database::guild_config(&mut txn, guild_id).await?;
+    let mut events = Vec::new();
+    if xpd_database::delete_guild_config(&mut txn, guild_id).await? > 0 {
+        let event = audit.change_event(
+            guild_id,
+            AuditLogEventKind::ConfigUpdate,
+            &old_config,
+            &None::<GuildConfig>,
+        )?;
+        xpd_database::add_audit_log_event(&mut txn, event.clone()).await?;
+        events.push(event);
+    }
+    txn.commit().await?;
+    state.notify_audit_log(events).await;
     state.update_config(guild_id, GuildConfig::default()).await;
     Ok("Reset guild reward config, but NOT rewards themselves!".to_string())
 }
@@ -158,42 +495,254 @@ async fn process_perm_checkup(
     let config = xpd_database::guild_config(&state.db, guild_id)
         .await?
         .unwrap_or_default();
-    let can_msg_in_level_up = config
-        .level_up_channel
-        .map(|level_up| xpd_util::can_create_message(&state.cache, state.bot_id, level_up))
-        .transpose()?;
+    let rewards = xpd_database::guild_rewards(&state.db, guild_id).await?;
+    let rank_rewards = xpd_database::guild_rank_rewards(&state.db, guild_id).await?;
+    let schedule = xpd_database::leaderboard_schedule(&state.db, guild_id).await?;
+    let failures = xpd_database::guild_role_update_failures(&state.db, guild_id).await?;
 
-    let rewards: Vec<Id<RoleMarker>> = xpd_database::guild_rewards(&state.db, guild_id)
-        .await?
+    let mut report = String::from("**Reward roles:**");
+    let reward_labels: Vec<(Id<RoleMarker>, String)> = rewards
         .iter()
-        .map(|v| v.id)
+        .map(|v| (v.id, format!("level {}", v.requirement)))
+        .chain(
+            rank_rewards
+                .iter()
+                .map(|v| (v.id, format!("top {}", v.max_rank))),
+        )
         .collect();
+    write_role_checkup(&state, guild_id, &reward_labels, &mut report)?;
 
-    let can_add_roles = xpd_util::can_manage_roles(&state.cache, state.bot_id, guild_id, &rewards)?;
-    let good_msg_state = EmojiFormatBool(can_msg_in_level_up != Some(false));
-
-    let can_add_roles = match can_add_roles {
-        CanAddRole::Yes => "✅",
-        CanAddRole::NoManageRoles => "⚠️ No manage roles permission!",
-        CanAddRole::HighestRoleIsLowerRoleThanTarget => {
-            "⚠️ My highest role is lower than the ones I need to assign!"
+    report.push_str("\n\n**Channels:**");
+    let mut channels = Vec::new();
+    if let Some(channel) = config.level_up_channel {
+        channels.push((
+            "Level-up channel",
+            "levels",
+            channel,
+            Permissions::SEND_MESSAGES,
+        ));
+    }
+    if let Some(channel) = config.mod_log_channel {
+        let required = Permissions::SEND_MESSAGES | Permissions::EMBED_LINKS;
+        channels.push(("Mod-log channel", "audit", channel, required));
+    }
+    if let Some(schedule) = schedule {
+        let mut required = Permissions::SEND_MESSAGES;
+        if schedule.winner_card {
+            required |= Permissions::ATTACH_FILES;
         }
-        CanAddRole::RoleIsManaged => "⚠️ That role is managed by another bot.",
+        channels.push((
+            "Leaderboard channel",
+            "schedule",
+            schedule.channel,
+            required,
+        ));
+    }
+    if channels.is_empty() {
+        report.push_str("\n✅ No channels are configured");
+    }
+    for (name, command, channel, required) in channels {
+        let access = xpd_util::channel_access(&state.cache, state.bot_id, channel, required)?;
+        match access {
+            ChannelAccess::Ok => write!(report, "\n✅ {name} <#{channel}>")?,
+            ChannelAccess::Deleted => write!(
+                report,
+                "\n⚠️ The {} was deleted. Pick a new one with `/config {command}`.",
+                name.to_lowercase()
+            )?,
+            ChannelAccess::Missing(missing) => write!(
+                report,
+                "\n⚠️ I'm missing {} in the {} <#{channel}>. Give me {} in the channel's settings.",
+                permission_names(missing),
+                name.to_lowercase(),
+                if missing.bits().is_power_of_two() {
+                    "it"
+                } else {
+                    "them"
+                }
+            )?,
+        }
+    }
+
+    let level_up_messages = xpd_database::guild_level_up_messages(&state.db, guild_id).await?;
+    write_template_checkup(&config, &level_up_messages, &mut report)?;
+
+    write_role_failures(&failures, &mut report)?;
+    Ok(report)
+}
+
+fn write_template_checkup(
+    config: &GuildConfig,
+    level_up_messages: &[LevelUpMessage],
+    report: &mut String,
+) -> Result<(), Error> {
+    let embed = config.level_up_embed == Some(true);
+    // In an embed, the level-up message is the description unless one is set
+    let message_limit = if embed && config.level_up_embed_description.is_none() {
+        xpd_util::MAX_EMBED_DESCRIPTION_LENGTH
+    } else {
+        xpd_util::MAX_MESSAGE_LENGTH
     };
-    Ok(format!(
-        "Can add roles: {can_add_roles}\nCan message in level up channel: {good_msg_state}"
-    ))
+    let mut templates = vec![(
+        "Level-up messages",
+        "levels",
+        config.level_up_message.as_ref(),
+        message_limit,
+    )];
+    if embed {
+        templates.extend([
+            (
+                "Level-up embed titles",
+                "level_up_embed",
+                config.level_up_embed_title.as_ref(),
+                xpd_util::MAX_EMBED_TITLE_LENGTH,
+            ),
+            (
+                "Level-up embed descriptions",
+                "level_up_embed",
+                config.level_up_embed_description.as_ref(),
+                xpd_util::MAX_EMBED_DESCRIPTION_LENGTH,
+            ),
+            (
+                "Level-up embed footers",
+                "level_up_embed",
+                config.level_up_embed_footer.as_ref(),
+                xpd_util::MAX_EMBED_FOOTER_LENGTH,
+            ),
+        ]);
+    }
+    for (name, command, template, limit) in templates {
+        let Some(template) = template else {
+            continue;
+        };
+        let max_len = xpd_util::max_level_up_message_len(template);
+        if max_len > limit {
+            write!(
+                report,
+                "\n\n⚠️ {name} could be up to {max_len} characters long, but Discord only allows \
+                {limit}. Shorten it with `/config {command}`."
+            )?;
+        }
+    }
+    for message in level_up_messages {
+        let max_len = xpd_util::max_level_up_message_len(&message.template);
+        if max_len > message_limit {
+            write!(
+                report,
+                "\n\n⚠️ Level-up message `{}` could be up to {max_len} characters long, but \
+                Discord only allows {message_limit}. Replace it with a shorter one using \
+                `/level-up`.",
+                message.id
+            )?;
+        }
+    }
+    Ok(())
 }
 
-#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
-struct EmojiFormatBool(pub bool);
+/// How many reward role problems `/config perms_checkup` lists
+const MAX_SHOWN_ROLE_PROBLEMS: usize = 10;
+/// How many role update failures `/config perms_checkup` lists
+const MAX_SHOWN_ROLE_FAILURES: usize = 5;
 
-impl std::fmt::Display for EmojiFormatBool {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.0 {
-            f.write_str("✅")
+fn write_role_checkup(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+    rewards: &[(Id<RoleMarker>, String)],
+    report: &mut String,
+) -> Result<(), Error> {
+    let roles: Vec<Id<RoleMarker>> = rewards.iter().map(|(id, _)| *id).collect();
+    let diagnostics = xpd_util::diagnose_roles(&state.cache, state.bot_id, guild_id, &roles)?;
+    if !diagnostics.manage_roles {
+        report.push_str(
+            "\n⚠️ I don't have the Manage Roles permission. Turn it on for one of my roles in \
+            Server Settings.",
+        );
+    }
+    if diagnostics.problems.is_empty() {
+        if rewards.is_empty() {
+            report.push_str("\n✅ No reward roles are set up");
         } else {
-            f.write_str("❎")
+            write!(
+                report,
+                "\n✅ I can give out all {} reward roles",
+                rewards.len()
+            )?;
+        }
+        return Ok(());
+    }
+    let highest = diagnostics.highest_role;
+    for (role, problem) in diagnostics.problems.iter().take(MAX_SHOWN_ROLE_PROBLEMS) {
+        let label = rewards
+            .iter()
+            .find(|(id, _)| id == role)
+            .map_or("reward", |(_, label)| label.as_str());
+        match problem {
+            RoleProblem::Deleted => write!(
+                report,
+                "\n⚠️ The {label} reward role was deleted. Remove it with `/rewards remove`."
+            )?,
+            RoleProblem::Managed => write!(
+                report,
+                "\n⚠️ <@&{role}> ({label}) is managed by an integration, so nobody can give it \
+                out. Use a different role."
+            )?,
+            RoleProblem::AboveMe => write!(
+                report,
+                "\n⚠️ <@&{role}> ({label}) is above my highest role. Move my role <@&{highest}> \
+                above <@&{role}>."
+            )?,
         }
     }
+    if diagnostics.problems.len() > MAX_SHOWN_ROLE_PROBLEMS {
+        write!(
+            report,
+            "\n...and {} more",
+            diagnostics.problems.len() - MAX_SHOWN_ROLE_PROBLEMS
+        )?;
+    }
+    Ok(())
+}
+
+fn write_role_failures(failures: &[RoleUpdateFailure], report: &mut String) -> Result<(), Error> {
+    if failures.is_empty() {
+        return Ok(());
+    }
+    report.push_str("\n\n**Recent role update failures:**");
+    for failure in failures.iter().take(MAX_SHOWN_ROLE_FAILURES) {
+        write!(
+            report,
+            "\n<@{}>: {} <t:{}:R>",
+            failure.user, failure.reason, failure.failed_at
+        )?;
+    }
+    if failures.len() > MAX_SHOWN_ROLE_FAILURES {
+        write!(
+            report,
+            "\n...and {} more",
+            failures.len() - MAX_SHOWN_ROLE_FAILURES
+        )?;
+    }
+    Ok(())
+}
+
+/// Turns `SEND_MESSAGES | EMBED_LINKS` into "Send Messages and Embed Links"
+fn permission_names(permissions: Permissions) -> String {
+    let names: Vec<String> = permissions
+        .iter_names()
+        .map(|(name, _)| {
+            name.split('_')
+                .map(|word| {
+                    let mut chars = word.chars();
+                    chars.next().map_or_else(String::new, |first| {
+                        first.to_string() + &chars.as_str().to_lowercase()
+                    })
+                })
+                .collect::<Vec<String>>()
+                .join(" ")
+        })
+        .collect();
+    match names.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} and {last}", rest.join(", ")),
+        _ => names.concat(),
+    }
 }
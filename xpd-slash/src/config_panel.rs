@@ -0,0 +1,597 @@
+use simpleinterpolation::Interpolation;
+use twilight_model::{
+    application::interaction::{
+        message_component::MessageComponentInteractionData,
+        modal::{ModalInteractionComponent, ModalInteractionData},
+    },
+    channel::{
+        ChannelType,
+        message::{
+            AllowedMentions, Component,
+            component::{
+                ActionRow, Button, ButtonStyle, Label, SelectDefaultValue, SelectMenu,
+                SelectMenuOption, SelectMenuType, TextInput, TextInputStyle,
+            },
+        },
+    },
+    http::interaction::InteractionResponseType,
+    id::{
+        Id,
+        marker::{ChannelMarker, GuildMarker},
+    },
+};
+use twilight_util::builder::embed::{EmbedBuilder, EmbedFieldBuilder};
+use xpd_common::{
+    DEFAULT_MAX_XP_PER_MESSAGE, DEFAULT_MESSAGE_COOLDOWN, DEFAULT_MIN_XP_PER_MESSAGE, GuildConfig,
+    LevelUpDelivery, MAX_MESSAGE_COOLDOWN,
+};
+use xpd_database::UpdateGuildConfig;
+
+use crate::{
+    Error, SlashState, XpdInteractionData, audit::AuditData, config::update_config_audited,
+    response::XpdInteractionResponse,
+};
+
+/// Every component and modal in the config panel has a custom ID starting with this
+pub const CUSTOM_ID_PREFIX: &str = "config:";
+
+/// Text input IDs inside the panel's modals
+const MESSAGE_INPUT: &str = "message";
+const MIN_XP_INPUT: &str = "min_xp";
+const MAX_XP_INPUT: &str = "max_xp";
+const COOLDOWN_INPUT: &str = "cooldown";
+
+/// Something the config panel can do. The panel is always drawn from the
+/// saved config, so like the leaderboard, its custom IDs need no state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PanelAction {
+    /// Channel select for where level-ups are announced, which can be emptied to unset it
+    LevelUpChannel,
+    /// Channel select for where audit log events are posted, which can be emptied to unset it
+    ModLogChannel,
+    /// String select for how level-ups are delivered, and whether they ping
+    Announcements,
+    /// String select for whether members keep every reward role
+    Rewards,
+    /// Button which opens the level-up message modal, and that modal's submission
+    Message,
+    /// Button which opens the XP and cooldown modal, and that modal's submission
+    Xp,
+    /// Button which redraws the panel from the saved config
+    Refresh,
+}
+
+impl PanelAction {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::LevelUpChannel => "level_up_channel",
+            Self::ModLogChannel => "mod_log_channel",
+            Self::Announcements => "announcements",
+            Self::Rewards => "rewards",
+            Self::Message => "message",
+            Self::Xp => "xp",
+            Self::Refresh => "refresh",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [
+            Self::LevelUpChannel,
+            Self::ModLogChannel,
+            Self::Announcements,
+            Self::Rewards,
+            Self::Message,
+            Self::Xp,
+            Self::Refresh,
+        ]
+        .into_iter()
+        .find(|action| action.name() == name)
+    }
+}
+
+fn to_custom_id(action: PanelAction) -> String {
+    format!("{CUSTOM_ID_PREFIX}{}", action.name())
+}
+
+fn parse_custom_id(custom_id: &str) -> Option<PanelAction> {
+    PanelAction::from_name(custom_id.strip_prefix(CUSTOM_ID_PREFIX)?)
+}
+
+/// `/config get`: show the guild's config, with controls to change the common settings in place
+pub async fn open_panel(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+) -> Result<XpdInteractionResponse, Error> {
+    Ok(panel(state, guild_id)
+        .await?
+        .ephemeral(true)
+        .into_interaction_response(InteractionResponseType::ChannelMessageWithSource))
+}
+
+pub async fn process_panel_component(
+    data: MessageComponentInteractionData,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+    state: SlashState,
+) -> Result<XpdInteractionResponse, Error> {
+    // The panel is always ephemeral, and /config is limited to administrators,
+    // so only an administrator who opened it can use it.
+    let action = parse_custom_id(&data.custom_id).ok_or(Error::InvalidComponentId)?;
+    let new_cfg = match action {
+        PanelAction::LevelUpChannel => {
+            let channel = selected_text_channel(&data, Error::LevelUpChannelMustBeText)?;
+            UpdateGuildConfig::new().level_up_channel(Some(channel))
+        }
+        PanelAction::ModLogChannel => {
+            let channel = selected_text_channel(&data, Error::ModLogChannelMustBeText)?;
+            UpdateGuildConfig::new().mod_log_channel(Some(channel))
+        }
+        PanelAction::Announcements => {
+            let (delivery, ping) = match selected_value(&data)? {
+                "chat_ping" => (LevelUpDelivery::Channel, Some(true)),
+                "chat" => (LevelUpDelivery::Channel, Some(false)),
+                "dm" => (LevelUpDelivery::Dm, None),
+                _ => return Err(Error::InvalidComponentId),
+            };
+            UpdateGuildConfig::new()
+                .level_up_delivery(Some(delivery))
+                .ping_users(ping)
+        }
+        PanelAction::Rewards => {
+            let one_at_a_time = match selected_value(&data)? {
+                "all" => false,
+                "highest" => true,
+                _ => return Err(Error::InvalidComponentId),
+            };
+            UpdateGuildConfig::new().one_at_a_time(Some(one_at_a_time))
+        }
+        PanelAction::Message => return message_modal(&state, guild_id).await,
+        PanelAction::Xp => return xp_modal(&state, guild_id).await,
+        PanelAction::Refresh => return redraw(&state, guild_id).await,
+    };
+    let config = update_config_audited(&state, guild_id, audit, new_cfg).await?;
+    state.update_config(guild_id, config).await;
+    redraw(&state, guild_id).await
+}
+
+pub async fn process_panel_modal(
+    data: ModalInteractionData,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+    state: SlashState,
+) -> Result<XpdInteractionResponse, Error> {
+    let new_cfg = match parse_custom_id(&data.custom_id) {
+        Some(PanelAction::Message) => {
+            let message = modal_value(&data, MESSAGE_INPUT)?;
+            if message.chars().count() > 512 {
+                return Err(Error::LevelUpMessageTooLong);
+            }
+            crate::config::validate_template(message)?;
+            UpdateGuildConfig::new().level_up_message(Some(Some(message.to_string())))
+        }
+        Some(PanelAction::Xp) => {
+            let xp = |input| {
+                modal_value(&data, input)?
+                    .trim()
+                    .parse::<i16>()
+                    .ok()
+                    .filter(|v| *v >= 0)
+                    .ok_or(Error::XpPerMessageOutOfRange)
+            };
+            let cooldown = modal_value(&data, COOLDOWN_INPUT)?
+                .trim()
+                .parse::<i16>()
+                .ok()
+                .filter(|v| (0..=MAX_MESSAGE_COOLDOWN).contains(v))
+                .ok_or(Error::CooldownOutOfRange)?;
+            UpdateGuildConfig::new()
+                .min_xp_per_message(Some(xp(MIN_XP_INPUT)?))
+                .max_xp_per_message(Some(xp(MAX_XP_INPUT)?))
+                .message_cooldown(Some(cooldown))
+        }
+        _ => return Err(Error::InvalidComponentId),
+    };
+    let config = update_config_audited(&state, guild_id, audit, new_cfg).await?;
+    state.update_config(guild_id, config).await;
+    // Both modals are opened from the panel, so submitting one can update it
+    redraw(&state, guild_id).await
+}
+
+async fn redraw(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+) -> Result<XpdInteractionResponse, Error> {
+    Ok(XpdInteractionResponse::new(
+        InteractionResponseType::UpdateMessage,
+        panel(state, guild_id).await?,
+    ))
+}
+
+async fn panel(state: &SlashState, guild_id: Id<GuildMarker>) -> Result<XpdInteractionData, Error> {
+    let config = xpd_database::guild_config(&state.db, guild_id)
+        .await?
+        .unwrap_or_default();
+    let schedule = xpd_database::leaderboard_schedule(&state.db, guild_id).await?;
+    let schedule = schedule.map_or_else(|| "unset".to_string(), |v| v.to_string());
+
+    let min_xp = config
+        .min_xp_per_message
+        .unwrap_or(DEFAULT_MIN_XP_PER_MESSAGE);
+    let max_xp = config
+        .max_xp_per_message
+        .unwrap_or(DEFAULT_MAX_XP_PER_MESSAGE);
+    let cooldown = config.cooldown.unwrap_or(DEFAULT_MESSAGE_COOLDOWN);
+    let level_up_message = config.level_up_message.as_ref().map_or_else(
+        || "unset".to_string(),
+        |v| format!("```\n{}\n```", v.input_value()),
+    );
+    let rewards = if config.one_at_a_time.is_some_and(|v| v) {
+        "Members only keep their highest reward role"
+    } else {
+        "Members keep every reward role they earn"
+    };
+
+    let embed = EmbedBuilder::new()
+        .title("Server configuration")
+        .description(
+            "Change these with the menus and buttons below. \
+            Everything else can be changed with the other `/config` commands.",
+        )
+        .field(EmbedFieldBuilder::new("Level-up message", level_up_message))
+        .field(
+            EmbedFieldBuilder::new(
+                "Level-up channel",
+                channel_or(
+                    state,
+                    config.level_up_channel,
+                    "Wherever the member leveled up",
+                ),
+            )
+            .inline(),
+        )
+        .field(EmbedFieldBuilder::new("Announcements", announcements(&config).1).inline())
+        .field(
+            EmbedFieldBuilder::new(
+                "Mod-log channel",
+                channel_or(state, config.mod_log_channel, "unset"),
+            )
+            .inline(),
+        )
+        .field(EmbedFieldBuilder::new("XP per message", format!("{min_xp} to {max_xp}")).inline())
+        .field(EmbedFieldBuilder::new("Cooldown", format!("{cooldown} seconds")).inline())
+        .field(EmbedFieldBuilder::new("Scheduled leaderboard", schedule).inline())
+        .field(EmbedFieldBuilder::new("Reward roles", rewards))
+        .build();
+
+    Ok(XpdInteractionData::new()
+        .allowed_mentions(AllowedMentions::default())
+        .embeds([embed])
+        .components(panel_components(&config)))
+}
+
+/// Mention a channel, warning if we can't post in it
+fn channel_or(state: &SlashState, channel: Option<Id<ChannelMarker>>, unset: &str) -> String {
+    let Some(channel) = channel else {
+        return unset.to_string();
+    };
+    if xpd_util::can_create_message(&state.cache, state.bot_id, channel).is_ok_and(|can| !can) {
+        format!("<#{channel}>\n⚠️ I can't send messages here!")
+    } else {
+        format!("<#{channel}>")
+    }
+}
+
+/// The selected announcement option's value, and a description of it
+fn announcements(config: &GuildConfig) -> (&'static str, &'static str) {
+    match config.level_up_delivery() {
+        LevelUpDelivery::Dm => ("dm", "In DMs"),
+        LevelUpDelivery::Channel if config.ping_on_level_up == Some(false) => {
+            ("chat", "In chat, without a ping")
+        }
+        LevelUpDelivery::Channel => ("chat_ping", "In chat, pinging the member"),
+    }
+}
+
+fn panel_components(config: &GuildConfig) -> [Component; 5] {
+    let button = |action, label: &str, style| {
+        Component::Button(Button {
+            custom_id: Some(to_custom_id(action)),
+            disabled: false,
+            emoji: None,
+            label: Some(label.to_string()),
+            style,
+            url: None,
+            sku_id: None,
+            id: None,
+        })
+    };
+    let rewards = if config.one_at_a_time.is_some_and(|v| v) {
+        "highest"
+    } else {
+        "all"
+    };
+
+    [
+        vec![channel_select(
+            PanelAction::LevelUpChannel,
+            "Choose a level-up channel, or none to announce where members level up",
+            config.level_up_channel,
+        )],
+        vec![channel_select(
+            PanelAction::ModLogChannel,
+            "Choose a mod-log channel, or none to stop posting events",
+            config.mod_log_channel,
+        )],
+        vec![string_select(
+            PanelAction::Announcements,
+            "How level-ups are announced",
+            &[
+                ("chat_ping", "In chat, pinging the member"),
+                ("chat", "In chat, without a ping"),
+                ("dm", "In DMs"),
+            ],
+            announcements(config).0,
+        )],
+        vec![string_select(
+            PanelAction::Rewards,
+            "Which reward roles members keep",
+            &[
+                ("all", "Keep every reward role earned"),
+                ("highest", "Only keep the highest reward role"),
+            ],
+            rewards,
+        )],
+        vec![
+            button(
+                PanelAction::Message,
+                "Edit level-up message",
+                ButtonStyle::Primary,
+            ),
+            button(
+                PanelAction::Xp,
+                "Edit XP and cooldown",
+                ButtonStyle::Primary,
+            ),
+            button(PanelAction::Refresh, "Refresh", ButtonStyle::Secondary),
+        ],
+    ]
+    .map(|components| {
+        Component::ActionRow(ActionRow {
+            id: None,
+            components,
+        })
+    })
+}
+
+fn channel_select(
+    action: PanelAction,
+    placeholder: &str,
+    current: Option<Id<ChannelMarker>>,
+) -> Component {
+    Component::SelectMenu(SelectMenu {
+        id: None,
+        channel_types: Some(vec![ChannelType::GuildText]),
+        custom_id: to_custom_id(action),
+        default_values: current.map(|v| vec![SelectDefaultValue::Channel(v)]),
+        disabled: false,
+        kind: SelectMenuType::Channel,
+        max_values: Some(1),
+        min_values: Some(0),
+        options: None,
+        placeholder: Some(placeholder.to_string()),
+        required: None,
+    })
+}
+
+/// A select of `(value, label)` choices, with `current` selected
+fn string_select(
+    action: PanelAction,
+    placeholder: &str,
+    choices: &[(&str, &str)],
+    current: &str,
+) -> Component {
+    let options = choices
+        .iter()
+        .map(|(value, label)| SelectMenuOption {
+            default: *value == current,
+            description: None,
+            emoji: None,
+            label: (*label).to_string(),
+            value: (*value).to_string(),
+        })
+        .collect();
+    Component::SelectMenu(SelectMenu {
+        id: None,
+        channel_types: None,
+        custom_id: to_custom_id(action),
+        default_values: None,
+        disabled: false,
+        kind: SelectMenuType::Text,
+        max_values: Some(1),
+        min_values: Some(1),
+        options: Some(options),
+        placeholder: Some(placeholder.to_string()),
+        required: None,
+    })
+}
+
+async fn message_modal(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+) -> Result<XpdInteractionResponse, Error> {
+    let config = xpd_database::guild_config(&state.db, guild_id)
+        .await?
+        .unwrap_or_default();
+    let current = config
+        .level_up_message
+        .as_ref()
+        .map(Interpolation::input_value);
+    let field = text_field(
+        MESSAGE_INPUT,
+        "Level-up message",
+        Some("Variables like {user_mention} and {level} are filled in when it's sent."),
+        TextInputStyle::Paragraph,
+        512,
+        current,
+    );
+    Ok(modal(
+        PanelAction::Message,
+        "Edit level-up message",
+        [field],
+    ))
+}
+
+async fn xp_modal(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+) -> Result<XpdInteractionResponse, Error> {
+    let config = xpd_database::guild_config(&state.db, guild_id)
+        .await?
+        .unwrap_or_default();
+    let number = |custom_id, label, description, value: i16| {
+        text_field(
+            custom_id,
+            label,
+            Some(description),
+            TextInputStyle::Short,
+            5,
+            Some(value.to_string()),
+        )
+    };
+    let fields = [
+        number(
+            MIN_XP_INPUT,
+            "Minimum XP per message",
+            "From 0 to 32767",
+            config
+                .min_xp_per_message
+                .unwrap_or(DEFAULT_MIN_XP_PER_MESSAGE),
+        ),
+        number(
+            MAX_XP_INPUT,
+            "Maximum XP per message",
+            "From 0 to 32767, and at least the minimum",
+            config
+                .max_xp_per_message
+                .unwrap_or(DEFAULT_MAX_XP_PER_MESSAGE),
+        ),
+        number(
+            COOLDOWN_INPUT,
+            "Cooldown",
+            "Seconds between messages that earn XP, from 0 to 28800",
+            config.cooldown.unwrap_or(DEFAULT_MESSAGE_COOLDOWN),
+        ),
+    ];
+    Ok(modal(PanelAction::Xp, "Edit XP and cooldown", fields))
+}
+
+fn modal(
+    action: PanelAction,
+    title: &str,
+    fields: impl Into<Vec<Component>>,
+) -> XpdInteractionResponse {
+    XpdInteractionResponse::new(
+        InteractionResponseType::Modal,
+        XpdInteractionData::new()
+            .components(fields)
+            .custom_id(to_custom_id(action))
+            .title(title.to_string()),
+    )
+}
+
+fn text_field(
+    custom_id: &str,
+    label: &str,
+    description: Option<&str>,
+    style: TextInputStyle,
+    max_length: u16,
+    value: Option<String>,
+) -> Component {
+    Component::Label(Label {
+        id: None,
+        label: label.to_string(),
+        description: description.map(ToString::to_string),
+        component: Box::new(Component::TextInput(TextInput {
+            custom_id: custom_id.to_string(),
+            #[expect(deprecated)]
+            label: None,
+            max_length: Some(max_length),
+            min_length: Some(1),
+            placeholder: None,
+            required: Some(true),
+            style,
+            value,
+            id: None,
+        })),
+    })
+}
+
+fn selected_value(data: &MessageComponentInteractionData) -> Result<&str, Error> {
+    data.values
+        .first()
+        .map(String::as_str)
+        .ok_or(Error::InvalidComponentId)
+}
+
+/// The channel picked in a channel select, or `None` if it was emptied
+fn selected_text_channel(
+    data: &MessageComponentInteractionData,
+    not_text: Error,
+) -> Result<Option<Id<ChannelMarker>>, Error> {
+    let Some(channel) = data.values.first() else {
+        return Ok(None);
+    };
+    let channel: Id<ChannelMarker> = channel.parse().map_err(|_| Error::InvalidComponentId)?;
+    // The select only offers text channels, but check what Discord resolved anyway
+    let kind = data
+        .resolved
+        .as_ref()
+        .and_then(|resolved| resolved.channels.get(&channel))
+        .ok_or(Error::NoResolvedData)?
+        .kind;
+    if kind != ChannelType::GuildText {
+        return Err(not_text);
+    }
+    Ok(Some(channel))
+}
+
+fn modal_value<'a>(data: &'a ModalInteractionData, custom_id: &str) -> Result<&'a str, Error> {
+    data.components
+        .iter()
+        .find_map(|component| {
+            let ModalInteractionComponent::Label(label) = component else {
+                return None;
+            };
+            match label.component.as_ref() {
+                ModalInteractionComponent::TextInput(input) if input.custom_id == custom_id => {
+                    Some(input.value.as_str())
+                }
+                _ => None,
+            }
+        })
+        .ok_or(Error::NoFormInput)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_id_roundtrip() {
+        for action in [
+            PanelAction::LevelUpChannel,
+            PanelAction::ModLogChannel,
+            PanelAction::Announcements,
+            PanelAction::Rewards,
+            PanelAction::Message,
+            PanelAction::Xp,
+            PanelAction::Refresh,
+        ] {
+            let custom_id = to_custom_id(action);
+            assert!(custom_id.len() <= 100);
+            assert_eq!(parse_custom_id(&custom_id), Some(action));
+        }
+        assert_eq!(parse_custom_id("config:nonsense"), None);
+        assert_eq!(parse_custom_id("audit:0:::::"), None);
+    }
+}
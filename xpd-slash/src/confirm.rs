@@ -0,0 +1,210 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use twilight_model::{
+    channel::message::{
+        AllowedMentions, Component,
+        component::{ActionRow, Button, ButtonStyle},
+    },
+    http::interaction::InteractionResponseType,
+    id::{
+        Id,
+        marker::{GuildMarker, UserMarker},
+    },
+};
+use twilight_util::builder::embed::EmbedBuilder;
+
+use crate::{
+    Error, SlashState, XpdInteractionData, audit::AuditData, dispatch::Respondable,
+    response::XpdInteractionResponse,
+};
+
+/// Every confirmation button's custom ID starts with this
+pub const CUSTOM_ID_PREFIX: &str = "confirm:";
+
+/// How long the buttons work for, after which the command has to be run again
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_mins(5);
+
+/// Something destructive, waiting for its invoker to press Confirm
+#[derive(Clone, Copy, Debug)]
+pub enum ConfirmAction {
+    /// `/manage reset`
+    ResetGuild(Id<GuildMarker>),
+    /// `/xp reset`
+    ResetUser(Id<GuildMarker>, Id<UserMarker>),
+    /// `/config reset`
+    ResetConfig(Id<GuildMarker>),
+    /// `/gdpr delete`, of the invoker's own data
+    GdprDelete,
+}
+
+#[derive(Debug)]
+struct PendingConfirmation {
+    invoker: Id<UserMarker>,
+    action: ConfirmAction,
+    created_at: Instant,
+}
+
+impl PendingConfirmation {
+    fn expired(&self) -> bool {
+        self.created_at.elapsed() >= CONFIRMATION_TIMEOUT
+    }
+}
+
+/// Confirmations which haven't been answered yet, by token. Only the token goes in the
+/// button's custom ID, so what's being confirmed can't be changed by the client.
+#[derive(Clone, Debug, Default)]
+pub struct Confirmations(Arc<DashMap<u64, PendingConfirmation>>);
+
+impl Confirmations {
+    fn insert(&self, invoker: Id<UserMarker>, action: ConfirmAction) -> u64 {
+        // Nobody answers most of these, so clear out the old ones as new ones come in
+        self.0.retain(|_, pending| !pending.expired());
+        let pending = PendingConfirmation {
+            invoker,
+            action,
+            created_at: Instant::now(),
+        };
+        loop {
+            let token = rand::random();
+            if let dashmap::Entry::Vacant(entry) = self.0.entry(token) {
+                entry.insert(pending);
+                return token;
+            }
+        }
+    }
+}
+
+/// Ask the invoker to confirm `action` before doing it. `warning` should say what will be lost.
+pub fn prompt(
+    state: &SlashState,
+    invoker: Id<UserMarker>,
+    action: ConfirmAction,
+    warning: &str,
+) -> XpdInteractionResponse {
+    let token = state.confirmations.insert(invoker, action);
+    let button = |answer, label: &str, style| {
+        Component::Button(Button {
+            custom_id: Some(to_custom_id(token, answer)),
+            disabled: false,
+            emoji: None,
+            label: Some(label.to_string()),
+            style,
+            url: None,
+            sku_id: None,
+            id: None,
+        })
+    };
+    let buttons = Component::ActionRow(ActionRow {
+        components: vec![
+            button(true, "Confirm", ButtonStyle::Danger),
+            button(false, "Cancel", ButtonStyle::Secondary),
+        ],
+        id: None,
+    });
+    let description = format!(
+        "{warning}\nThis can't be undone. These buttons stop working after {} minutes.",
+        CONFIRMATION_TIMEOUT.as_secs() / 60
+    );
+    XpdInteractionData::new()
+        .allowed_mentions(AllowedMentions::default())
+        .embeds([EmbedBuilder::new().description(description).build()])
+        .components([buttons])
+        .ephemeral(true)
+        .into_interaction_response(InteractionResponseType::ChannelMessageWithSource)
+}
+
+pub async fn process_confirm_component(
+    custom_id: &str,
+    invoker: Id<UserMarker>,
+    respondable: Respondable,
+    state: SlashState,
+) -> Result<XpdInteractionResponse, Error> {
+    let (token, confirmed) = parse_custom_id(custom_id).ok_or(Error::InvalidComponentId)?;
+
+    let Some((_, pending)) = state
+        .confirmations
+        .0
+        .remove_if(&token, |_, pending| pending.invoker == invoker)
+    else {
+        return if state.confirmations.0.contains_key(&token) {
+            Err(Error::NotYourConfirmation)
+        } else {
+            Ok(answered(
+                "This confirmation has expired. Run the command again.",
+            ))
+        };
+    };
+    if pending.expired() {
+        return Ok(answered(
+            "This confirmation has expired. Run the command again.",
+        ));
+    }
+    if !confirmed {
+        return Ok(answered("Cancelled. Nothing was changed."));
+    }
+
+    let audit = AuditData {
+        interaction: respondable.id(),
+        invoker,
+    };
+    let contents = match pending.action {
+        ConfirmAction::ResetGuild(guild) => {
+            crate::manager::reset_guild_xp(state, guild, audit).await?
+        }
+        ConfirmAction::ResetUser(guild, user) => {
+            crate::experience::reset_user_xp(state, guild, user, audit).await?
+        }
+        ConfirmAction::ResetConfig(guild) => {
+            crate::config::reset_config(state, guild, audit).await?
+        }
+        ConfirmAction::GdprDelete => {
+            crate::gdpr::delete_user_data(state, invoker, respondable.id()).await?
+        }
+    };
+    Ok(answered(contents))
+}
+
+// `confirm:<token>:<yes|no>`, with the token in hex
+fn to_custom_id(token: u64, confirmed: bool) -> String {
+    let answer = if confirmed { "yes" } else { "no" };
+    format!("{CUSTOM_ID_PREFIX}{token:x}:{answer}")
+}
+
+fn parse_custom_id(custom_id: &str) -> Option<(u64, bool)> {
+    let (token, answer) = custom_id.strip_prefix(CUSTOM_ID_PREFIX)?.split_once(':')?;
+    let confirmed = match answer {
+        "yes" => true,
+        "no" => false,
+        _ => return None,
+    };
+    Some((u64::from_str_radix(token, 16).ok()?, confirmed))
+}
+
+/// Replace the prompt, buttons and all, with the outcome
+fn answered(contents: impl Into<String>) -> XpdInteractionResponse {
+    XpdInteractionData::new()
+        .allowed_mentions(AllowedMentions::default())
+        .embeds([EmbedBuilder::new().description(contents).build()])
+        .components([])
+        .into_interaction_response(InteractionResponseType::UpdateMessage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_id_roundtrip() {
+        for (token, confirmed) in [(0, true), (u64::MAX, false)] {
+            let id = to_custom_id(token, confirmed);
+            assert_eq!(parse_custom_id(&id), Some((token, confirmed)));
+        }
+        assert_eq!(parse_custom_id("confirm:zz:yes"), None);
+        assert_eq!(parse_custom_id("confirm:ff:maybe"), None);
+        assert_eq!(parse_custom_id("audit:ff:yes"), None);
+    }
+}
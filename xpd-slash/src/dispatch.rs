@@ -8,7 +8,7 @@ use twilight_model::{
     },
     id::{
         Id,
-        marker::{GuildMarker, InteractionMarker},
+        marker::{ChannelMarker, GuildMarker, InteractionMarker},
     },
 };
 use xpd_common::MemberDisplayInfo;
@@ -19,14 +19,16 @@ use xpd_slash_defs::{
     config::ConfigCommand,
     experience::XpCommand,
     gdpr::GdprCommand,
+    level_up::LevelUpCommand,
     levels::{LeaderboardCommand, RankCommand},
     manage::ManageCommand,
     rewards::RewardsCommand,
+    settings::SettingsCommand,
 };
 
 use crate::{
     Error, SlashState,
-    experience::XpAuditData,
+    audit::AuditData,
     leaderboard::{process_message_component, process_modal_submit},
     response::XpdInteractionResponse,
 };
@@ -35,6 +37,7 @@ use crate::{
 pub struct Respondable {
     token: String,
     id: Id<InteractionMarker>,
+    channel: Option<Id<ChannelMarker>>,
 }
 
 impl Respondable {
@@ -46,6 +49,11 @@ impl Respondable {
     pub const fn id(&self) -> Id<InteractionMarker> {
         self.id
     }
+
+    /// The channel the interaction was used in, for when the token has expired
+    pub const fn channel(&self) -> Option<Id<ChannelMarker>> {
+        self.channel
+    }
 }
 
 pub async fn process(
@@ -56,6 +64,7 @@ pub async fn process(
     let respondable = Respondable {
         token: interaction.token.clone(),
         id: interaction.id,
+        channel: interaction.channel.as_ref().map(|channel| channel.id),
     };
     let Some(data) = interaction.data else {
         return Err(Error::NoInteractionData);
@@ -84,26 +93,101 @@ pub async fn process(
             process_app_cmd(state, *cmd, respondable, invoker, guild_id).await
         }
         InteractionData::MessageComponent(mcd) => {
-            let Some(original_msg) = interaction.message else {
-                return Err(Error::NoInteractionMessage);
+            let audit = AuditData {
+                interaction: respondable.id,
+                invoker: invoker.id,
             };
-            process_message_component(
-                *mcd,
-                original_msg,
-                guild_id.ok_or(Error::NoGuildId)?,
-                invoker.id,
-                state,
-                respondable,
-            )
-            .await
+            match ComponentNamespace::of(&mcd.custom_id) {
+                ComponentNamespace::Confirm => {
+                    crate::confirm::process_confirm_component(
+                        &mcd.custom_id,
+                        invoker.id,
+                        respondable,
+                        state,
+                    )
+                    .await
+                }
+                ComponentNamespace::Audit => {
+                    crate::audit::process_audit_component(
+                        &mcd.custom_id,
+                        guild_id.ok_or(Error::NoGuildId)?,
+                        state,
+                    )
+                    .await
+                }
+                ComponentNamespace::Config => {
+                    crate::config_panel::process_panel_component(
+                        *mcd,
+                        guild_id.ok_or(Error::NoGuildId)?,
+                        audit,
+                        state,
+                    )
+                    .await
+                }
+                ComponentNamespace::Leaderboard => {
+                    let Some(original_msg) = interaction.message else {
+                        return Err(Error::NoInteractionMessage);
+                    };
+                    process_message_component(
+                        *mcd,
+                        original_msg,
+                        guild_id.ok_or(Error::NoGuildId)?,
+                        invoker.id,
+                        state,
+                        respondable,
+                    )
+                    .await
+                }
+            }
         }
         InteractionData::ModalSubmit(mid) => {
-            process_modal_submit(*mid, guild_id.ok_or(Error::NoGuildId)?, state).await
+            let guild_id = guild_id.ok_or(Error::NoGuildId)?;
+            let audit = AuditData {
+                interaction: respondable.id,
+                invoker: invoker.id,
+            };
+            match ComponentNamespace::of(&mid.custom_id) {
+                ComponentNamespace::Config => {
+                    crate::config_panel::process_panel_modal(*mid, guild_id, audit, state).await
+                }
+                ComponentNamespace::Leaderboard => {
+                    process_modal_submit(*mid, guild_id, state).await
+                }
+                ComponentNamespace::Confirm | ComponentNamespace::Audit => {
+                    Err(Error::InvalidComponentId)
+                }
+            }
         }
         _ => Err(Error::NoInteractionData),
     }
 }
 
+/// Which feature a message component or modal belongs to. Every custom ID starts with
+/// its feature's namespace, like the `config:` in `config:rewards`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ComponentNamespace {
+    Confirm,
+    Audit,
+    Config,
+    Leaderboard,
+}
+
+impl ComponentNamespace {
+    fn of(custom_id: &str) -> Self {
+        if custom_id.starts_with(crate::confirm::CUSTOM_ID_PREFIX) {
+            Self::Confirm
+        } else if custom_id.starts_with(crate::audit::CUSTOM_ID_PREFIX) {
+            Self::Audit
+        } else if custom_id.starts_with(crate::config_panel::CUSTOM_ID_PREFIX) {
+            Self::Config
+        } else {
+            // Leaderboards sent before custom IDs were namespaced have bare IDs,
+            // like `3` or `jump_modal`, so anything else is treated as one.
+            Self::Leaderboard
+        }
+    }
+}
+
 async fn process_app_cmd(
     state: SlashState,
     data: CommandData,
@@ -133,6 +217,10 @@ async fn process_slash_cmd(
     invoker: MemberDisplayInfo,
     state: SlashState,
 ) -> Result<XpdInteractionResponse, Error> {
+    let audit = AuditData {
+        interaction: respondable.id,
+        invoker: invoker.id,
+    };
     match data.name.as_str() {
         "help" => Ok(crate::help::help()),
         "rank" => {
@@ -168,10 +256,7 @@ async fn process_slash_cmd(
                 XpCommand::from_interaction(data.into())?,
                 state,
                 guild_id.ok_or(Error::NoGuildId)?,
-                XpAuditData {
-                    interaction: respondable.id,
-                    invoker: invoker.id,
-                },
+                audit,
             )
             .await
         }
@@ -179,6 +264,7 @@ async fn process_slash_cmd(
             crate::config::process_config(
                 ConfigCommand::from_interaction(data.into())?,
                 guild_id.ok_or(Error::NoGuildId)?,
+                audit,
                 state,
             )
             .await
@@ -193,9 +279,10 @@ async fn process_slash_cmd(
             .await
         }
         "audit" => {
-            crate::audit::process_audit_logs(
+            crate::audit::process_audit(
                 AuditLogCommand::from_interaction(data.into())?,
                 guild_id.ok_or(Error::NoGuildId)?,
+                audit,
                 state,
             )
             .await
@@ -213,6 +300,7 @@ async fn process_slash_cmd(
             GuildCardCommand::from_interaction(data.into())?,
             &state,
             guild_id.ok_or(Error::NoGuildId)?,
+            audit,
         )
         .await?),
         "gdpr" => {
@@ -227,19 +315,37 @@ async fn process_slash_cmd(
             )
             .await
         }
-        "manage" => {
-            crate::manager::process_manage(
-                ManageCommand::from_interaction(data.into())?,
+        "manage" => crate::manager::process_manage(
+            ManageCommand::from_interaction(data.into())?,
+            guild_id.ok_or(Error::NoGuildId)?,
+            respondable,
+            audit,
+            state,
+        ),
+        "rewards" => {
+            crate::rewards::process_rewards(
+                RewardsCommand::from_interaction(data.into())?,
                 guild_id.ok_or(Error::NoGuildId)?,
                 respondable,
+                audit,
                 state,
             )
             .await
         }
-        "rewards" => {
-            crate::rewards::process_rewards(
-                RewardsCommand::from_interaction(data.into())?,
+        "level-up" => {
+            crate::level_up::process_level_up(
+                LevelUpCommand::from_interaction(data.into())?,
                 guild_id.ok_or(Error::NoGuildId)?,
+                audit,
+                state,
+            )
+            .await
+        }
+        "settings" => {
+            crate::settings::process_settings(
+                SettingsCommand::from_interaction(data.into())?,
+                invoker.id,
+                guild_id,
                 state,
             )
             .await
@@ -290,3 +396,34 @@ async fn process_msg_cmd(
 
     crate::levels::get_level(guild_id, target, invoker.id, DEFAULT_SHOWOFF, state).await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn component_namespaces() {
+        assert_eq!(
+            ComponentNamespace::of("confirm:ff:yes"),
+            ComponentNamespace::Confirm
+        );
+        assert_eq!(
+            ComponentNamespace::of("audit:0:::::"),
+            ComponentNamespace::Audit
+        );
+        assert_eq!(
+            ComponentNamespace::of("config:rewards"),
+            ComponentNamespace::Config
+        );
+        assert_eq!(
+            ComponentNamespace::of("leaderboard:3"),
+            ComponentNamespace::Leaderboard
+        );
+        // Leaderboards from before namespacing
+        assert_eq!(ComponentNamespace::of("3"), ComponentNamespace::Leaderboard);
+        assert_eq!(
+            ComponentNamespace::of("jump_modal"),
+            ComponentNamespace::Leaderboard
+        );
+    }
+}
@@ -86,8 +86,22 @@ pub enum Error {
     UnknownInterpolationVariable(String),
     #[error("Level up message must be less than 512 characters!")]
     LevelUpMessageTooLong,
+    #[error(
+        "The embed {0} could be up to {1} characters long once filled in, but Discord only allows {2}!"
+    )]
+    EmbedTemplateTooLong(&'static str, usize, usize),
+    #[error("Embed colors must be a hex code, like `#FF8800`!")]
+    InvalidEmbedColor,
+    #[error("Role rewards are already being synced in this server!")]
+    RewardSyncInProgress,
     #[error("Level up channel must be a text channel!")]
     LevelUpChannelMustBeText,
+    #[error("Leaderboard channel must be a text channel!")]
+    ScheduleChannelMustBeText,
+    #[error("Mod-log channel must be a text channel!")]
+    ModLogChannelMustBeText,
+    #[error("You can't set the {0} and clear it at the same time!")]
+    SetAndClear(&'static str),
     #[error("That card does not exist!")]
     UnknownCard,
     #[error("That toy does not exist!")]
@@ -102,6 +116,22 @@ pub enum Error {
     NoInteractionInvocationOnInteractionMessage,
     #[error("You didn't create this leaderboard.")]
     NotYourLeaderboard,
+    #[error("This button is out of date, please run the command again.")]
+    InvalidComponentId,
+    #[error("Choose exactly one of an event or a moderator to revert.")]
+    RevertNeedsEventOrModerator,
+    #[error("That audit log event does not exist!")]
+    UnknownAuditLogEvent,
+    #[error("Only changes to a single user's XP can be reverted.")]
+    EventNotRevertable,
+    #[error("That event has already been reverted.")]
+    AlreadyReverted,
+    #[error("That moderator made no XP changes in that time which can be reverted.")]
+    NothingToRevert,
+    #[error(
+        "Later changes to the same users would be lost by this revert. Revert these events first: {0}"
+    )]
+    RevertConflict(String),
     #[error(
         "Bots do not have leveling data. If one does somehow, you can still use /xp experience reset on it."
     )]
@@ -110,6 +140,22 @@ pub enum Error {
     NoRanksYet,
     #[error("This user does not have a most recent message.")]
     NoLastMessage,
+    #[error("This server already has {0} level-up messages! Remove one first.")]
+    TooManyLevelUpMessages(usize),
+    #[error("The minimum level can't be above the maximum level!")]
+    LevelRangeBackwards,
+    #[error("That level-up message does not exist!")]
+    UnknownLevelUpMessage,
+    #[error("That role is already a {0} reward! Remove it first.")]
+    RewardRoleInUse(&'static str),
+    #[error("Run this in a server to change it for just that server!")]
+    SettingNeedsGuild,
+    #[error("Only the person who ran this command can answer it!")]
+    NotYourConfirmation,
+    #[error("XP per message must be a whole number from 0 to 32767!")]
+    XpPerMessageOutOfRange,
+    #[error("The cooldown must be a whole number of seconds from 0 to 28800!")]
+    CooldownOutOfRange,
 }
 
 impl From<twilight_http::Error> for Error {
@@ -3,46 +3,29 @@ use twilight_model::{
     http::interaction::InteractionResponseType,
     id::{
         Id,
-        marker::{GuildMarker, InteractionMarker, UserMarker},
+        marker::{GuildMarker, UserMarker},
     },
 };
 use twilight_util::builder::embed::EmbedBuilder;
 use xpd_common::{AuditLogEvent, AuditLogEventKind};
 use xpd_database::AcquireWrapper as _;
 use xpd_slash_defs::experience::XpCommand;
-use xpd_util::snowflake_to_timestamp;
 
-use crate::{Error, SlashState, XpdInteractionData, response::XpdInteractionResponse};
-
-pub struct XpAuditData {
-    pub interaction: Id<InteractionMarker>,
-    pub invoker: Id<UserMarker>,
-}
+use crate::{
+    Error, SlashState, XpdInteractionData, audit::AuditData, confirm::ConfirmAction,
+    response::XpdInteractionResponse,
+};
 
 pub async fn process_xp(
     data: XpCommand,
     state: SlashState,
     guild_id: Id<GuildMarker>,
-    audit: XpAuditData,
+    audit: AuditData,
 ) -> Result<XpdInteractionResponse, Error> {
-    let contents = process_experience(data, guild_id, state, audit).await?;
-    Ok(XpdInteractionData::new()
-        .allowed_mentions_o(Some(AllowedMentions::default()))
-        .ephemeral(true)
-        .embeds([EmbedBuilder::new().description(contents).build()])
-        .into_interaction_response(InteractionResponseType::ChannelMessageWithSource))
-}
-
-async fn process_experience(
-    data: XpCommand,
-    guild_id: Id<GuildMarker>,
-    state: SlashState,
-    audit: XpAuditData,
-) -> Result<String, Error> {
     if !allowed_command_for_target(&data) {
         return Err(Error::BotsDontLevel);
     }
-    match data {
+    let contents = match data {
         XpCommand::Add(add) => {
             modify_user_xp(state, guild_id, add.user.resolved.id, add.amount, audit).await
         }
@@ -50,12 +33,23 @@ async fn process_experience(
             modify_user_xp(state, guild_id, rm.user.resolved.id, -rm.amount, audit).await
         }
         XpCommand::Reset(reset) => {
-            reset_user_xp(state, guild_id, reset.user.resolved.id, audit).await
+            let target = reset.user.resolved.id;
+            return Ok(crate::confirm::prompt(
+                &state,
+                audit.invoker,
+                ConfirmAction::ResetUser(guild_id, target),
+                &format!("This will delete all of <@{target}>'s XP in this server."),
+            ));
         }
         XpCommand::Set(set) => {
             set_user_xp(state, guild_id, set.user.resolved.id, set.xp, audit).await
         }
-    }
+    }?;
+    Ok(XpdInteractionData::new()
+        .allowed_mentions_o(Some(AllowedMentions::default()))
+        .ephemeral(true)
+        .embeds([EmbedBuilder::new().description(contents).build()])
+        .into_interaction_response(InteractionResponseType::ChannelMessageWithSource))
 }
 
 async fn modify_user_xp(
@@ -63,7 +57,7 @@ async fn modify_user_xp(
     guild: Id<GuildMarker>,
     target: Id<UserMarker>,
     amount: i64,
-    audit: XpAuditData,
+    audit: AuditData,
 ) -> Result<String, Error> {
     let mut txn = state.db.xbegin().await?;
     let xp = xpd_database::add_xp(txn.as_mut(), target, guild, amount).await?;
@@ -73,16 +67,21 @@ async fn modify_user_xp(
     }
     let audit_event = AuditLogEvent {
         guild,
-        target,
+        target: Some(target),
         moderator: audit.invoker,
-        timestamp: snowflake_to_timestamp(audit.interaction),
-        previous: xp + amount,
+        timestamp: audit.timestamp(),
+        previous: xp - amount,
         delta: amount,
         kind: AuditLogEventKind::AddOrSub,
+        details: None,
     };
-    xpd_database::add_audit_log_event(txn.as_mut(), audit_event).await?;
+    xpd_database::add_audit_log_event(txn.as_mut(), audit_event.clone()).await?;
 
     txn.commit().await?;
+    state.notify_audit_log([audit_event]).await;
+    state
+        .recheck_rewards(guild, Some(target), level_dropped(xp - amount, xp))
+        .await;
     let current_level = mee6::LevelInfo::new(xp.try_into().unwrap_or(0)).level();
     let (action, targeter) = if amount.is_positive() {
         ("Added", "to")
@@ -95,27 +94,33 @@ async fn modify_user_xp(
     ))
 }
 
-async fn reset_user_xp(
+pub async fn reset_user_xp(
     state: SlashState,
     guild: Id<GuildMarker>,
     target: Id<UserMarker>,
-    audit: XpAuditData,
+    audit: AuditData,
 ) -> Result<String, Error> {
     let mut txn = state.db.xbegin().await?;
     let old_xp = xpd_database::delete_levels_user_guild(txn.as_mut(), target, guild).await?;
+    xpd_database::delete_soft_deleted_levels_user_guild(txn.as_mut(), target, guild).await?;
 
     let audit_event = AuditLogEvent {
         guild,
-        target,
+        target: Some(target),
         moderator: audit.invoker,
-        timestamp: snowflake_to_timestamp(audit.interaction),
+        timestamp: audit.timestamp(),
         previous: old_xp,
         delta: -old_xp,
         kind: AuditLogEventKind::Reset,
+        details: None,
     };
-    xpd_database::add_audit_log_event(txn.as_mut(), audit_event).await?;
+    xpd_database::add_audit_log_event(txn.as_mut(), audit_event.clone()).await?;
 
     txn.commit().await?;
+    state.notify_audit_log([audit_event]).await;
+    state
+        .recheck_rewards(guild, Some(target), level_dropped(old_xp, 0))
+        .await;
 
     Ok(format!(
         "Deleted <@{target}> from my database in this server!"
@@ -127,7 +132,7 @@ async fn set_user_xp(
     guild: Id<GuildMarker>,
     target: Id<UserMarker>,
     setpoint: i64,
-    audit: XpAuditData,
+    audit: AuditData,
 ) -> Result<String, Error> {
     let mut txn = state.db.xbegin().await?;
     let old_xp = xpd_database::user_xp(txn.as_mut(), guild, target)
@@ -137,16 +142,21 @@ async fn set_user_xp(
 
     let audit_event = AuditLogEvent {
         guild,
-        target,
+        target: Some(target),
         moderator: audit.invoker,
-        timestamp: snowflake_to_timestamp(audit.interaction),
+        timestamp: audit.timestamp(),
         previous: old_xp,
         delta: setpoint - old_xp,
         kind: AuditLogEventKind::Set,
+        details: None,
     };
-    xpd_database::add_audit_log_event(txn.as_mut(), audit_event).await?;
+    xpd_database::add_audit_log_event(txn.as_mut(), audit_event.clone()).await?;
 
     txn.commit().await?;
+    state.notify_audit_log([audit_event]).await;
+    state
+        .recheck_rewards(guild, Some(target), level_dropped(old_xp, setpoint))
+        .await;
 
     let level = mee6::LevelInfo::new(setpoint.try_into().unwrap_or(0));
     Ok(format!(
@@ -156,6 +166,12 @@ async fn set_user_xp(
     ))
 }
 
+/// Whether going from `before` to `after` XP cost a level, so rewards above it should go
+pub fn level_dropped(before: i64, after: i64) -> bool {
+    let level = |xp: i64| mee6::LevelInfo::new(xp.try_into().unwrap_or(0)).level();
+    level(after) < level(before)
+}
+
 /// For commands that target a specific user, other than reset, prevent commands from being used on a bot.
 const fn allowed_command_for_target(data: &XpCommand) -> bool {
     match data {
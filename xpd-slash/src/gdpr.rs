@@ -1,18 +1,24 @@
-use std::sync::Arc;
-
 use csv::{IntoInnerError as CsvIntoInnerError, Writer as CsvWriter};
 use serde::Serialize;
 use twilight_model::{
     http::{attachment::Attachment, interaction::InteractionResponseType},
-    id::{Id, marker::GuildMarker},
+    id::{
+        Id,
+        marker::{GuildMarker, InteractionMarker, UserMarker},
+    },
+};
+use xpd_common::{
+    AuditLogChange, AuditLogEntry, AuditLogEvent, AuditLogEventKind, DISCORD_EPOCH_SECS,
+    MemberDisplayInfo, RewardGrant, RoleUpdateFailure, UserCleanup, UserSettings,
 };
-use xpd_common::MemberDisplayInfo;
 use xpd_database::AcquireWrapper as _;
-use xpd_slash_defs::gdpr::{GdprCommand, GdprCommandDelete};
+use xpd_rank_card::customizations::Customizations;
+use xpd_slash_defs::gdpr::{GdprCommand, GdprDownloadFormat};
+use xpd_util::snowflake_to_timestamp;
 
 use crate::{
-    Error, SlashState, XpdInteractionData, levels::get_customizations,
-    response::XpdInteractionResponse,
+    Error, SlashState, XpdInteractionData, confirm::ConfirmAction, experience::level_dropped,
+    levels::get_customizations, response::XpdInteractionResponse,
 };
 
 pub async fn process_gdpr(
@@ -21,69 +27,203 @@ pub async fn process_gdpr(
     invoker: MemberDisplayInfo,
 ) -> Result<XpdInteractionResponse, Error> {
     match cmd {
-        GdprCommand::Delete(data) => delete(state, data, invoker).await,
-        GdprCommand::Download(_) => download(state, invoker).await,
+        GdprCommand::Delete(_) => Ok(crate::confirm::prompt(
+            &state,
+            invoker.id,
+            ConfirmAction::GdprDelete,
+            "This will delete all of your data from Experienced, including your XP in every \
+            server, your rank card and your settings.",
+        )),
+        GdprCommand::Download(data) => download(state, data.format, invoker).await,
     }
 }
 
-async fn delete(
+pub async fn delete_user_data(
     state: SlashState,
-    cmd: GdprCommandDelete,
-    invoker: MemberDisplayInfo,
-) -> Result<XpdInteractionResponse, Error> {
-    if cmd.user == invoker.id {
-        let mut txn = state.db.xbegin().await?;
-        xpd_database::delete_levels_user(&mut txn, invoker.id).await?;
-        xpd_database::delete_card_customizations(&mut txn, invoker.id.cast()).await?;
-        xpd_database::delete_audit_log_events_user(&mut txn, invoker.id).await?;
-        txn.commit().await?;
-        Ok(
-            XpdInteractionData::with_embed_text("All data wiped. Thank you for using experienced.")
-                .ephemeral(true)
-                .into_interaction_response(InteractionResponseType::ChannelMessageWithSource),
-        )
-    } else {
-        Ok(XpdInteractionData::with_embed_text(
-            "Please make sure the username you entered is correct!",
-        )
-        .ephemeral(true)
-        .into_interaction_response(InteractionResponseType::ChannelMessageWithSource))
+    user: Id<UserMarker>,
+    interaction: Id<InteractionMarker>,
+) -> Result<String, Error> {
+    let mut txn = state.db.xbegin().await?;
+    let levels = xpd_database::get_all_levels(&mut txn, user).await?;
+    xpd_database::delete_levels_user(&mut txn, user).await?;
+    xpd_database::delete_soft_deleted_levels_user(&mut txn, user).await?;
+    xpd_database::delete_card_customizations(&mut txn, user.cast()).await?;
+    xpd_database::delete_audit_log_events_user(&mut txn, user).await?;
+    xpd_database::delete_reward_grants_user(&mut txn, user).await?;
+    xpd_database::delete_role_update_failures_user(&mut txn, user).await?;
+    xpd_database::delete_user_settings(&mut txn, user).await?;
+    xpd_database::delete_user_guild_opt_outs(&mut txn, user).await?;
+    xpd_database::delete_cooldowns_user(&mut txn, user).await?;
+    xpd_database::delete_user_cleanups_user(&mut txn, user).await?;
+    // Leave a record in each guild of why XP disappeared, but not whose it was,
+    // so the record doesn't keep the very data that was just erased
+    let mut events = Vec::with_capacity(levels.len());
+    for level in levels {
+        let event = AuditLogEvent {
+            guild: level.guild,
+            target: None,
+            moderator: state.bot_id,
+            timestamp: snowflake_to_timestamp(interaction),
+            previous: level.xp,
+            delta: -level.xp,
+            kind: AuditLogEventKind::GdprDelete,
+            details: None,
+        };
+        xpd_database::add_audit_log_event(&mut txn, event.clone()).await?;
+        events.push(event);
+    }
+    txn.commit().await?;
+    let guilds: Vec<(Id<GuildMarker>, bool)> = events
+        .iter()
+        .map(|event| (event.guild, level_dropped(event.previous, 0)))
+        .collect();
+    state.notify_audit_log(events).await;
+    for (guild, level_dropped) in guilds {
+        state
+            .recheck_rewards(guild, Some(user), level_dropped)
+            .await;
     }
+    state
+        .update_level_up_dms(user, UserSettings::default().level_up_dms)
+        .await;
+    Ok("All data wiped. Thank you for using experienced.".to_string())
 }
 
+/// Bumped whenever a field of [`GdprArchive`] is removed or changes meaning.
+/// The format is documented in the "Downloading your data" section of the docs.
+const ARCHIVE_VERSION: u32 = 1;
+
 async fn download(
     state: SlashState,
+    format: Option<GdprDownloadFormat>,
     invoker: MemberDisplayInfo,
 ) -> Result<XpdInteractionResponse, Error> {
-    let invoker = Arc::new(invoker);
-    let levels = xpd_database::get_all_levels(&state.db, invoker.id).await?;
-
-    let invoker_id = &[invoker.id.cast()];
-    let custom_card = get_customizations(&state, invoker_id).await?;
-
-    let levels: Vec<UserXpArchiveEntry> = levels
-        .into_iter()
-        .map(|v| UserXpArchiveEntry::from_record(v.guild, v.xp))
-        .collect();
-
-    let levels = multicsv(&levels)?;
-    let custom_card = multicsv(&[custom_card])?;
-
-    let level_file = Attachment::from_bytes(format!("leveling-{}.csv", invoker.id), levels, 1);
-    let card_file = Attachment::from_bytes(format!("card-{}.csv", invoker.id), custom_card, 2);
-
-    let attachments: Vec<Attachment> = [level_file, card_file]
-        .into_iter()
-        .filter(|v| !v.file.is_empty())
-        .collect();
+    let archive = GdprArchive::collect(&state, invoker.id).await?;
+    let attachments = match format.unwrap_or(GdprDownloadFormat::Json) {
+        GdprDownloadFormat::Json => vec![Attachment::from_bytes(
+            format!("experienced-{}.json", invoker.id),
+            serde_json::to_vec_pretty(&archive)?,
+            1,
+        )],
+        GdprDownloadFormat::Csv => archive.into_csv_files()?,
+    };
 
     Ok(XpdInteractionData::new()
-        .content("Here you go!".to_string())
+        .content(
+            "Here you go! What each file contains is explained at \
+            <https://xp.valk.sh/docs/#downloading-your-data>."
+                .to_string(),
+        )
         .attachments(attachments)
         .ephemeral(true)
         .into_interaction_response(InteractionResponseType::DeferredChannelMessageWithSource))
 }
 
+/// Everything stored about one user. All timestamps are Unix timestamps, in seconds.
+#[derive(Serialize)]
+struct GdprArchive {
+    version: u32,
+    user: Id<UserMarker>,
+    levels: Vec<UserXpArchiveEntry>,
+    soft_deleted_levels: Vec<UserXpArchiveEntry>,
+    card: Customizations,
+    settings: UserSettings,
+    xp_opt_outs: Vec<XpOptOutArchiveEntry>,
+    audit_logs: Vec<AuditLogArchiveEntry>,
+    cooldowns: Vec<CooldownArchiveEntry>,
+    cleanups: Vec<UserCleanup>,
+    reward_grants: Vec<RewardGrant>,
+    role_update_failures: Vec<RoleUpdateFailure>,
+}
+
+impl GdprArchive {
+    async fn collect(state: &SlashState, user: Id<UserMarker>) -> Result<Self, Error> {
+        let mut txn = state.db.xbegin().await?;
+        let levels = xpd_database::get_all_levels(&mut txn, user)
+            .await?
+            .into_iter()
+            .map(|v| UserXpArchiveEntry::from_record(v.guild, v.xp))
+            .collect();
+        let soft_deleted_levels = xpd_database::soft_deleted_levels_user(&mut txn, user)
+            .await?
+            .into_iter()
+            .map(|v| UserXpArchiveEntry::from_record(v.guild, v.xp))
+            .collect();
+        let settings = xpd_database::user_settings(&mut txn, user).await?;
+        let xp_opt_outs = xpd_database::user_guild_opt_outs(&mut txn, user)
+            .await?
+            .into_iter()
+            .map(|guild| XpOptOutArchiveEntry { guild })
+            .collect();
+        let audit_logs = xpd_database::user_audit_log_events(&mut txn, user)
+            .await?
+            .into_iter()
+            .map(AuditLogArchiveEntry::from)
+            .collect();
+        let cooldowns = xpd_database::user_cooldowns(&mut txn, user)
+            .await?
+            .into_iter()
+            .map(|v| CooldownArchiveEntry {
+                guild: v.guild,
+                last_message: v.last_message + DISCORD_EPOCH_SECS,
+            })
+            .collect();
+        let cleanups = xpd_database::user_cleanups(&mut txn, user).await?;
+        let reward_grants = xpd_database::reward_grants_for_user(&mut txn, user).await?;
+        let role_update_failures = xpd_database::user_role_update_failures(&mut txn, user).await?;
+        txn.commit().await?;
+        let card = get_customizations(state, &[user.cast()]).await?;
+
+        Ok(Self {
+            version: ARCHIVE_VERSION,
+            user,
+            levels,
+            soft_deleted_levels,
+            card,
+            settings,
+            xp_opt_outs,
+            audit_logs,
+            cooldowns,
+            cleanups,
+            reward_grants,
+            role_update_failures,
+        })
+    }
+
+    /// One CSV file per field, leaving out the empty ones
+    fn into_csv_files(self) -> Result<Vec<Attachment>, Error> {
+        let audit_logs: Vec<AuditLogCsvEntry> = self
+            .audit_logs
+            .into_iter()
+            .map(AuditLogCsvEntry::try_from)
+            .collect::<Result<_, _>>()?;
+        let files = [
+            ("leveling", multicsv(&self.levels)?),
+            ("soft-deleted-levels", multicsv(&self.soft_deleted_levels)?),
+            ("card", multicsv(&[self.card])?),
+            ("settings", multicsv(&[self.settings])?),
+            ("xp-opt-outs", multicsv(&self.xp_opt_outs)?),
+            ("audit-logs", multicsv(&audit_logs)?),
+            ("cooldowns", multicsv(&self.cooldowns)?),
+            ("cleanups", multicsv(&self.cleanups)?),
+            ("reward-grants", multicsv(&self.reward_grants)?),
+            (
+                "role-update-failures",
+                multicsv(&self.role_update_failures)?,
+            ),
+        ];
+        let attachments = files
+            .into_iter()
+            .filter(|(_, file)| !file.is_empty())
+            .zip(1..)
+            .map(|((name, file), id)| {
+                Attachment::from_bytes(format!("{name}-{}.csv", self.user), file, id)
+            })
+            .collect();
+        Ok(attachments)
+    }
+}
+
 #[derive(Serialize)]
 struct UserXpArchiveEntry {
     guild: Id<GuildMarker>,
@@ -96,6 +236,83 @@ impl UserXpArchiveEntry {
     }
 }
 
+#[derive(Serialize)]
+struct XpOptOutArchiveEntry {
+    guild: Id<GuildMarker>,
+}
+
+#[derive(Serialize)]
+struct CooldownArchiveEntry {
+    guild: Id<GuildMarker>,
+    last_message: i64,
+}
+
+#[derive(Serialize)]
+struct AuditLogArchiveEntry {
+    id: i64,
+    guild: Id<GuildMarker>,
+    target: Option<Id<UserMarker>>,
+    moderator: Id<UserMarker>,
+    timestamp: i64,
+    previous: i64,
+    delta: i64,
+    kind: AuditLogEventKind,
+    details: Option<AuditLogChange>,
+}
+
+impl From<AuditLogEntry> for AuditLogArchiveEntry {
+    fn from(entry: AuditLogEntry) -> Self {
+        let event = entry.event;
+        Self {
+            id: entry.id,
+            guild: event.guild,
+            target: event.target,
+            moderator: event.moderator,
+            timestamp: event.timestamp + DISCORD_EPOCH_SECS,
+            previous: event.previous,
+            delta: event.delta,
+            kind: event.kind,
+            details: event.details,
+        }
+    }
+}
+
+/// CSV can't hold nested data, so the details are written out as JSON
+#[derive(Serialize)]
+struct AuditLogCsvEntry {
+    id: i64,
+    guild: Id<GuildMarker>,
+    target: Option<Id<UserMarker>>,
+    moderator: Id<UserMarker>,
+    timestamp: i64,
+    previous: i64,
+    delta: i64,
+    kind: AuditLogEventKind,
+    details: Option<String>,
+}
+
+impl TryFrom<AuditLogArchiveEntry> for AuditLogCsvEntry {
+    type Error = serde_json::Error;
+
+    fn try_from(entry: AuditLogArchiveEntry) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: entry.id,
+            guild: entry.guild,
+            target: entry.target,
+            moderator: entry.moderator,
+            timestamp: entry.timestamp,
+            previous: entry.previous,
+            delta: entry.delta,
+            kind: entry.kind,
+            details: entry
+                .details
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()?,
+        })
+    }
+}
+
 fn multicsv<T: Serialize>(data: &[T]) -> Result<Vec<u8>, Error> {
     let mut data_wtr = CsvWriter::from_writer(Vec::new());
     for datum in data {
@@ -1,4 +1,4 @@
-use std::{convert::TryInto, fmt::Write};
+use std::{collections::HashSet, convert::TryInto, fmt::Write};
 
 use twilight_model::{
     application::interaction::{
@@ -18,13 +18,16 @@ use twilight_model::{
         marker::{GuildMarker, UserMarker},
     },
 };
-use xpd_common::UserStatus;
+use xpd_common::{LeaderboardSchedule, LeaderboardSchedulePeriod, UserStatus};
 use xpd_slash_defs::levels::LeaderboardCommand;
 
 use crate::{
     Error, SlashState, XpdInteractionData, dispatch::Respondable, response::XpdInteractionResponse,
 };
 
+/// Every leaderboard button and modal has a custom ID starting with this
+pub const CUSTOM_ID_PREFIX: &str = "leaderboard:";
+
 pub async fn leaderboard(
     state: SlashState,
     guild_id: Id<GuildMarker>,
@@ -60,17 +63,7 @@ async fn gen_leaderboard(
     show_off: Option<bool>,
 ) -> Result<XpdInteractionData, Error> {
     let is_ephemeral = !(show_off.unwrap_or(true));
-    let users_in_guild = xpd_database::get_guild_leaderboard(&state.db, guild_id).await?;
-    let cache = state.cache.clone();
-    let users = tokio::task::spawn_blocking(move || {
-        let mut users: Box<[UserStatus]> = users_in_guild
-            .into_iter()
-            .filter(|v| cache.member(guild_id, v.id).is_some())
-            .collect();
-        users.sort_unstable_by_key(|v| std::cmp::Reverse(v.xp));
-        users
-    })
-    .await?;
+    let users = ranked_members(state, guild_id).await?;
 
     if users.is_empty() {
         return Err(Error::NoRanksYet);
@@ -119,10 +112,112 @@ async fn gen_leaderboard(
         .flags(flags))
 }
 
+/// Get every member of the guild with levels, sorted from most to least XP.
+/// Users who are no longer in the guild, or who hide from the leaderboard, are left out.
+async fn ranked_members(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+) -> Result<Box<[UserStatus]>, Error> {
+    let (users_in_guild, hidden) = tokio::try_join!(
+        xpd_database::get_guild_leaderboard(&state.db, guild_id),
+        xpd_database::hidden_from_leaderboard(&state.db, guild_id)
+    )?;
+    let hidden: HashSet<Id<UserMarker>> = hidden.into_iter().collect();
+    let cache = state.cache.clone();
+    let users = tokio::task::spawn_blocking(move || {
+        let mut users: Box<[UserStatus]> = users_in_guild
+            .into_iter()
+            .filter(|v| !hidden.contains(&v.id) && cache.member(guild_id, v.id).is_some())
+            .collect();
+        users.sort_unstable_by_key(|v| std::cmp::Reverse(v.xp));
+        users
+    })
+    .await?;
+    Ok(users)
+}
+
+const PODIUM_SIZE: usize = 3;
+
+pub async fn post_scheduled_leaderboard(
+    state: &SlashState,
+    schedule: LeaderboardSchedule,
+) -> Result<(), Error> {
+    if !xpd_util::can_create_message(&state.cache, state.bot_id, schedule.channel)? {
+        warn!(guild = ?schedule.guild, channel = ?schedule.channel, "Could not post scheduled leaderboard");
+        return Ok(());
+    }
+
+    let users = ranked_members(state, schedule.guild).await?;
+    if users.is_empty() {
+        debug!(guild = ?schedule.guild, "Skipping scheduled leaderboard, nobody is ranked");
+        return Ok(());
+    }
+    let page_users = &users[..users.len().min(USERS_PER_PAGE)];
+
+    let mut description = String::with_capacity(256 + page_users.len() * 128);
+    let title = match schedule.period {
+        LeaderboardSchedulePeriod::Weekly => "Weekly",
+        LeaderboardSchedulePeriod::Monthly => "Monthly",
+    };
+    writeln!(description, "### {title} Leaderboard")?;
+    for (i, user) in page_users.iter().enumerate() {
+        let level = mee6::LevelInfo::new(user.xp.try_into().unwrap_or(0)).level();
+        writeln!(
+            description,
+            "**#{}.** <@{}> - Level {level}",
+            i + 1,
+            user.id
+        )?;
+    }
+
+    let winners = &page_users[..page_users.len().min(PODIUM_SIZE)];
+    let allowed_mentions = if schedule.mention_winners {
+        let mentions: Vec<String> = winners.iter().map(|v| format!("<@{}>", v.id)).collect();
+        write!(description, "\nCongratulations {}!", mentions.join(", "))?;
+        AllowedMentions {
+            users: winners.iter().map(|v| v.id).collect(),
+            ..AllowedMentions::default()
+        }
+    } else {
+        AllowedMentions::default()
+    };
+
+    let attachments = if schedule.winner_card {
+        let winner = page_users[0];
+        let member = state
+            .client
+            .guild_member(schedule.guild, winner.id)
+            .await?
+            .model()
+            .await?;
+        let level_info = mee6::LevelInfo::new(winner.xp.try_into().unwrap_or(0));
+        let card = crate::levels::gen_card(
+            state.clone(),
+            member.into(),
+            Some(schedule.guild),
+            level_info,
+            1,
+        )
+        .await?;
+        vec![card]
+    } else {
+        Vec::new()
+    };
+
+    state
+        .client
+        .create_message(schedule.channel)
+        .allowed_mentions(Some(&allowed_mentions))
+        .attachments(&attachments)
+        .content(&description)
+        .await?;
+    Ok(())
+}
+
 fn control_options(zpage: usize, next_page_exists: bool) -> [Component; 5] {
     [
         Button {
-            custom_id: Some("page_indicator".to_string()),
+            custom_id: Some(format!("{CUSTOM_ID_PREFIX}indicator")),
             disabled: true,
             emoji: None,
             label: Some(format!("Page {}", zpage + 1)),
@@ -132,7 +227,7 @@ fn control_options(zpage: usize, next_page_exists: bool) -> [Component; 5] {
             id: None,
         },
         Button {
-            custom_id: Some((zpage - 1).to_string()),
+            custom_id: Some(format!("{CUSTOM_ID_PREFIX}{}", zpage - 1)),
             disabled: zpage == 0,
             emoji: Some(EmojiReactionType::Unicode {
                 name: "⬅".to_string(),
@@ -144,7 +239,7 @@ fn control_options(zpage: usize, next_page_exists: bool) -> [Component; 5] {
             id: None,
         },
         Button {
-            custom_id: Some("jump_modal".to_string()),
+            custom_id: Some(format!("{CUSTOM_ID_PREFIX}jump")),
             disabled: !next_page_exists && zpage == 0,
             emoji: None,
             label: Some("Go to page".to_string()),
@@ -154,7 +249,7 @@ fn control_options(zpage: usize, next_page_exists: bool) -> [Component; 5] {
             id: None,
         },
         Button {
-            custom_id: Some((zpage + 1).to_string()),
+            custom_id: Some(format!("{CUSTOM_ID_PREFIX}{}", zpage + 1)),
             disabled: !next_page_exists,
             emoji: Some(EmojiReactionType::Unicode {
                 name: "➡️".to_string(),
@@ -166,7 +261,7 @@ fn control_options(zpage: usize, next_page_exists: bool) -> [Component; 5] {
             id: None,
         },
         Button {
-            custom_id: Some("delete_leaderboard".to_string()),
+            custom_id: Some(format!("{CUSTOM_ID_PREFIX}delete")),
             disabled: false,
             emoji: Some(EmojiReactionType::Unicode {
                 name: "🗑️".to_string(),
@@ -226,8 +321,13 @@ pub async fn process_message_component(
     {
         return Err(Error::NotYourLeaderboard);
     }
-    match data.custom_id.as_str() {
-        "jump_modal" => Ok(XpdInteractionResponse::new(
+    // Leaderboards sent before custom IDs were namespaced still have bare IDs
+    let action = data
+        .custom_id
+        .strip_prefix(CUSTOM_ID_PREFIX)
+        .unwrap_or(&data.custom_id);
+    match action {
+        "jump" | "jump_modal" => Ok(XpdInteractionResponse::new(
             InteractionResponseType::Modal,
             XpdInteractionData::new()
                 .components([Component::Label(Label {
@@ -247,10 +347,10 @@ pub async fn process_message_component(
                         id: None,
                     })),
                 })])
-                .custom_id("jump_modal".to_string())
+                .custom_id(format!("{CUSTOM_ID_PREFIX}jump"))
                 .title("Go to page..".to_string()),
         )),
-        "delete_leaderboard" => {
+        "delete" | "delete_leaderboard" => {
             let deferred_update = InteractionResponse {
                 kind: InteractionResponseType::UpdateMessage,
                 data: None,
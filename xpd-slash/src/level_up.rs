@@ -0,0 +1,124 @@
+use std::fmt::Write;
+
+use twilight_model::{
+    channel::message::AllowedMentions,
+    http::interaction::InteractionResponseType,
+    id::{Id, marker::GuildMarker},
+};
+use twilight_util::builder::embed::EmbedBuilder;
+use xpd_common::{AuditLogEventKind, LevelUpMessage};
+use xpd_database::AcquireWrapper as _;
+use xpd_slash_defs::level_up::{LevelUpCommand, LevelUpCommandAdd, LevelUpCommandRemove};
+
+use crate::{
+    Error, SlashState, XpdInteractionData, audit::AuditData, config::validate_template,
+    response::XpdInteractionResponse,
+};
+
+/// How many level-up messages a guild can have
+const MAX_LEVEL_UP_MESSAGES: usize = 50;
+
+pub async fn process_level_up(
+    cmd: LevelUpCommand,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+    state: SlashState,
+) -> Result<XpdInteractionResponse, Error> {
+    let contents = match cmd {
+        LevelUpCommand::Add(add) => process_level_up_add(add, state, guild_id, audit).await,
+        LevelUpCommand::Remove(rm) => process_level_up_rm(rm, state, guild_id, audit).await,
+        LevelUpCommand::List(_list) => process_level_up_list(state, guild_id).await,
+    }?;
+    Ok(XpdInteractionData::new()
+        .allowed_mentions(AllowedMentions::default())
+        .ephemeral(true)
+        .embeds([EmbedBuilder::new().description(contents).build()])
+        .into_interaction_response(InteractionResponseType::ChannelMessageWithSource))
+}
+
+async fn process_level_up_add(
+    options: LevelUpCommandAdd,
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+) -> Result<String, Error> {
+    if let (Some(min), Some(max)) = (options.min_level, options.max_level)
+        && min > max
+    {
+        return Err(Error::LevelRangeBackwards);
+    }
+    let template = options.message.replace("\\n", "\n");
+    validate_template(&template)?;
+
+    let mut txn = state.db.xbegin().await?;
+    let existing = xpd_database::guild_level_up_messages(&mut txn, guild_id).await?;
+    if existing.len() >= MAX_LEVEL_UP_MESSAGES {
+        return Err(Error::TooManyLevelUpMessages(MAX_LEVEL_UP_MESSAGES));
+    }
+    let id = xpd_database::add_level_up_message(
+        &mut txn,
+        guild_id,
+        options.min_level,
+        options.max_level,
+        &template,
+    )
+    .await?;
+    let message = LevelUpMessage {
+        id,
+        min_level: options.min_level,
+        max_level: options.max_level,
+        template: simpleinterpolation::Interpolation::new(template)?,
+    };
+    let event = audit.change_event(
+        guild_id,
+        AuditLogEventKind::ConfigUpdate,
+        &None::<LevelUpMessage>,
+        &message,
+    )?;
+    xpd_database::add_audit_log_event(&mut txn, event.clone()).await?;
+    txn.commit().await?;
+    state.notify_audit_log([event]).await;
+    state.invalidate_level_up_messages(guild_id).await;
+    Ok(format!("Added level-up message {id}!\n{message}"))
+}
+
+async fn process_level_up_rm(
+    options: LevelUpCommandRemove,
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+) -> Result<String, Error> {
+    let mut txn = state.db.xbegin().await?;
+    let old_message = xpd_database::guild_level_up_messages(&mut txn, guild_id)
+        .await?
+        .into_iter()
+        .find(|message| message.id == options.id)
+        .ok_or(Error::UnknownLevelUpMessage)?;
+    xpd_database::delete_level_up_message(&mut txn, guild_id, options.id).await?;
+    let event = audit.change_event(
+        guild_id,
+        AuditLogEventKind::ConfigUpdate,
+        &old_message,
+        &None::<LevelUpMessage>,
+    )?;
+    xpd_database::add_audit_log_event(&mut txn, event.clone()).await?;
+    txn.commit().await?;
+    state.notify_audit_log([event]).await;
+    state.invalidate_level_up_messages(guild_id).await;
+    Ok(format!("Removed level-up message {}.", options.id))
+}
+
+async fn process_level_up_list(
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+) -> Result<String, Error> {
+    let messages = xpd_database::guild_level_up_messages(&state.db, guild_id).await?;
+    if messages.is_empty() {
+        return Ok("No level-up messages set for this server".to_string());
+    }
+    let mut data = String::new();
+    for message in messages {
+        writeln!(data, "`{}` {message}", message.id)?;
+    }
+    Ok(data)
+}
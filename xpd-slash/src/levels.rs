@@ -1,7 +1,7 @@
 use base64::Engine;
 use tokio::try_join;
 use twilight_model::{
-    channel::message::MessageFlags,
+    channel::message::{Embed, MessageFlags},
     http::{attachment::Attachment, interaction::InteractionResponseType},
     id::{
         Id,
@@ -9,8 +9,9 @@ use twilight_model::{
     },
     util::ImageHash,
 };
-use twilight_util::builder::embed::EmbedBuilder;
-use xpd_common::MemberDisplayInfo;
+use twilight_util::builder::embed::{EmbedBuilder, ImageSource};
+use xpd_common::{LevelUpAnnouncement, MemberDisplayInfo};
+use xpd_database::RawCustomizations;
 use xpd_rank_card::customizations::{Color, Customizations};
 use xpd_util::DisplayName;
 
@@ -25,7 +26,9 @@ pub async fn get_level(
 ) -> Result<XpdInteractionResponse, Error> {
     let rank_stats = state.get_user_stats(target.id, guild_id);
     let guild_config = xpd_database::guild_config(&state.db, guild_id);
-    let (rank_stats, guild_config) = try_join!(rank_stats, guild_config)?;
+    let target_settings = xpd_database::user_settings(&state.db, target.id);
+    let (rank_stats, guild_config, target_settings) =
+        try_join!(rank_stats, guild_config, target_settings)?;
     let flags = if showoff
         .or_else(|| guild_config.map(|cfg| cfg.guild_card_default_show_off))
         .is_some_and(|v| v)
@@ -38,6 +41,8 @@ pub async fn get_level(
     let level_info = mee6::LevelInfo::new(u64::try_from(rank_stats.xp).unwrap_or(0));
     let content = if target.bot {
         "Bots aren't ranked, that would be silly!".to_string()
+    } else if invoker != target.id && target_settings.hide_from_rank {
+        format!("{} keeps their rank private.", target.display_name())
     } else if invoker == target.id {
         if rank_stats.xp == 0 {
             "You aren't ranked yet, because you haven't sent any messages!".to_string()
@@ -90,6 +95,56 @@ async fn generate_level_response(
         .into_interaction_response(InteractionResponseType::ChannelMessageWithSource))
 }
 
+pub async fn post_level_up_card(
+    state: &SlashState,
+    announcement: LevelUpAnnouncement,
+) -> Result<(), Error> {
+    let user = announcement.member.id;
+    let rank_stats = state.get_user_stats(user, announcement.guild).await?;
+    let level_info = mee6::LevelInfo::new(u64::try_from(rank_stats.xp).unwrap_or(0));
+    let card = gen_card(
+        state.clone(),
+        announcement.member,
+        Some(announcement.guild),
+        level_info,
+        rank_stats.rank,
+    )
+    .await?;
+    let embeds: Vec<Embed> = announcement
+        .embed
+        .map(|embed| -> Result<Embed, Error> {
+            Ok(EmbedBuilder::from(embed)
+                .image(ImageSource::attachment(&card.filename)?)
+                .build())
+        })
+        .transpose()?
+        .into_iter()
+        .collect();
+
+    let mut message = state
+        .client
+        .create_message(announcement.channel)
+        .allowed_mentions(Some(&announcement.allowed_mentions))
+        .attachments(std::slice::from_ref(&card))
+        .embeds(&embeds);
+    if let Some(reply_to) = announcement.reply_to {
+        message = message.reply(reply_to);
+    }
+    if let Some(content) = announcement.content.as_deref() {
+        message = message.content(content);
+    }
+    match message.await {
+        Ok(_) => Ok(()),
+        // Level-ups delivered by DM go nowhere if the member doesn't accept them
+        Err(source) if xpd_util::is_cannot_message_user(&source) => {
+            debug!(channel = ?announcement.channel, "User does not accept DMs, dropping level-up card");
+            state.dms_closed(user).await;
+            Ok(())
+        }
+        Err(source) => Err(source.into()),
+    }
+}
+
 async fn get_customizations_fields(
     state: SlashState,
     user_id: Id<UserMarker>,
@@ -147,7 +202,16 @@ pub async fn get_customizations(
     state: &SlashState,
     ids: &[Id<GenericMarker>],
 ) -> Result<Customizations, Error> {
-    let Some(customizations) = xpd_database::card_customizations(&state.db, ids).await? else {
+    let customizations = xpd_database::card_customizations(&state.db, ids).await?;
+    resolve_customizations(state, customizations)
+}
+
+/// Fill in whatever a card's saved customizations leave unset from its layout's defaults
+pub fn resolve_customizations(
+    state: &SlashState,
+    customizations: Option<RawCustomizations>,
+) -> Result<Customizations, Error> {
+    let Some(customizations) = customizations else {
         return Ok(state.svg.default_customizations().clone());
     };
     let defaults = state
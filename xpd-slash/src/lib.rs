@@ -5,17 +5,21 @@ mod admin;
 mod audit;
 mod autocomplete;
 mod config;
+mod config_panel;
+mod confirm;
 mod dispatch;
 mod error;
 mod experience;
 mod gdpr;
 mod help;
 mod leaderboard;
+mod level_up;
 mod levels;
 mod manage_card;
 mod manager;
 mod response;
 mod rewards;
+mod settings;
 
 use std::{future::Future, sync::Arc, time::Instant};
 
@@ -37,7 +41,10 @@ use twilight_model::{
         marker::{ApplicationMarker, GuildMarker, UserMarker},
     },
 };
-use xpd_common::{EventBusMessage, GuildConfig, RequiredDiscordResources};
+use xpd_common::{
+    AuditLogEvent, EventBusMessage, GuildConfig, LeaderboardSchedule, LevelUpAnnouncement,
+    RequiredDiscordResources,
+};
 use xpd_rank_card::SvgState;
 use xpd_util::LogError;
 
@@ -85,6 +92,8 @@ impl XpdSlash {
             control_guild,
             owners: owners.into(),
             event_bus,
+            confirmations: confirm::Confirmations::default(),
+            reward_syncs: rewards::RewardSyncs::default(),
         };
         Self { state }
     }
@@ -118,6 +127,28 @@ impl XpdSlash {
             })
     }
 
+    /// Post the top of a guild's leaderboard to the channel chosen in its schedule.
+    /// This is skipped if the bot can't send messages in that channel.
+    ///
+    /// # Errors
+    /// Fails if the database or the Discord API can't be reached, or if the winner's card
+    /// can't be rendered.
+    pub async fn post_scheduled_leaderboard(
+        &self,
+        schedule: LeaderboardSchedule,
+    ) -> Result<(), Error> {
+        leaderboard::post_scheduled_leaderboard(&self.state, schedule).await
+    }
+
+    /// Render the member's rank card and post it with their level-up announcement.
+    ///
+    /// # Errors
+    /// Fails if the database or the Discord API can't be reached, or if the card
+    /// can't be rendered.
+    pub async fn post_level_up_card(&self, announcement: LevelUpAnnouncement) -> Result<(), Error> {
+        levels::post_level_up_card(&self.state, announcement).await
+    }
+
     #[must_use]
     pub fn client(&self) -> Arc<twilight_http::Client> {
         self.state.client.clone()
@@ -156,6 +187,8 @@ pub struct SlashState {
     pub owners: Arc<[Id<UserMarker>]>,
     pub control_guild: Id<GuildMarker>,
     pub event_bus: EventBus,
+    confirmations: confirm::Confirmations,
+    reward_syncs: rewards::RewardSyncs,
 }
 
 impl SlashState {
@@ -166,12 +199,56 @@ impl SlashState {
             .await;
     }
 
+    /// Post committed audit log events to their guild's mod-log channel
+    pub async fn notify_audit_log(&self, events: impl IntoIterator<Item = AuditLogEvent>) {
+        for event in events {
+            let _ = self
+                .event_bus
+                .send(EventBusMessage::AuditLogEvent(event))
+                .await;
+        }
+    }
+
+    /// Re-evaluate role rewards after XP changed, for one user or the whole guild.
+    /// Rewards above the new level are only removed if `level_dropped` is set.
+    pub async fn recheck_rewards(
+        &self,
+        guild: Id<GuildMarker>,
+        user: Option<Id<UserMarker>>,
+        level_dropped: bool,
+    ) {
+        let _ = self
+            .event_bus
+            .send(EventBusMessage::RecheckRewards(guild, user, level_dropped))
+            .await;
+    }
+
     pub async fn invalidate_rewards(&self, guild: Id<GuildMarker>) {
         let _ = self
             .event_bus
             .send(EventBusMessage::InvalidateRewards(guild))
             .await;
     }
+
+    /// Apply a user's level-up DM setting to announcements straight away
+    pub async fn update_level_up_dms(&self, user: Id<UserMarker>, enabled: bool) {
+        let _ = self
+            .event_bus
+            .send(EventBusMessage::UpdateLevelUpDms(user, enabled))
+            .await;
+    }
+
+    /// Pause level-up DMs to a user who doesn't accept them
+    pub async fn dms_closed(&self, user: Id<UserMarker>) {
+        let _ = self.event_bus.send(EventBusMessage::DmsClosed(user)).await;
+    }
+
+    pub async fn invalidate_level_up_messages(&self, guild: Id<GuildMarker>) {
+        let _ = self
+            .event_bus
+            .send(EventBusMessage::InvalidateLevelUpMessages(guild))
+            .await;
+    }
 }
 
 #[derive(Copy, Clone)]
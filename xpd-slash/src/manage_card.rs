@@ -7,12 +7,15 @@ use twilight_model::{
     },
 };
 use twilight_util::builder::embed::{EmbedBuilder, ImageSource};
-use xpd_common::MemberDisplayInfo;
-use xpd_database::CardUpdate;
+use xpd_common::{AuditLogEventKind, MemberDisplayInfo};
+use xpd_database::{AcquireWrapper as _, CardUpdate};
 use xpd_rank_card::NameableItem;
 use xpd_slash_defs::card::{CardCommand, CardCommandEdit, ColorOption, GuildCardCommand};
 
-use crate::{Error, SlashState, UserStats, XpdInteractionData, response::XpdInteractionResponse};
+use crate::{
+    Error, SlashState, UserStats, XpdInteractionData, audit::AuditData,
+    response::XpdInteractionResponse,
+};
 
 pub async fn user_card_update(
     command: CardCommand,
@@ -60,11 +63,17 @@ pub async fn guild_card_update(
     command: GuildCardCommand,
     state: &SlashState,
     guild_id: Id<GuildMarker>,
+    audit: AuditData,
 ) -> Result<XpdInteractionResponse, Error> {
     let contents = match command {
-        GuildCardCommand::Reset(_reset) => process_reset(state, guild_id.cast()).await?,
+        GuildCardCommand::Reset(_reset) => {
+            audited_guild_card_change(state, guild_id, audit, None).await?
+        }
         GuildCardCommand::Fetch(_fetch) => process_fetch(state, &[guild_id.cast()]).await?,
-        GuildCardCommand::Edit(edit) => process_edit(edit, state, guild_id.cast()).await?,
+        GuildCardCommand::Edit(edit) => {
+            let update = card_update(edit, state)?;
+            audited_guild_card_change(state, guild_id, audit, Some(update)).await?
+        }
     };
     let referenced_user = fake_user(guild_id.cast());
     let level_info = LevelInfo::new(40);
@@ -87,6 +96,39 @@ pub async fn guild_card_update(
         .into_interaction_response(InteractionResponseType::ChannelMessageWithSource))
 }
 
+/// Applies `update` to the guild's card, or resets it when there is none, recording
+/// the card before and after in the audit log in the same transaction
+async fn audited_guild_card_change(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+    update: Option<CardUpdate>,
+) -> Result<String, Error> {
+    let card_id = [guild_id.cast()];
+    let mut txn = state.db.xbegin().await?;
+    let before = xpd_database::card_customizations(txn.as_mut(), &card_id).await?;
+    let before = crate::levels::resolve_customizations(state, before)?;
+    let contents = if let Some(update) = update {
+        xpd_database::update_card(txn.as_mut(), guild_id.cast(), &update).await?;
+        "Updated card!"
+    } else {
+        xpd_database::delete_card_customizations(txn.as_mut(), guild_id.cast()).await?;
+        "Card settings cleared!"
+    };
+    let after = xpd_database::card_customizations(txn.as_mut(), &card_id).await?;
+    let after = crate::levels::resolve_customizations(state, after)?;
+    let event = audit.change_event(
+        guild_id,
+        AuditLogEventKind::GuildCardUpdate,
+        &before,
+        &after,
+    )?;
+    xpd_database::add_audit_log_event(txn.as_mut(), event.clone()).await?;
+    txn.commit().await?;
+    state.notify_audit_log([event]).await;
+    Ok(contents.to_string())
+}
+
 fn process_edit_helper<I: NameableItem>(
     items: &[I],
     field: Option<String>,
@@ -113,12 +155,19 @@ async fn process_edit(
     state: &SlashState,
     id: Id<GenericMarker>,
 ) -> Result<String, Error> {
+    let update = card_update(edit, state)?;
+    xpd_database::update_card(&state.db, id, &update).await?;
+    Ok("Updated card!".to_string())
+}
+
+/// Check an edit's choices against the available cards, fonts and toys
+fn card_update(edit: CardCommandEdit, state: &SlashState) -> Result<CardUpdate, Error> {
     let items = state.svg.config();
     let toy_image = process_edit_helper(&items.toys, edit.toy_image, Error::UnknownToy)?;
     let card_layout = process_edit_helper(&items.cards, edit.card_layout, Error::UnknownCard)?;
     let font = process_edit_helper(&items.fonts, edit.font, Error::UnknownFont)?;
 
-    let update = CardUpdate {
+    Ok(CardUpdate {
         username: edit.username.map(ColorOption::string),
         rank: edit.rank.map(ColorOption::string),
         level: edit.level.map(ColorOption::string),
@@ -132,11 +181,7 @@ async fn process_edit(
         toy_image,
         card_layout,
         card_layout_default: "classic.svg".to_string(),
-    };
-
-    xpd_database::update_card(&state.db, id, &update).await?;
-
-    Ok("Updated card!".to_string())
+    })
 }
 
 fn matches_config_item<I: NameableItem>(ci: &I, choice: &str) -> Option<String> {
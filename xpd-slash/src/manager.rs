@@ -10,31 +10,41 @@ use twilight_model::{
     },
 };
 use twilight_util::builder::embed::EmbedBuilder;
+use xpd_common::{AuditLogChange, AuditLogEvent, AuditLogEventKind};
 use xpd_database::AcquireWrapper as _;
-use xpd_slash_defs::manage::{CONFIRMATION_STRING, ManageCommand};
+use xpd_slash_defs::manage::ManageCommand;
 
 use crate::{
-    Error, SlashState, XpdInteractionData, dispatch::Respondable, response::XpdInteractionResponse,
+    Error, SlashState, XpdInteractionData, audit::AuditData, confirm::ConfirmAction,
+    dispatch::Respondable, response::XpdInteractionResponse,
 };
 
-pub async fn process_manage(
+pub fn process_manage(
     data: ManageCommand,
     guild_id: Id<GuildMarker>,
     respondable: Respondable,
+    audit: AuditData,
     state: SlashState,
 ) -> Result<XpdInteractionResponse, Error> {
     let contents = match data {
-        ManageCommand::ResetGuild(rg) => {
-            reset_guild_xp(state, guild_id, rg.confirm_message).await?
+        ManageCommand::ResetGuild(_) => {
+            return Ok(crate::confirm::prompt(
+                &state,
+                audit.invoker,
+                ConfirmAction::ResetGuild(guild_id),
+                "This will delete ALL leveling data in this server, including everyone's XP \
+                and the audit log.",
+            ));
         }
         ManageCommand::Import(import) => import_level_data(
             state,
             respondable,
             guild_id,
+            audit,
             import.levels,
             import.overwrite.unwrap_or(false),
         )?,
-        ManageCommand::Export(_) => export_level_data(state, respondable, guild_id)?,
+        ManageCommand::Export(_) => export_level_data(state, respondable, guild_id, audit)?,
     };
     Ok(XpdInteractionData::new()
         .allowed_mentions(AllowedMentions::default())
@@ -54,6 +64,7 @@ fn export_level_data(
     state: SlashState,
     respondable: Respondable,
     guild_id: Id<GuildMarker>,
+    audit: AuditData,
 ) -> Result<String, Error> {
     state
         .task_tracker
@@ -62,6 +73,7 @@ fn export_level_data(
             state,
             respondable,
             guild_id,
+            audit,
             None,
             false,
         ));
@@ -92,6 +104,7 @@ fn import_level_data(
     state: SlashState,
     respondable: Respondable,
     guild_id: Id<GuildMarker>,
+    audit: AuditData,
     attachment: Attachment,
     overwrite: bool,
 ) -> Result<String, Error> {
@@ -99,6 +112,7 @@ fn import_level_data(
         state,
         respondable,
         guild_id,
+        audit,
         Some(attachment),
         overwrite,
     ));
@@ -110,6 +124,7 @@ const MAX_IMPORT_SIZE: usize = 1024 * 1024 * 10;
 async fn background_data_import(
     state: &SlashState,
     guild_id: Id<GuildMarker>,
+    audit: AuditData,
     attachment: Attachment,
     overwrite: bool,
 ) -> Result<XpdInteractionData, Error> {
@@ -128,6 +143,7 @@ async fn background_data_import(
     let data: Vec<ImportUser> = serde_json::from_slice(&body)?;
     let user_count = data.len();
     let mut txn = state.db.xbegin().await?;
+    let old_total = xpd_database::total_guild_xp(txn.as_mut(), guild_id).await?;
     for user in data {
         if overwrite {
             xpd_database::set_xp(txn.as_mut(), user.id, guild_id, user.xp).await?;
@@ -135,8 +151,26 @@ async fn background_data_import(
             xpd_database::add_xp(txn.as_mut(), user.id, guild_id, user.xp).await?;
         }
     }
-
+    let new_total = xpd_database::total_guild_xp(txn.as_mut(), guild_id).await?;
+
+    let event = AuditLogEvent {
+        guild: guild_id,
+        target: None,
+        moderator: audit.invoker,
+        timestamp: audit.timestamp(),
+        previous: old_total,
+        delta: new_total - old_total,
+        kind: AuditLogEventKind::Import,
+        details: Some(AuditLogChange {
+            before: serde_json::Value::Null,
+            after: serde_json::json!({ "users": user_count, "overwrite": overwrite }),
+        }),
+    };
+    xpd_database::add_audit_log_event(txn.as_mut(), event.clone()).await?;
     txn.commit().await?;
+    state.notify_audit_log([event]).await;
+    // Without overwriting, imported XP is only ever added on top
+    state.recheck_rewards(guild_id, None, overwrite).await;
 
     let seconds = start.elapsed().as_secs_f64();
     Ok(XpdInteractionData::with_embed_text(format!(
@@ -148,11 +182,12 @@ async fn background_data_operation_wrapper(
     state: SlashState,
     respondable: Respondable,
     guild_id: Id<GuildMarker>,
+    audit: AuditData,
     attachment: Option<Attachment>,
     overwrite: bool,
 ) {
     let xsr = if let Some(attachment) = attachment {
-        background_data_import(&state, guild_id, attachment, overwrite)
+        background_data_import(&state, guild_id, audit, attachment, overwrite)
             .await
             .unwrap_or_else(|source| {
                 error!(?source, "Failed to import level data");
@@ -174,19 +209,34 @@ async fn background_data_operation_wrapper(
     state.send_followup(xsr, respondable.token()).await;
 }
 
-async fn reset_guild_xp(
+pub async fn reset_guild_xp(
     state: SlashState,
     guild_id: Id<GuildMarker>,
-    confirmation: String,
+    audit: AuditData,
 ) -> Result<String, Error> {
-    if confirmation != CONFIRMATION_STRING {
-        return Ok("Confirmation string did not match.".to_string());
-    }
-
     let mut txn = state.db.xbegin().await?;
-    xpd_database::delete_levels_guild(&mut txn, guild_id).await?;
+    let total_xp = xpd_database::total_guild_xp(&mut txn, guild_id).await?;
+    let users = xpd_database::delete_levels_guild(&mut txn, guild_id).await?;
+    xpd_database::delete_soft_deleted_levels_guild(&mut txn, guild_id).await?;
     xpd_database::delete_audit_log_events_guild(&mut txn, guild_id).await?;
+    // The rest of the audit log is gone, but the reset itself is kept
+    let event = AuditLogEvent {
+        guild: guild_id,
+        target: None,
+        moderator: audit.invoker,
+        timestamp: audit.timestamp(),
+        previous: total_xp,
+        delta: -total_xp,
+        kind: AuditLogEventKind::GuildReset,
+        details: Some(AuditLogChange {
+            before: serde_json::json!({ "users": users }),
+            after: serde_json::Value::Null,
+        }),
+    };
+    xpd_database::add_audit_log_event(&mut txn, event.clone()).await?;
     txn.commit().await?;
+    state.notify_audit_log([event]).await;
+    state.recheck_rewards(guild_id, None, true).await;
 
     Ok("Done. Thank you for using Experienced.".to_string())
 }
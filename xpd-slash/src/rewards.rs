@@ -1,24 +1,46 @@
-use std::fmt::Write;
+use std::{
+    fmt::Write,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+use dashmap::DashSet;
+use sqlx::PgConnection;
 use twilight_model::{
     channel::message::AllowedMentions,
     http::interaction::InteractionResponseType,
-    id::{Id, marker::GuildMarker},
+    id::{
+        Id,
+        marker::{GuildMarker, RoleMarker, UserMarker},
+    },
 };
 use twilight_util::builder::embed::EmbedBuilder;
-use xpd_slash_defs::rewards::{RewardsCommand, RewardsCommandAdd, RewardsCommandRemove};
+use xpd_common::{AuditLogEvent, AuditLogEventKind, RankReward, RewardGrant, RoleReward};
+use xpd_database::AcquireWrapper as _;
+use xpd_slash_defs::rewards::{
+    RewardsCommand, RewardsCommandAdd, RewardsCommandAddRank, RewardsCommandRemove,
+    RewardsCommandSync,
+};
+use xpd_util::{LogError as _, RoleChangeList};
 
-use crate::{Error, SlashState, XpdInteractionData, response::XpdInteractionResponse};
+use crate::{
+    Error, SlashState, XpdInteractionData, audit::AuditData, dispatch::Respondable,
+    response::XpdInteractionResponse,
+};
 
 pub async fn process_rewards(
     cmd: RewardsCommand,
     guild_id: Id<GuildMarker>,
+    respondable: Respondable,
+    audit: AuditData,
     state: SlashState,
 ) -> Result<XpdInteractionResponse, Error> {
     let contents = match cmd {
-        RewardsCommand::Add(add) => process_rewards_add(add, state, guild_id).await,
-        RewardsCommand::Remove(remove) => process_rewards_rm(remove, state, guild_id).await,
+        RewardsCommand::Add(add) => process_rewards_add(add, state, guild_id, audit).await,
+        RewardsCommand::AddRank(add) => process_rewards_add_rank(add, state, guild_id, audit).await,
+        RewardsCommand::Remove(remove) => process_rewards_rm(remove, state, guild_id, audit).await,
         RewardsCommand::List(_list) => process_rewards_list(state, guild_id).await,
+        RewardsCommand::Sync(sync) => process_rewards_sync(&sync, state, guild_id, respondable),
     }?;
     Ok(XpdInteractionData::new()
         .allowed_mentions(AllowedMentions::default())
@@ -31,22 +53,125 @@ async fn process_rewards_add(
     options: RewardsCommandAdd,
     state: SlashState,
     guild_id: Id<GuildMarker>,
+    audit: AuditData,
 ) -> Result<String, Error> {
-    xpd_database::add_reward_role(&state.db, guild_id, options.level, options.role.id).await?;
+    let mut txn = state.db.xbegin().await?;
+    if xpd_database::guild_rank_rewards(&mut txn, guild_id)
+        .await?
+        .iter()
+        .any(|reward| reward.id == options.role.id)
+    {
+        return Err(Error::RewardRoleInUse("rank"));
+    }
+    let old_reward = xpd_database::guild_rewards(&mut txn, guild_id)
+        .await?
+        .into_iter()
+        .find(|reward| reward.id == options.role.id);
+    xpd_database::add_reward_role(
+        &mut txn,
+        guild_id,
+        options.level,
+        options.role.id,
+        options.duration_hours,
+    )
+    .await?;
+    let new_reward = RoleReward {
+        id: options.role.id,
+        requirement: options.level,
+        duration_hours: options.duration_hours,
+    };
+    let event = audit.change_event(
+        guild_id,
+        AuditLogEventKind::RewardAdd,
+        &old_reward,
+        &new_reward,
+    )?;
+    xpd_database::add_audit_log_event(&mut txn, event.clone()).await?;
+    txn.commit().await?;
+    state.notify_audit_log([event]).await;
     state.invalidate_rewards(guild_id).await;
+    let duration = options
+        .duration_hours
+        .map_or_else(String::new, |hours| format!(", lasting {hours} hours"));
     Ok(format!(
-        "Added role reward <@&{}> at level {}!",
+        "Added role reward <@&{}> at level {}{duration}!",
         options.role.id, options.level
     ))
 }
 
+async fn process_rewards_add_rank(
+    options: RewardsCommandAddRank,
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+    audit: AuditData,
+) -> Result<String, Error> {
+    let mut txn = state.db.xbegin().await?;
+    if xpd_database::guild_rewards(&mut txn, guild_id)
+        .await?
+        .iter()
+        .any(|reward| reward.id == options.role.id)
+    {
+        return Err(Error::RewardRoleInUse("level"));
+    }
+    let old_reward = xpd_database::guild_rank_rewards(&mut txn, guild_id)
+        .await?
+        .into_iter()
+        .find(|reward| reward.id == options.role.id);
+    xpd_database::add_rank_reward(&mut txn, guild_id, options.rank, options.role.id).await?;
+    let new_reward = RankReward {
+        id: options.role.id,
+        max_rank: options.rank,
+    };
+    let event = audit.change_event(
+        guild_id,
+        AuditLogEventKind::RewardAdd,
+        &old_reward,
+        &new_reward,
+    )?;
+    xpd_database::add_audit_log_event(&mut txn, event.clone()).await?;
+    txn.commit().await?;
+    state.notify_audit_log([event]).await;
+    // The listener moves the role onto the current top members once it reloads rewards
+    state.invalidate_rewards(guild_id).await;
+    Ok(format!(
+        "Added rank reward <@&{}> for the top {} members!",
+        options.role.id, options.rank
+    ))
+}
+
 async fn process_rewards_rm(
     options: RewardsCommandRemove,
     state: SlashState,
     guild_id: Id<GuildMarker>,
+    audit: AuditData,
 ) -> Result<String, Error> {
-    match xpd_database::delete_reward_role(&state.db, guild_id, options.level, options.role).await {
+    let mut txn = state.db.xbegin().await?;
+    // Matches the rows delete_reward_role removes
+    let old_rewards: Vec<RoleReward> = xpd_database::guild_rewards(&mut txn, guild_id)
+        .await?
+        .into_iter()
+        .filter(|reward| {
+            Some(reward.id) == options.role || Some(reward.requirement) == options.level
+        })
+        .collect();
+    match xpd_database::delete_reward_role(&mut txn, guild_id, options.level, options.role).await {
         Ok(count) => {
+            let mut events = Vec::new();
+            if count > 0 {
+                let event = audit.change_event(
+                    guild_id,
+                    AuditLogEventKind::RewardRemove,
+                    &old_rewards,
+                    &None::<RoleReward>,
+                )?;
+                xpd_database::add_audit_log_event(&mut txn, event.clone()).await?;
+                events.push(event);
+            }
+            let count = count
+                + remove_rank_reward(txn.as_mut(), guild_id, options.role, &audit, &mut events)
+                    .await?;
+            txn.commit().await?;
+            state.notify_audit_log(events).await;
             state.invalidate_rewards(guild_id).await;
             let pluralizer = if count == 1 { "" } else { "s" };
             Ok(format!("Deleted {count} role reward{pluralizer}."))
@@ -58,24 +183,354 @@ async fn process_rewards_rm(
     }
 }
 
+/// Rank rewards can only be removed by role, since they have no level
+async fn remove_rank_reward(
+    conn: &mut PgConnection,
+    guild_id: Id<GuildMarker>,
+    role: Option<Id<RoleMarker>>,
+    audit: &AuditData,
+    events: &mut Vec<AuditLogEvent>,
+) -> Result<u64, Error> {
+    let Some(role) = role else {
+        return Ok(0);
+    };
+    let old_reward = xpd_database::guild_rank_rewards(&mut *conn, guild_id)
+        .await?
+        .into_iter()
+        .find(|reward| reward.id == role);
+    let count = xpd_database::delete_rank_reward(&mut *conn, guild_id, role).await?;
+    if count > 0 {
+        let event = audit.change_event(
+            guild_id,
+            AuditLogEventKind::RewardRemove,
+            &old_reward,
+            &None::<RankReward>,
+        )?;
+        xpd_database::add_audit_log_event(&mut *conn, event.clone()).await?;
+        events.push(event);
+    }
+    Ok(count)
+}
+
 async fn process_rewards_list(
     state: SlashState,
     guild_id: Id<GuildMarker>,
 ) -> Result<String, Error> {
     let mut roles = xpd_database::guild_rewards(&state.db, guild_id).await?;
-    if roles.is_empty() {
+    let mut rank_roles = xpd_database::guild_rank_rewards(&state.db, guild_id).await?;
+    if roles.is_empty() && rank_roles.is_empty() {
         return Ok("No role rewards set for this server".to_string());
     }
     let mut data = String::new();
 
-    roles.sort_by_key(|v| v.requirement);
+    roles.sort_by(xpd_common::compare_rewards_requirement);
 
-    for role in roles {
+    for level in roles.chunk_by(|a, b| a.requirement == b.requirement) {
+        let mentions: Vec<String> = level
+            .iter()
+            .map(|role| {
+                let duration = role
+                    .duration_hours
+                    .map_or_else(String::new, |hours| format!(" (for {hours} hours)"));
+                format!("<@&{}>{duration}", role.id)
+            })
+            .collect();
         writeln!(
             data,
-            "Role reward <@&{}> at level {}",
-            role.id, role.requirement
+            "Level {}: {}",
+            level[0].requirement,
+            mentions.join(", ")
         )?;
     }
+
+    rank_roles.sort_by_key(|v| v.max_rank);
+    for role in rank_roles {
+        writeln!(data, "Top {}: <@&{}>", role.max_rank, role.id)?;
+    }
     Ok(data)
 }
+
+/// How many members to check between progress reports during a sync
+const SYNC_PROGRESS_INTERVAL: usize = 500;
+/// Interaction tokens stop working after 15 minutes, so anything later goes to the channel.
+/// This leaves a little room for a followup that's already on its way.
+const INTERACTION_TOKEN_LIFETIME: Duration = Duration::from_mins(14);
+
+/// Where a sync reports to: followups while the interaction token lasts, and the channel
+/// the command was used in after that
+struct SyncReporter {
+    respondable: Respondable,
+    started: Instant,
+}
+
+impl SyncReporter {
+    async fn send(&self, state: &SlashState, text: String) {
+        let xsr = XpdInteractionData::with_embed_text(text)
+            .allowed_mentions(AllowedMentions::default())
+            .ephemeral(true);
+        if self.started.elapsed() < INTERACTION_TOKEN_LIFETIME {
+            state.send_followup(xsr, self.respondable.token()).await;
+            return;
+        }
+        let Some(channel) = self.respondable.channel() else {
+            return;
+        };
+        match xpd_util::can_create_message(&state.cache, state.bot_id, channel) {
+            Ok(true) => {}
+            Ok(false) => {
+                warn!(?channel, "Can't post role reward sync progress in channel");
+                return;
+            }
+            Err(source) => {
+                warn!(?source, ?channel, "Failed to check channel permissions");
+                return;
+            }
+        }
+        state
+            .client
+            .create_message(channel)
+            .allowed_mentions(xsr.allowed_mentions.as_ref())
+            .embeds(&xsr.embeds.unwrap_or_default())
+            .await
+            .log_error("Failed to post role reward sync progress");
+    }
+}
+
+/// Guilds with a `/rewards sync` running, so two can't work through the same members at once
+#[derive(Clone, Debug, Default)]
+pub struct RewardSyncs(Arc<DashSet<Id<GuildMarker>>>);
+
+impl RewardSyncs {
+    /// Mark a sync as running in `guild` until the guard is dropped, or return `None`
+    /// if one already is
+    fn start(&self, guild: Id<GuildMarker>) -> Option<RewardSyncGuard> {
+        self.0.insert(guild).then(|| RewardSyncGuard {
+            syncs: self.clone(),
+            guild,
+        })
+    }
+}
+
+struct RewardSyncGuard {
+    syncs: RewardSyncs,
+    guild: Id<GuildMarker>,
+}
+
+impl Drop for RewardSyncGuard {
+    fn drop(&mut self) {
+        self.syncs.0.remove(&self.guild);
+    }
+}
+
+fn process_rewards_sync(
+    options: &RewardsCommandSync,
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+    respondable: Respondable,
+) -> Result<String, Error> {
+    let guard = state
+        .reward_syncs
+        .start(guild_id)
+        .ok_or(Error::RewardSyncInProgress)?;
+    let dry_run = options.dry_run.unwrap_or(false);
+    let reporter = SyncReporter {
+        respondable,
+        started: Instant::now(),
+    };
+    state.clone().spawn(async move {
+        background_sync_wrapper(state, reporter, guild_id, dry_run).await;
+        drop(guard);
+    });
+    if dry_run {
+        Ok("Checking role rewards for all ranked members, check back soon!".to_string())
+    } else {
+        Ok("Syncing role rewards for all ranked members, check back soon!".to_string())
+    }
+}
+
+#[derive(Debug, Default)]
+struct SyncTally {
+    members: usize,
+    checked: usize,
+    updated: usize,
+    roles_added: usize,
+    roles_removed: usize,
+    not_in_server: usize,
+    missing_permissions: usize,
+    failed: usize,
+}
+
+impl SyncTally {
+    fn summary(&self, dry_run: bool) -> Result<String, Error> {
+        let mut out = String::new();
+        if dry_run {
+            writeln!(
+                out,
+                "Dry run: {} of {} ranked members would have their roles updated, \
+                adding {} and removing {} roles.",
+                self.updated, self.members, self.roles_added, self.roles_removed
+            )?;
+        } else {
+            writeln!(
+                out,
+                "Updated roles for {} of {} ranked members, adding {} and removing {} roles.",
+                self.updated, self.members, self.roles_added, self.roles_removed
+            )?;
+        }
+        if self.not_in_server > 0 {
+            writeln!(
+                out,
+                "Skipped {} members who are no longer in this server.",
+                self.not_in_server
+            )?;
+        }
+        if self.missing_permissions > 0 {
+            writeln!(
+                out,
+                "Skipped {} members because I cannot manage their reward roles. \
+                Run `/config perms_checkup` for details.",
+                self.missing_permissions
+            )?;
+        }
+        if self.failed > 0 {
+            writeln!(out, "Failed to update roles for {} members.", self.failed)?;
+        }
+        Ok(out)
+    }
+}
+
+async fn background_sync(
+    state: &SlashState,
+    reporter: &SyncReporter,
+    guild_id: Id<GuildMarker>,
+    dry_run: bool,
+) -> Result<SyncTally, Error> {
+    let config = xpd_database::guild_config(&state.db, guild_id)
+        .await?
+        .unwrap_or_default();
+    let mut rewards = xpd_database::guild_rewards(&state.db, guild_id).await?;
+    rewards.sort_by(xpd_common::compare_rewards_requirement);
+    let users = xpd_database::export_bulk_users(&state.db, guild_id).await?;
+    let timed_rewards = rewards.iter().any(|reward| reward.duration_hours.is_some());
+
+    let mut tally = SyncTally {
+        members: users.len(),
+        ..Default::default()
+    };
+    for user in users {
+        tally.checked += 1;
+        if tally.checked.is_multiple_of(SYNC_PROGRESS_INTERVAL) {
+            let progress = format!(
+                "Checked {} of {} ranked members...",
+                tally.checked, tally.members
+            );
+            reporter.send(state, progress).await;
+        }
+
+        let level = mee6::LevelInfo::new(u64::try_from(user.xp).unwrap_or(0)).level();
+        let reward_idx = xpd_util::get_reward_idx(&rewards, level.try_into()?);
+        let Some(member_roles) = state
+            .cache
+            .member(guild_id, user.id)
+            .map(|member| member.roles().to_vec())
+        else {
+            tally.not_in_server += 1;
+            continue;
+        };
+        let mut changes =
+            xpd_util::get_role_changes(&config, &member_roles, &rewards, reward_idx, false);
+        let grants = if timed_rewards {
+            xpd_database::user_reward_grants(&state.db, guild_id, user.id).await?
+        } else {
+            Vec::new()
+        };
+        // Time-limited rewards which have run out stay gone
+        let expired: Vec<Id<RoleMarker>> = grants
+            .iter()
+            .filter(|grant| grant.expired)
+            .map(|grant| grant.role)
+            .collect();
+        changes.remove_roles(&member_roles, &expired);
+        if changes.changed_roles.is_empty() {
+            continue;
+        }
+        if !xpd_util::can_manage_roles(
+            &state.cache,
+            state.bot_id,
+            guild_id,
+            &changes.changed_roles,
+        )?
+        .can_update_roles()
+        {
+            tally.missing_permissions += 1;
+            continue;
+        }
+
+        if !dry_run {
+            // Requests are awaited one at a time, so twilight's ratelimiter keeps us within
+            // the member-edit bucket instead of bursting through it.
+            let result = state
+                .client
+                .update_guild_member(guild_id, user.id)
+                .roles(&changes.total_roles)
+                .await;
+            if result.is_err() {
+                result.log_error("Failed to sync member role rewards");
+                tally.failed += 1;
+                continue;
+            }
+            record_grants(state, guild_id, user.id, &rewards, &changes, &grants).await?;
+        }
+        let added = changes
+            .changed_roles
+            .iter()
+            .filter(|role| changes.total_roles.contains(role))
+            .count();
+        tally.updated += 1;
+        tally.roles_added += added;
+        tally.roles_removed += changes.changed_roles.len() - added;
+    }
+    Ok(tally)
+}
+
+/// Start the clock on any time-limited rewards a sync just gave out
+async fn record_grants(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+    user_id: Id<UserMarker>,
+    rewards: &[RoleReward],
+    changes: &RoleChangeList,
+    grants: &[RewardGrant],
+) -> Result<(), Error> {
+    for reward in rewards {
+        let Some(duration_hours) = reward.duration_hours else {
+            continue;
+        };
+        let added =
+            changes.changed_roles.contains(&reward.id) && changes.total_roles.contains(&reward.id);
+        if added && !grants.iter().any(|grant| grant.role == reward.id) {
+            xpd_database::add_reward_grant(&state.db, guild_id, user_id, reward.id, duration_hours)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn background_sync_wrapper(
+    state: SlashState,
+    reporter: SyncReporter,
+    guild_id: Id<GuildMarker>,
+    dry_run: bool,
+) {
+    let text = match background_sync(&state, &reporter, guild_id, dry_run).await {
+        Ok(tally) => tally.summary(dry_run).unwrap_or_else(|source| {
+            error!(?source, "Failed to format role reward sync summary");
+            "Role reward sync finished.".to_string()
+        }),
+        Err(source) => {
+            error!(?source, "Failed to sync role rewards");
+            format!("Failed to sync role rewards: {source}")
+        }
+    };
+    reporter.send(&state, text).await;
+}
@@ -0,0 +1,118 @@
+use std::fmt::Write as _;
+
+use twilight_model::{
+    http::interaction::InteractionResponseType,
+    id::{
+        Id,
+        marker::{GuildMarker, UserMarker},
+    },
+};
+use xpd_common::UserSettings;
+use xpd_database::{AcquireWrapper as _, UpdateUserSettings};
+use xpd_slash_defs::settings::{
+    SettingsCommand, SettingsCommandLeveling, SettingsCommandNotifications, SettingsCommandPrivacy,
+};
+
+use crate::{Error, SlashState, XpdInteractionData, response::XpdInteractionResponse};
+
+pub async fn process_settings(
+    cmd: SettingsCommand,
+    invoker: Id<UserMarker>,
+    guild_id: Option<Id<GuildMarker>>,
+    state: SlashState,
+) -> Result<XpdInteractionResponse, Error> {
+    let contents = match cmd {
+        SettingsCommand::Notifications(notifs) => {
+            process_notifications(notifs, invoker, state).await
+        }
+        SettingsCommand::Leveling(leveling) => {
+            process_leveling(leveling, invoker, guild_id, state).await
+        }
+        SettingsCommand::Privacy(privacy) => {
+            process_privacy(privacy, invoker, guild_id, state).await
+        }
+    }?;
+    Ok(XpdInteractionData::with_embed_text(contents)
+        .ephemeral(true)
+        .into_interaction_response(InteractionResponseType::ChannelMessageWithSource))
+}
+
+async fn process_notifications(
+    options: SettingsCommandNotifications,
+    invoker: Id<UserMarker>,
+    state: SlashState,
+) -> Result<String, Error> {
+    let update = UpdateUserSettings::new().level_up_dms(options.level_up_dms);
+    let settings = xpd_database::update_user_settings(&state.db, invoker, update).await?;
+    state
+        .update_level_up_dms(invoker, settings.level_up_dms)
+        .await;
+    Ok(describe_notifications(settings))
+}
+
+fn describe_notifications(settings: UserSettings) -> String {
+    if settings.level_up_dms {
+        "Servers that announce level-ups in DMs will DM you when you level up.".to_string()
+    } else {
+        "You won't get level-up DMs from any server. \
+        Servers that announce level-ups in DMs won't announce yours at all."
+            .to_string()
+    }
+}
+
+async fn process_leveling(
+    options: SettingsCommandLeveling,
+    invoker: Id<UserMarker>,
+    guild_id: Option<Id<GuildMarker>>,
+    state: SlashState,
+) -> Result<String, Error> {
+    let mut txn = state.db.xbegin().await?;
+    let settings = if options.this_server_only == Some(true) {
+        let guild_id = guild_id.ok_or(Error::SettingNeedsGuild)?;
+        xpd_database::set_guild_opt_out(&mut txn, invoker, guild_id, !options.enabled).await?;
+        xpd_database::user_settings(&mut txn, invoker).await?
+    } else {
+        let update = UpdateUserSettings::new().xp_opt_out(Some(!options.enabled));
+        xpd_database::update_user_settings(&mut txn, invoker, update).await?
+    };
+    let opted_out_guilds = xpd_database::user_guild_opt_outs(&mut txn, invoker).await?;
+    txn.commit().await?;
+    describe_settings(settings, guild_id, &opted_out_guilds)
+}
+
+async fn process_privacy(
+    options: SettingsCommandPrivacy,
+    invoker: Id<UserMarker>,
+    guild_id: Option<Id<GuildMarker>>,
+    state: SlashState,
+) -> Result<String, Error> {
+    let update = UpdateUserSettings::new()
+        .hide_from_leaderboard(options.hide_from_leaderboard)
+        .hide_from_rank(options.hide_from_rank);
+    let settings = xpd_database::update_user_settings(&state.db, invoker, update).await?;
+    let opted_out_guilds = xpd_database::user_guild_opt_outs(&state.db, invoker).await?;
+    describe_settings(settings, guild_id, &opted_out_guilds)
+}
+
+fn describe_settings(
+    settings: UserSettings,
+    guild_id: Option<Id<GuildMarker>>,
+    opted_out_guilds: &[Id<GuildMarker>],
+) -> Result<String, Error> {
+    let mut out = format!("### Your settings\n{settings}\n");
+    if let Some(guild_id) = guild_id {
+        writeln!(
+            out,
+            "Earning XP in this server: {}",
+            !settings.xp_opt_out && !opted_out_guilds.contains(&guild_id)
+        )?;
+    }
+    if !opted_out_guilds.is_empty() {
+        writeln!(
+            out,
+            "Not earning XP in {} specific server(s)",
+            opted_out_guilds.len()
+        )?;
+    }
+    Ok(out)
+}
@@ -1,4 +1,11 @@
-use twilight_cache_inmemory::{CacheableRole, InMemoryCache};
+use std::{borrow::Cow, cmp::Reverse, collections::HashMap};
+
+use simpleinterpolation::Interpolation;
+use twilight_cache_inmemory::{CacheableRole, InMemoryCache, permission::ChannelErrorType};
+use twilight_http::{
+    api_error::{ApiError, GeneralApiError},
+    error::ErrorType,
+};
 use twilight_model::{
     guild::{Member, Permissions},
     id::{
@@ -7,10 +14,50 @@ use twilight_model::{
     },
     user::User,
 };
+use xpd_common::{GuildConfig, LevelUpMessage, RankReward, RoleReward};
 
 #[macro_use]
 extern crate tracing;
 
+/// Discord's error code for a user who doesn't accept DMs from us
+const CANNOT_MESSAGE_USER: u64 = 50007;
+/// Discord's error codes for a guild, member or role that doesn't exist (anymore)
+const UNKNOWN_GUILD: u64 = 10004;
+const UNKNOWN_MEMBER: u64 = 10007;
+const UNKNOWN_ROLE: u64 = 10011;
+
+/// Whether a failed request was Discord refusing to deliver a DM, because the user
+/// has DMs closed, blocked us, or shares no servers with us anymore.
+#[must_use]
+pub fn is_cannot_message_user(error: &twilight_http::Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorType::Response {
+            error: ApiError::General(GeneralApiError {
+                code: CANNOT_MESSAGE_USER,
+                ..
+            }),
+            ..
+        }
+    )
+}
+
+/// Whether a failed request was about a guild, member or role that's gone, so there's
+/// nothing left to change.
+#[must_use]
+pub fn is_unknown_member_or_role(error: &twilight_http::Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorType::Response {
+            error: ApiError::General(GeneralApiError {
+                code: UNKNOWN_GUILD | UNKNOWN_MEMBER | UNKNOWN_ROLE,
+                ..
+            }),
+            ..
+        }
+    )
+}
+
 pub fn can_manage_roles(
     cache: &InMemoryCache,
     bot_id: Id<UserMarker>,
@@ -77,6 +124,19 @@ impl CanAddRole {
     }
 }
 
+impl std::fmt::Display for CanAddRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CanAddRole::Yes => "I can manage every role involved",
+            CanAddRole::NoManageRoles => "I don't have the Manage Roles permission",
+            CanAddRole::HighestRoleIsLowerRoleThanTarget => {
+                "My highest role is below a role I need to assign"
+            }
+            CanAddRole::RoleIsManaged => "A role I need to assign is managed by an integration",
+        })
+    }
+}
+
 pub fn can_create_message(
     cache: &InMemoryCache,
     bot_id: Id<UserMarker>,
@@ -92,6 +152,149 @@ pub fn can_create_message(
         .map_err(Into::into)
 }
 
+/// Why a single role can't be given out by us
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoleProblem {
+    /// The role no longer exists
+    Deleted,
+    /// The role belongs to an integration, like a bot or server boosting
+    Managed,
+    /// The role is at or above our highest role
+    AboveMe,
+}
+
+/// Everything that stops us from giving out a set of roles, role by role
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoleDiagnostics {
+    pub manage_roles: bool,
+    pub highest_role: Id<RoleMarker>,
+    /// Only the roles with a problem are listed, in the order they were checked
+    pub problems: Vec<(Id<RoleMarker>, RoleProblem)>,
+}
+
+/// Like [`can_manage_roles`], but checks every role on its own instead of stopping at the
+/// first one we can't give out.
+pub fn diagnose_roles(
+    cache: &InMemoryCache,
+    bot_id: Id<UserMarker>,
+    guild_id: Id<GuildMarker>,
+    targets: &[Id<RoleMarker>],
+) -> Result<RoleDiagnostics, PermissionCheckError> {
+    let manage_roles = cache
+        .permissions()
+        .root(bot_id, guild_id)?
+        .contains(Permissions::MANAGE_ROLES);
+    let highest_role = cache
+        .member_highest_role(guild_id, bot_id)
+        .ok_or(PermissionCheckError::NoHighestRoleForSelf)?;
+    let my_rank = cache
+        .role(highest_role)
+        .map(|role| role_rank(role.resource()))
+        .ok_or(PermissionCheckError::UnknownPositionForOwnHighestRole)?;
+
+    let problems = targets
+        .iter()
+        .filter_map(|&target| {
+            let problem = match cache.role(target) {
+                None => RoleProblem::Deleted,
+                Some(role) if role.managed => RoleProblem::Managed,
+                Some(role) if role_rank(role.resource()) >= my_rank => RoleProblem::AboveMe,
+                Some(_) => return None,
+            };
+            Some((target, problem))
+        })
+        .collect();
+    Ok(RoleDiagnostics {
+        manage_roles,
+        highest_role,
+        problems,
+    })
+}
+
+/// Sorts the same way Discord orders roles: by position, with the older role on top in a tie.
+fn role_rank(role: &impl CacheableRole) -> (i64, Reverse<Id<RoleMarker>>) {
+    (role.position(), Reverse(role.id()))
+}
+
+/// Whether we can post what we need to in a channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelAccess {
+    Ok,
+    /// The channel no longer exists
+    Deleted,
+    /// We're missing these permissions in the channel
+    Missing(Permissions),
+}
+
+pub fn channel_access(
+    cache: &InMemoryCache,
+    bot_id: Id<UserMarker>,
+    channel_id: Id<ChannelMarker>,
+    required: Permissions,
+) -> Result<ChannelAccess, PermissionCheckError> {
+    let permissions = match cache.permissions().in_channel(bot_id, channel_id) {
+        Ok(permissions) => permissions,
+        Err(source) if matches!(source.kind(), ChannelErrorType::ChannelUnavailable { .. }) => {
+            return Ok(ChannelAccess::Deleted);
+        }
+        Err(source) => return Err(source.into()),
+    };
+    let missing = required - permissions;
+    if missing.is_empty() {
+        Ok(ChannelAccess::Ok)
+    } else {
+        Ok(ChannelAccess::Missing(missing))
+    }
+}
+
+/// The pool of level-up messages to pick from at `level`: the ones with the narrowest range
+/// that covers it. Empty if no message covers the level.
+#[must_use]
+pub fn level_up_message_pool(messages: &[LevelUpMessage], level: i64) -> Vec<&LevelUpMessage> {
+    let covering = messages.iter().filter(|message| message.covers(level));
+    let Some(narrowest) = covering.clone().map(LevelUpMessage::span).min() else {
+        return Vec::new();
+    };
+    covering
+        .filter(|message| message.span() == narrowest)
+        .collect()
+}
+
+/// The most characters Discord allows in a message's content
+pub const MAX_MESSAGE_LENGTH: usize = 2000;
+/// The most characters Discord allows in an embed's title
+pub const MAX_EMBED_TITLE_LENGTH: usize = 256;
+/// The most characters Discord allows in an embed's description
+pub const MAX_EMBED_DESCRIPTION_LENGTH: usize = 4096;
+/// The most characters Discord allows in an embed's footer
+pub const MAX_EMBED_FOOTER_LENGTH: usize = 2048;
+
+/// The longest a rendered level-up message could be, in characters.
+///
+/// Every variable is filled in with the longest value it could have: 32 characters for names,
+/// 20 digits for IDs, 19 digits for XP, and 7 digits for levels, since nobody can reach a level
+/// with more digits than that without overflowing their XP.
+#[must_use]
+pub fn max_level_up_message_len(template: &Interpolation) -> usize {
+    let id = u64::MAX.to_string();
+    let mention = format!("<@{id}>");
+    let name = "W".repeat(32);
+    let level = "9".repeat(7);
+    let xp = i64::MAX.to_string();
+    let map: HashMap<Cow<str>, Cow<str>> = HashMap::from([
+        (Cow::Borrowed("user_id"), Cow::Borrowed(id.as_str())),
+        ("user_mention".into(), mention.as_str().into()),
+        ("user_username".into(), name.as_str().into()),
+        ("user_display_name".into(), name.as_str().into()),
+        ("user_nickname".into(), name.as_str().into()),
+        ("old_level".into(), level.as_str().into()),
+        ("level".into(), level.as_str().into()),
+        ("old_xp".into(), xp.as_str().into()),
+        ("xp".into(), xp.as_str().into()),
+    ]);
+    template.render(&map).chars().count()
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum PermissionCheckError {
     #[error("Unknown role: <@&{0}>")]
@@ -154,3 +357,538 @@ impl DisplayName for xpd_common::MemberDisplayInfo {
             .unwrap_or(&self.name)
     }
 }
+
+pub type RoleList = Vec<Id<RoleMarker>>;
+
+/// Find the index of the highest reward earned at `user_level`, if any.
+/// `rewards` must be sorted by requirement, and the index is that of the last reward in its level.
+#[must_use]
+pub fn get_reward_idx(rewards: &[RoleReward], user_level: i64) -> Option<usize> {
+    let mut reward_idx = None;
+    for (idx, data) in rewards.iter().enumerate() {
+        if data.requirement > user_level {
+            break;
+        }
+        reward_idx = Some(idx);
+    }
+    reward_idx
+}
+
+/// The role list a member should end up with, and which of those roles were added or removed
+#[derive(Debug)]
+pub struct RoleChangeList {
+    pub total_roles: RoleList,
+    pub changed_roles: RoleList,
+}
+
+impl RoleChangeList {
+    /// Take `roles` away from the result, whether the member already had them or they were being added
+    pub fn remove_roles(&mut self, member_roles: &[Id<RoleMarker>], roles: &[Id<RoleMarker>]) {
+        self.total_roles.retain(|v| !roles.contains(v));
+        self.changed_roles
+            .retain(|v| !roles.contains(v) || member_roles.contains(v));
+        for role in roles {
+            if member_roles.contains(role) && !self.changed_roles.contains(role) {
+                self.changed_roles.push(*role);
+            }
+        }
+    }
+}
+
+/// Compute the roles a member should have at the reward index from [`get_reward_idx`].
+///
+/// Earned rewards the member is missing are added. Reward roles above their level are left alone,
+/// since moderators hand those out too, unless `remove_unearned` is set because the member's level
+/// just went down. Then every reward they don't qualify for is removed, including every reward
+/// when `reward_idx` is `None`.
+#[must_use]
+pub fn get_role_changes(
+    guild_config: &GuildConfig,
+    member_roles: &[Id<RoleMarker>],
+    rewards: &[RoleReward],
+    reward_idx: Option<usize>,
+    remove_unearned: bool,
+) -> RoleChangeList {
+    let one_at_a_time = guild_config.one_at_a_time.is_some_and(|v| v);
+
+    let achieved_roles = match reward_idx {
+        // Every reward at the same level is earned together, so one at a time means one level at a time
+        Some(idx) if one_at_a_time => {
+            let requirement = rewards[idx].requirement;
+            &rewards[rewards.partition_point(|v| v.requirement < requirement)..=idx]
+        }
+        Some(idx) => &rewards[..=idx],
+        None => &[],
+    };
+    let roles_to_add = achieved_roles.iter().filter_map(|v| {
+        if !member_roles.contains(&v.id) {
+            Some(v.id)
+        } else {
+            None
+        }
+    });
+
+    let current_requirement = reward_idx.map(|idx| rewards[idx].requirement);
+
+    let mut changed_roles = Vec::with_capacity(8);
+
+    let total_roles: RoleList = member_roles
+        .iter()
+        .copied()
+        .chain(roles_to_add)
+        // Roles that aren't rewards are always kept, and so are achieved rewards. Rewards above
+        // the member's level are only removed when asked to, and lower ones only in one at a time
+        // mode. Anything we remove, or that the member didn't have before because it was added
+        // in the chain, goes in the changelist.
+        .filter(|v| {
+            let keeper = match rewards.iter().find(|reward| reward.id == *v) {
+                None => true,
+                Some(_) if achieved_roles.iter().any(|reward| reward.id == *v) => true,
+                Some(reward) => {
+                    !remove_unearned
+                        && current_requirement.is_none_or(|current| reward.requirement > current)
+                }
+            };
+            if !keeper || !member_roles.contains(v) {
+                changed_roles.push(*v);
+            }
+            keeper
+        })
+        .collect();
+
+    RoleChangeList {
+        total_roles,
+        changed_roles,
+    }
+}
+
+/// The rank rewards held by whoever is at the 0-indexed leaderboard `position`
+pub fn rank_reward_roles(
+    rank_rewards: &[RankReward],
+    position: Option<usize>,
+) -> impl Iterator<Item = Id<RoleMarker>> + '_ {
+    let position = position.and_then(|v| i64::try_from(v).ok());
+    rank_rewards
+        .iter()
+        .filter(move |reward| position.is_some_and(|v| v < reward.max_rank))
+        .map(|reward| reward.id)
+}
+
+/// Compute the roles a member should have at the 0-indexed leaderboard `position`,
+/// adding rank rewards they now hold and removing ones someone else has taken over.
+#[must_use]
+pub fn get_rank_role_changes(
+    member_roles: &[Id<RoleMarker>],
+    rank_rewards: &[RankReward],
+    position: Option<usize>,
+) -> RoleChangeList {
+    let held: RoleList = rank_reward_roles(rank_rewards, position).collect();
+    let roles_to_add = held.iter().copied().filter(|v| !member_roles.contains(v));
+
+    let mut changed_roles = Vec::with_capacity(4);
+
+    let total_roles: RoleList = member_roles
+        .iter()
+        .copied()
+        .chain(roles_to_add)
+        .filter(|v| {
+            let keeper = held.contains(v) || !rank_rewards.iter().any(|reward| reward.id == *v);
+            if !keeper || !member_roles.contains(v) {
+                changed_roles.push(*v);
+            }
+            keeper
+        })
+        .collect();
+
+    RoleChangeList {
+        total_roles,
+        changed_roles,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use twilight_model::guild::{MemberFlags, PartialMember};
+
+    use super::*;
+
+    fn member_with_roles(roles: impl Into<RoleList>) -> PartialMember {
+        PartialMember {
+            avatar: None,
+            avatar_decoration_data: None,
+            banner: None,
+            communication_disabled_until: None,
+            deaf: false,
+            flags: MemberFlags::empty(),
+            joined_at: None,
+            mute: false,
+            nick: None,
+            permissions: None,
+            premium_since: None,
+            roles: roles.into(),
+            user: None,
+        }
+    }
+
+    // Non-one at a time only changes the behavior to not remove the previous role
+    fn conf_one_at_time() -> GuildConfig {
+        GuildConfig {
+            one_at_a_time: Some(true),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn no_changes() {
+        let rewards = [RoleReward {
+            id: Id::new(1),
+            requirement: 2,
+            duration_hours: None,
+        }];
+        let reward_idx = get_reward_idx(&rewards, 2);
+        let member = member_with_roles([Id::new(1)]);
+        let changes = get_role_changes(
+            &conf_one_at_time(),
+            &member.roles,
+            &rewards,
+            reward_idx,
+            false,
+        );
+        assert_eq!(changes.changed_roles, RoleList::new());
+        assert_eq!(changes.total_roles, [Id::new(1)]);
+    }
+
+    #[test]
+    fn minecraft_discord() {
+        let rewards = [RoleReward {
+            id: Id::new(1),
+            requirement: 5,
+            duration_hours: None,
+        }];
+        let reward_idx = get_reward_idx(&rewards, 5);
+        let member = member_with_roles([]);
+        let changes = get_role_changes(
+            &conf_one_at_time(),
+            &member.roles,
+            &rewards,
+            reward_idx,
+            false,
+        );
+        assert_eq!(changes.changed_roles, [Id::new(1)]);
+        assert_eq!(changes.total_roles, [Id::new(1)]);
+    }
+
+    #[test]
+    fn add_one_role() {
+        let rewards = [
+            RoleReward {
+                id: Id::new(1),
+                requirement: 2,
+                duration_hours: None,
+            },
+            RoleReward {
+                id: Id::new(2),
+                requirement: 10,
+                duration_hours: None,
+            },
+        ];
+        let reward_idx = get_reward_idx(&rewards, 4);
+        let member = member_with_roles([]);
+        let changes = get_role_changes(
+            &conf_one_at_time(),
+            &member.roles,
+            &rewards,
+            reward_idx,
+            false,
+        );
+        assert_eq!(changes.changed_roles, vec![Id::new(1)]);
+        assert_eq!(changes.total_roles, [Id::new(1)]);
+    }
+
+    const TEST_REWARDS: [RoleReward; 3] = [
+        RoleReward {
+            id: Id::new(1),
+            requirement: 2,
+            duration_hours: None,
+        },
+        RoleReward {
+            id: Id::new(2),
+            requirement: 4,
+            duration_hours: None,
+        },
+        RoleReward {
+            id: Id::new(3),
+            requirement: 10,
+            duration_hours: None,
+        },
+    ];
+
+    #[test]
+    fn skip_roles() {
+        let reward_idx = get_reward_idx(&TEST_REWARDS, 10);
+        let member = member_with_roles([]);
+        let changes = get_role_changes(
+            &conf_one_at_time(),
+            &member.roles,
+            &TEST_REWARDS,
+            reward_idx,
+            false,
+        );
+        assert_eq!(changes.changed_roles, [Id::new(3)]);
+        assert_eq!(changes.total_roles, [Id::new(3)]);
+    }
+    #[test]
+    fn stop_on_role() {
+        let reward_idx = get_reward_idx(&TEST_REWARDS, 5);
+        let member = member_with_roles([Id::new(1)]);
+        let changes = get_role_changes(
+            &conf_one_at_time(),
+            &member.roles,
+            &TEST_REWARDS,
+            reward_idx,
+            false,
+        );
+        assert_eq!(changes.changed_roles, [Id::new(1), Id::new(2)]);
+        assert_eq!(changes.total_roles, [Id::new(2)]);
+    }
+
+    #[test]
+    fn conf_many_doesnt_nuke() {
+        let reward_idx = get_reward_idx(&TEST_REWARDS, 5);
+        let member = member_with_roles([Id::new(1)]);
+        let changes = get_role_changes(
+            &GuildConfig::default(),
+            &member.roles,
+            &TEST_REWARDS,
+            reward_idx,
+            false,
+        );
+        assert_eq!(changes.changed_roles, [Id::new(2)]);
+        assert_eq!(changes.total_roles, [Id::new(1), Id::new(2)]);
+    }
+
+    #[test]
+    fn conf_many_adds_many() {
+        let reward_idx = get_reward_idx(&TEST_REWARDS, 11);
+        let member = member_with_roles([]);
+        let changes = get_role_changes(
+            &GuildConfig::default(),
+            &member.roles,
+            &TEST_REWARDS,
+            reward_idx,
+            false,
+        );
+        assert_eq!(changes.changed_roles, [Id::new(1), Id::new(2), Id::new(3)]);
+        assert_eq!(changes.total_roles, [Id::new(1), Id::new(2), Id::new(3)]);
+    }
+
+    const GROUPED_REWARDS: [RoleReward; 5] = [
+        RoleReward {
+            id: Id::new(1),
+            requirement: 2,
+            duration_hours: None,
+        },
+        RoleReward {
+            id: Id::new(2),
+            requirement: 2,
+            duration_hours: None,
+        },
+        RoleReward {
+            id: Id::new(3),
+            requirement: 4,
+            duration_hours: None,
+        },
+        RoleReward {
+            id: Id::new(4),
+            requirement: 4,
+            duration_hours: None,
+        },
+        RoleReward {
+            id: Id::new(5),
+            requirement: 10,
+            duration_hours: None,
+        },
+    ];
+
+    #[test]
+    fn grouped_one_at_a_time() {
+        let reward_idx = get_reward_idx(&GROUPED_REWARDS, 4);
+        assert_eq!(reward_idx, Some(3));
+        let member = member_with_roles([Id::new(1), Id::new(2)]);
+        let changes = get_role_changes(
+            &conf_one_at_time(),
+            &member.roles,
+            &GROUPED_REWARDS,
+            reward_idx,
+            false,
+        );
+        assert_eq!(
+            changes.changed_roles,
+            [Id::new(1), Id::new(2), Id::new(3), Id::new(4)]
+        );
+        assert_eq!(changes.total_roles, [Id::new(3), Id::new(4)]);
+    }
+
+    #[test]
+    fn grouped_adds_whole_level() {
+        let reward_idx = get_reward_idx(&GROUPED_REWARDS, 3);
+        let member = member_with_roles([Id::new(2)]);
+        let changes = get_role_changes(
+            &GuildConfig::default(),
+            &member.roles,
+            &GROUPED_REWARDS,
+            reward_idx,
+            false,
+        );
+        assert_eq!(changes.changed_roles, [Id::new(1)]);
+        assert_eq!(changes.total_roles, [Id::new(2), Id::new(1)]);
+    }
+
+    #[test]
+    fn leave_alone_higher_roles() {
+        let reward_idx = get_reward_idx(&TEST_REWARDS, 3);
+        let member = member_with_roles([Id::new(3)]);
+        let changes = get_role_changes(
+            &GuildConfig::default(),
+            &member.roles,
+            &TEST_REWARDS,
+            reward_idx,
+            false,
+        );
+        assert_eq!(changes.changed_roles, [Id::new(1)]);
+        assert_eq!(changes.total_roles, [Id::new(3), Id::new(1)]);
+    }
+
+    #[test]
+    fn remove_higher_roles_after_level_drop() {
+        let reward_idx = get_reward_idx(&TEST_REWARDS, 3);
+        let member = member_with_roles([Id::new(3)]);
+        let changes = get_role_changes(
+            &GuildConfig::default(),
+            &member.roles,
+            &TEST_REWARDS,
+            reward_idx,
+            true,
+        );
+        assert_eq!(changes.changed_roles, [Id::new(3), Id::new(1)]);
+        assert_eq!(changes.total_roles, [Id::new(1)]);
+    }
+
+    #[test]
+    fn demote_one_at_a_time() {
+        let reward_idx = get_reward_idx(&TEST_REWARDS, 5);
+        let member = member_with_roles([Id::new(7), Id::new(3)]);
+        let changes = get_role_changes(
+            &conf_one_at_time(),
+            &member.roles,
+            &TEST_REWARDS,
+            reward_idx,
+            true,
+        );
+        assert_eq!(changes.changed_roles, [Id::new(3), Id::new(2)]);
+        assert_eq!(changes.total_roles, [Id::new(7), Id::new(2)]);
+    }
+
+    #[test]
+    fn remove_all_below_first_reward() {
+        let reward_idx = get_reward_idx(&TEST_REWARDS, 1);
+        let member = member_with_roles([Id::new(1), Id::new(2), Id::new(7)]);
+        let changes = get_role_changes(
+            &GuildConfig::default(),
+            &member.roles,
+            &TEST_REWARDS,
+            reward_idx,
+            true,
+        );
+        assert_eq!(changes.changed_roles, [Id::new(1), Id::new(2)]);
+        assert_eq!(changes.total_roles, [Id::new(7)]);
+    }
+
+    const RANK_REWARDS: [RankReward; 2] = [
+        RankReward {
+            id: Id::new(10),
+            max_rank: 1,
+        },
+        RankReward {
+            id: Id::new(11),
+            max_rank: 10,
+        },
+    ];
+
+    #[test]
+    fn rank_top_spot() {
+        let member = member_with_roles([Id::new(7)]);
+        let changes = get_rank_role_changes(&member.roles, &RANK_REWARDS, Some(0));
+        assert_eq!(changes.changed_roles, [Id::new(10), Id::new(11)]);
+        assert_eq!(changes.total_roles, [Id::new(7), Id::new(10), Id::new(11)]);
+    }
+
+    #[test]
+    fn rank_overtaken() {
+        let member = member_with_roles([Id::new(10), Id::new(11), Id::new(7)]);
+        let changes = get_rank_role_changes(&member.roles, &RANK_REWARDS, Some(1));
+        assert_eq!(changes.changed_roles, [Id::new(10)]);
+        assert_eq!(changes.total_roles, [Id::new(11), Id::new(7)]);
+
+        let changes = get_rank_role_changes(&member.roles, &RANK_REWARDS, None);
+        assert_eq!(changes.changed_roles, [Id::new(10), Id::new(11)]);
+        assert_eq!(changes.total_roles, [Id::new(7)]);
+    }
+
+    #[test]
+    fn expired_rewards_removed() {
+        let reward_idx = get_reward_idx(&TEST_REWARDS, 5);
+        let member = member_with_roles([Id::new(1), Id::new(7)]);
+        let mut changes = get_role_changes(
+            &GuildConfig::default(),
+            &member.roles,
+            &TEST_REWARDS,
+            reward_idx,
+            false,
+        );
+        changes.remove_roles(&member.roles, &[Id::new(1), Id::new(2)]);
+        assert_eq!(changes.changed_roles, [Id::new(1)]);
+        assert_eq!(changes.total_roles, [Id::new(7)]);
+    }
+
+    #[test]
+    fn level_up_message_worst_case() {
+        let short = Interpolation::new("GG {user_mention}, you're level {level}!").unwrap();
+        assert_eq!(max_level_up_message_len(&short), 49);
+        // 128 XP counters fit in the 512 character template limit, and can't fit in a message
+        let long = Interpolation::new("{xp}".repeat(128)).unwrap();
+        assert_eq!(max_level_up_message_len(&long), 19 * 128);
+        assert!(max_level_up_message_len(&long) > MAX_MESSAGE_LENGTH);
+    }
+
+    fn level_up_message(id: i64, min_level: Option<i64>, max_level: Option<i64>) -> LevelUpMessage {
+        LevelUpMessage {
+            id,
+            min_level,
+            max_level,
+            template: Interpolation::new(id.to_string()).unwrap(),
+        }
+    }
+
+    #[test]
+    fn level_up_message_most_specific() {
+        let messages = [
+            level_up_message(1, None, None),
+            level_up_message(2, None, None),
+            level_up_message(3, Some(10), Some(19)),
+            level_up_message(4, Some(50), Some(50)),
+            level_up_message(5, Some(50), None),
+        ];
+        let pool_ids = |level| -> Vec<i64> {
+            level_up_message_pool(&messages, level)
+                .iter()
+                .map(|v| v.id)
+                .collect()
+        };
+        assert_eq!(pool_ids(5), [1, 2]);
+        assert_eq!(pool_ids(10), [3]);
+        assert_eq!(pool_ids(50), [4]);
+        assert_eq!(pool_ids(51), [5]);
+        assert!(level_up_message_pool(&messages[2..], 5).is_empty());
+    }
+}